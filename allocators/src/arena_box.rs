@@ -0,0 +1,168 @@
+use crate::linear_allocator::{LinearAllocator, LinearAllocatorInternal};
+
+use core::ptr::NonNull;
+
+/// An owned handle to a single object in a [LinearAllocator], created with
+/// [LinearAllocator::alloc_owned()]. Dropping the handle drops the object and
+/// gives its memory back if it's still the last allocation. Handles that are
+/// dropped out of LIFO order leak their memory until the allocator is rewound
+/// instead of rewinding over the later allocations.
+pub struct ArenaBox<'a, T> {
+    allocator: &'a LinearAllocator<'a>,
+    ptr: NonNull<T>,
+    // Start of the free block before the allocation, includes alignment padding
+    start: *mut u8,
+}
+
+impl<'a, T> ArenaBox<'a, T> {
+    pub(crate) fn new(allocator: &'a LinearAllocator<'a>, obj: T) -> Self {
+        // A root ScopedScratch would rewind over the object while the handle is alive
+        assert!(
+            !allocator.is_scoped(),
+            "Tried to allocate an ArenaBox on an allocator held by a ScopedScratch"
+        );
+
+        let start = allocator.peek();
+        let ptr = NonNull::from(allocator.alloc_internal(obj));
+        Self {
+            allocator,
+            ptr,
+            start,
+        }
+    }
+}
+
+impl<T> core::ops::Deref for ArenaBox<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // Safety:
+        // - ptr was initialized in new() and is only dropped with the handle
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T> core::ops::DerefMut for ArenaBox<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // Safety:
+        // - ptr was initialized in new() and is only dropped with the handle
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+impl<T> Drop for ArenaBox<'_, T> {
+    fn drop(&mut self) {
+        // Safety:
+        // - The object was initialized in new() and isn't used after the
+        //   handle is dropped
+        unsafe { self.ptr.as_ptr().drop_in_place() };
+
+        // Zero-sized objects don't take memory and a ScopedScratch created
+        // after the handle assumes the allocator doesn't move below its start
+        if core::mem::size_of::<T>() == 0 || self.allocator.is_scoped() {
+            return;
+        }
+        let end = self.ptr.as_ptr().wrapping_add(1) as *mut u8;
+        if end == self.allocator.peek() {
+            // # Safety
+            //  - start is from peek() right before the allocation, which was
+            //    the last one
+            //  - The object was just dropped
+            unsafe { self.allocator.rewind(self.start) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::cell::Cell;
+
+    struct A<'a> {
+        data: u32,
+        dropped: &'a Cell<usize>,
+    }
+    impl Drop for A<'_> {
+        fn drop(&mut self) {
+            self.dropped.set(self.dropped.get() + 1);
+        }
+    }
+
+    #[test]
+    fn lifo() {
+        let dropped = Cell::new(0);
+        let alloc = LinearAllocator::new(1024);
+        let _ = alloc.alloc_internal(0u8);
+        let start = alloc.peek();
+        {
+            let mut a = alloc.alloc_owned(A {
+                data: 1,
+                dropped: &dropped,
+            });
+            a.data += 1;
+            assert_eq!(a.data, 2);
+            let a_end = alloc.peek();
+            {
+                let b = alloc.alloc_owned(0xABu8);
+                let b_end = alloc.peek();
+                {
+                    let c = alloc.alloc_owned(0xCAFEBABEu64);
+                    assert_eq!(*c, 0xCAFEBABEu64);
+                }
+                // c gave back its memory, including the alignment padding
+                assert_eq!(alloc.peek(), b_end);
+                assert_eq!(*b, 0xABu8);
+            }
+            assert_eq!(alloc.peek(), a_end);
+            assert_eq!(dropped.get(), 0);
+        }
+        assert_eq!(dropped.get(), 1);
+        assert_eq!(alloc.peek(), start);
+    }
+
+    #[test]
+    fn lifo_violation() {
+        let dropped = Cell::new(0);
+        let alloc = LinearAllocator::new(1024);
+
+        let a = alloc.alloc_owned(A {
+            data: 1,
+            dropped: &dropped,
+        });
+        let b = alloc.alloc_owned(0xCAFEBABEu32);
+        let peek = alloc.peek();
+
+        // a isn't the last allocation so its memory is leaked instead of
+        // rewinding over b
+        drop(a);
+        assert_eq!(dropped.get(), 1);
+        assert_eq!(alloc.peek(), peek);
+        let c = alloc.alloc_internal(0xDEADCAFEu32);
+        assert_eq!(*b, 0xCAFEBABEu32);
+        assert_eq!(*c, 0xDEADCAFEu32);
+    }
+
+    #[test]
+    fn scope_after_box() {
+        let alloc = LinearAllocator::new(1024);
+
+        let a = alloc.alloc_owned(0xCAFEBABEu32);
+        let scratch = crate::ScopedScratch::new(&alloc);
+        let peek = alloc.peek();
+        // The scope owns the end of the allocator
+        drop(a);
+        assert_eq!(alloc.peek(), peek);
+        drop(scratch);
+    }
+
+    #[should_panic(
+        expected = "Tried to allocate an ArenaBox on an allocator held by a ScopedScratch"
+    )]
+    #[test]
+    fn alloc_in_scope() {
+        let alloc = LinearAllocator::new(1024);
+        let _scratch = crate::ScopedScratch::new(&alloc);
+        let _ = alloc.alloc_owned(0u32);
+    }
+}
@@ -0,0 +1,236 @@
+use crate::linear_allocator::{LinearAllocator, LinearAllocatorInternal};
+
+use core::ptr::NonNull;
+
+/// Builds a slice of unknown length at the end of a [LinearAllocator], created
+/// with [LinearAllocator::vec_builder()]. The storage grows in place as long as
+/// nothing else is allocated in between and is moved to the end of the block
+/// otherwise, leaking the previous storage until the allocator is rewound.
+/// Pushed objects are dropped if the builder is dropped before [finish()].
+pub struct ArenaVec<'a, T> {
+    allocator: &'a LinearAllocator<'a>,
+    ptr: NonNull<T>,
+    len: usize,
+    capacity: usize,
+}
+
+impl<'a, T> ArenaVec<'a, T> {
+    pub(crate) fn new(allocator: &'a LinearAllocator<'a>) -> Self {
+        Self {
+            allocator,
+            ptr: NonNull::dangling(),
+            len: 0,
+            // Zero-sized objects never need storage
+            capacity: if core::mem::size_of::<T>() == 0 {
+                usize::MAX
+            } else {
+                0
+            },
+        }
+    }
+
+    /// Returns the number of pushed objects
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if nothing has been pushed yet
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of objects that fit without growing the storage
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Appends `obj`, growing the storage if it's full. Panics if the grown
+    /// storage doesn't fit in the allocator.
+    pub fn push(&mut self, obj: T) {
+        if self.len == self.capacity {
+            self.grow();
+        }
+        // Safety:
+        // - len is within the capacity of the storage, which is aligned for T
+        unsafe { self.ptr.as_ptr().add(self.len).write(obj) };
+        self.len += 1;
+    }
+
+    /// Returns the pushed objects as a slice that lives as long as the allocator
+    /// borrow. Unused capacity is given back if it's still at the end of the
+    /// allocator.
+    pub fn finish(self) -> &'a mut [T] {
+        let this = core::mem::ManuallyDrop::new(self);
+        this.release_after(this.len);
+        // Safety:
+        // - The first len objects were initialized by push()
+        // - Ownership of the objects moves to the returned slice as self isn't dropped
+        unsafe { core::slice::from_raw_parts_mut(this.ptr.as_ptr(), this.len) }
+    }
+
+    fn grow(&mut self) {
+        // A root ScopedScratch would rewind over the storage
+        assert!(
+            !self.allocator.is_scoped(),
+            "Tried to grow an ArenaVec on an allocator held by a ScopedScratch"
+        );
+
+        let extra = self.capacity.max(4);
+        if self.capacity > 0 && self.end() == self.allocator.peek() {
+            // The end of the storage is aligned for T so the new slots follow it
+            // directly if there's room
+            if let Ok(slots) = self.allocator.try_alloc_slice_uninit::<T>(extra) {
+                debug_assert_eq!(slots.as_mut_ptr() as *mut u8, self.end());
+                self.capacity += extra;
+                return;
+            }
+        }
+
+        let capacity = self.capacity + extra;
+        let slots = match self.allocator.try_alloc_slice_uninit::<T>(capacity) {
+            Ok(slots) => slots,
+            Err(err) => self.allocator.alloc_failed(err),
+        };
+        let ptr = slots.as_mut_ptr() as *mut T;
+        // Safety:
+        // - The new storage was just allocated after the old one so they can't overlap
+        // - The first len objects of the old storage are initialized and are
+        //   moved to the new storage
+        unsafe { core::ptr::copy_nonoverlapping(self.ptr.as_ptr(), ptr, self.len) };
+        // Safety:
+        // - ptr is from a slice with a non-zero length, so it's not null
+        self.ptr = unsafe { NonNull::new_unchecked(ptr) };
+        self.capacity = capacity;
+    }
+
+    // Rewinds the allocator over the storage after the first keep slots if
+    // nothing was allocated after it
+    fn release_after(&self, keep: usize) {
+        if core::mem::size_of::<T>() == 0 || keep == self.capacity || self.allocator.is_scoped() {
+            return;
+        }
+        if self.end() == self.allocator.peek() {
+            // # Safety
+            //  - The target is within the storage, which was the last allocation
+            //  - The rewound slots were never initialized or their objects were
+            //    dropped or moved out
+            unsafe {
+                self.allocator
+                    .rewind(self.ptr.as_ptr().add(keep) as *mut u8);
+            }
+        }
+    }
+
+    // Returns the end of the storage
+    fn end(&self) -> *mut u8 {
+        self.ptr.as_ptr().wrapping_add(self.capacity) as *mut u8
+    }
+}
+
+impl<T> Drop for ArenaVec<'_, T> {
+    fn drop(&mut self) {
+        // Safety:
+        // - The first len objects were initialized by push() and aren't used
+        //   after the builder is dropped
+        unsafe {
+            core::ptr::slice_from_raw_parts_mut(self.ptr.as_ptr(), self.len).drop_in_place();
+        }
+        self.release_after(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn contiguous_growth() {
+        let alloc = LinearAllocator::new(1024);
+        let _ = alloc.alloc_internal(0u8);
+
+        let mut v = alloc.vec_builder::<u32>();
+        assert!(v.is_empty());
+        v.push(0);
+        let start = v.ptr;
+        assert_eq!(v.capacity(), 4);
+        for i in 1..10 {
+            v.push(i);
+        }
+        assert_eq!(v.len(), 10);
+        assert_eq!(v.capacity(), 16);
+        // Grew in place
+        assert_eq!(v.ptr, start);
+
+        let a = v.finish();
+        assert_eq!(a, &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        assert_eq!(a.as_ptr(), start.as_ptr());
+        // The unused capacity is given back
+        assert_eq!(alloc.used_bytes(), 4 + 10 * 4);
+    }
+
+    #[test]
+    fn interleaved_allocation() {
+        let alloc = LinearAllocator::new(1024);
+
+        let mut v = alloc.vec_builder::<u64>();
+        for i in 0..4 {
+            v.push(i);
+        }
+        let start = v.ptr;
+        let b = alloc.alloc_internal(0xCAFEBABEu32);
+
+        // The storage is full and can't grow in place anymore
+        v.push(4);
+        assert_ne!(v.ptr, start);
+        assert_eq!(v.capacity(), 8);
+        let a = v.finish();
+        assert_eq!(a, &[0, 1, 2, 3, 4]);
+        assert_eq!(*b, 0xCAFEBABEu32);
+        assert_eq!(alloc.used_bytes(), 32 + 8 + 5 * 8);
+    }
+
+    #[test]
+    fn drop_unfinished() {
+        let dropped = std::cell::Cell::new(0);
+        struct A<'a>(&'a std::cell::Cell<usize>);
+        impl Drop for A<'_> {
+            fn drop(&mut self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        let alloc = LinearAllocator::new(1024);
+        {
+            let mut v = alloc.vec_builder();
+            for _ in 0..5 {
+                v.push(A(&dropped));
+            }
+        }
+        assert_eq!(dropped.get(), 5);
+        // The storage is given back as it's still at the end of the allocator
+        assert_eq!(alloc.used_bytes(), 0);
+    }
+
+    #[test]
+    fn zero_sized() {
+        let alloc = LinearAllocator::new(1024);
+
+        let mut v = alloc.vec_builder::<()>();
+        for _ in 0..100 {
+            v.push(());
+        }
+        assert_eq!(v.finish().len(), 100);
+        assert_eq!(alloc.used_bytes(), 0);
+    }
+
+    #[should_panic(expected = "Tried to grow an ArenaVec on an allocator held by a ScopedScratch")]
+    #[test]
+    fn grow_in_scope() {
+        let alloc = LinearAllocator::new(1024);
+
+        let mut v = alloc.vec_builder::<u32>();
+        let _scratch = crate::ScopedScratch::new(&alloc);
+        v.push(0);
+    }
+}
@@ -0,0 +1,241 @@
+use crate::{
+    error::AllocationError,
+    linear_allocator::{check_size, L1_CACHE_LINE_SIZE},
+};
+
+use core::{
+    alloc::Layout,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+/// A linear allocator that can be shared between threads. Concurrent
+/// allocations each get a disjoint region of the block. Rewinding requires
+/// exclusive access since it's not safe under contention.
+pub struct AtomicLinearAllocator {
+    block_start: *mut u8,
+    layout: Layout,
+    size_bytes: usize,
+    next_alloc: AtomicPtr<u8>,
+}
+
+// Safety:
+// - The block is owned by the allocator
+// - next_alloc is only advanced atomically through shared references, and
+//   rewound only through exclusive ones
+unsafe impl Send for AtomicLinearAllocator {}
+unsafe impl Sync for AtomicLinearAllocator {}
+
+impl AtomicLinearAllocator {
+    pub fn new(size_bytes: usize) -> Self {
+        assert_ne!(size_bytes, 0, "Cannot create an allocator with size 0");
+        // Limit so that we can assume allocation arithmetic can never overflow
+        assert!(size_bytes < isize::MAX as usize);
+
+        let layout = Layout::from_size_align(size_bytes, L1_CACHE_LINE_SIZE)
+            .expect("Failed to create memory layout");
+
+        // Safety:
+        // - layout has a non-zero size since size_bytes is not 0 and its construction succeeded
+        let block_start = unsafe { alloc::alloc::alloc(layout) };
+
+        if block_start.is_null() {
+            alloc::alloc::handle_alloc_error(layout);
+        }
+
+        Self {
+            block_start,
+            layout,
+            size_bytes,
+            next_alloc: AtomicPtr::new(block_start),
+        }
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    /// Allocates and initializes `obj`, panicking if it doesn't fit.
+    /// Destructors of allocated objects are never run.
+    pub fn alloc<T: Sized>(&self, obj: T) -> &mut T {
+        match self.try_alloc(obj) {
+            Ok(ret) => ret,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    /// Allocates and initializes `obj`, or returns an error if it doesn't fit.
+    /// On failure, `obj` is dropped.
+    pub fn try_alloc<T: Sized>(&self, obj: T) -> Result<&mut T, AllocationError> {
+        let size_bytes = core::mem::size_of::<T>();
+        let alignment = core::mem::align_of::<T>();
+        // Make sure new_size never overflows
+        check_size(size_bytes, alignment)?;
+
+        let mut next_alloc = self.next_alloc.load(Ordering::Relaxed);
+        let new_alloc = loop {
+            let align_offset = next_alloc.align_offset(alignment);
+            assert_ne!(align_offset, usize::MAX);
+
+            // Safety:
+            // - next_alloc is derived from self.block_start and within the
+            //   allocation (or one byte past it), see LinearAllocator::try_bump()
+            let previous_size = unsafe { next_alloc.offset_from(self.block_start) as usize };
+
+            // The asserts above make sure this can't overflow since
+            // previous_size <= self.size_bytes < isize::MAX
+            let new_size = previous_size + align_offset + size_bytes;
+            if new_size > self.size_bytes {
+                let remaining_bytes = self.size_bytes - previous_size;
+                return Err(AllocationError::OutOfMemory {
+                    size_bytes,
+                    alignment,
+                    padding_bytes: align_offset,
+                    remaining_bytes,
+                });
+            }
+
+            // Safety:
+            // - We just verified that the aligned object fits the allocation
+            let (new_alloc, new_next_alloc) = unsafe {
+                let new_alloc = next_alloc.add(align_offset);
+                (new_alloc, new_alloc.add(size_bytes))
+            };
+
+            // The RMW order on next_alloc alone is enough to make the regions
+            // disjoint, the objects are written after the exchange
+            match self.next_alloc.compare_exchange_weak(
+                next_alloc,
+                new_next_alloc,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break new_alloc,
+                Err(current) => next_alloc = current,
+            }
+        };
+
+        // Safety:
+        // - new_alloc is a pointer to at least size_of::<T>() bytes of the block
+        //   that no other allocation can overlap
+        // - We aligned new_alloc for T
+        unsafe {
+            let t_ptr = new_alloc as *mut T;
+            t_ptr.write(obj);
+            Ok(&mut *t_ptr)
+        }
+    }
+
+    /// Returns the pointer to the start of the free block
+    pub fn peek(&self) -> *mut u8 {
+        self.next_alloc.load(Ordering::Relaxed)
+    }
+
+    /// Rewinds the allocator back to `alloc`, which has to be within the block.
+    /// Destructors are not run. The exclusive borrow guarantees that no
+    /// references to the rewound allocations are alive.
+    pub fn rewind(&mut self, alloc: *mut u8) {
+        assert!(
+            (alloc as usize) >= (self.block_start as usize)
+                && (alloc as usize) <= (self.block_start as usize) + self.size_bytes,
+            "alloc doesn't belong to this allocator"
+        );
+        *self.next_alloc.get_mut() = alloc;
+    }
+
+    /// Rewinds the allocator back to the start of its memory.
+    /// Destructors are not run.
+    pub fn reset(&mut self) {
+        *self.next_alloc.get_mut() = self.block_start;
+    }
+}
+
+impl Drop for AtomicLinearAllocator {
+    fn drop(&mut self) {
+        // Safety:
+        //  - self.block_start was allocated using the same allocator in new()
+        //  - self.layout is the layout it was allocated with
+        unsafe {
+            alloc::alloc::dealloc(self.block_start, self.layout);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn alloc() {
+        let alloc = AtomicLinearAllocator::new(1024);
+
+        let a = alloc.alloc(0xABu8);
+        let b = alloc.alloc(0xCAFEBABEu32);
+        assert_eq!(*a, 0xABu8);
+        assert_eq!(*b, 0xCAFEBABEu32);
+        assert_eq!(a as *const u8, alloc.block_start);
+        assert_eq!((b as *const u32 as usize) % 4, 0);
+    }
+
+    #[should_panic(
+        expected = "Tried to allocate 1000 bytes aligned at 4 (wasted 0 padding bytes) with only 768 remaining."
+    )]
+    #[test]
+    fn overflow() {
+        let alloc = AtomicLinearAllocator::new(1024);
+        let _ = alloc.alloc([0u8; 256]);
+        let _ = alloc.alloc([0u32; 250]);
+    }
+
+    #[test]
+    fn rewind() {
+        let mut alloc = AtomicLinearAllocator::new(1024);
+
+        let _ = alloc.alloc(0u8);
+        let target = alloc.peek();
+        let _ = alloc.alloc(0u64);
+        assert_ne!(alloc.peek(), target);
+        alloc.rewind(target);
+        assert_eq!(alloc.peek(), target);
+        alloc.reset();
+        assert_eq!(alloc.peek(), alloc.block_start);
+    }
+
+    #[test]
+    fn concurrent_allocs() {
+        const THREADS: usize = 8;
+        const ALLOCS: usize = 1000;
+        let alloc = AtomicLinearAllocator::new(THREADS * ALLOCS * 8);
+
+        let mut ptrs: Vec<(usize, u64)> = std::thread::scope(|s| {
+            let handles: Vec<_> = (0..THREADS)
+                .map(|t| {
+                    let alloc = &alloc;
+                    s.spawn(move || {
+                        (0..ALLOCS)
+                            .map(|i| {
+                                let v = (t * ALLOCS + i) as u64;
+                                let a = alloc.alloc(v);
+                                (a as *mut u64 as usize, v)
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|h| h.join().unwrap())
+                .collect()
+        });
+        assert_eq!(
+            alloc.peek() as usize,
+            alloc.block_start as usize + alloc.size_bytes
+        );
+
+        ptrs.sort();
+        for w in ptrs.windows(2) {
+            assert!(w[1].0 - w[0].0 >= 8, "Allocations overlap");
+        }
+        for (ptr, v) in ptrs {
+            assert_eq!(unsafe { *(ptr as *const u64) }, v);
+        }
+    }
+}
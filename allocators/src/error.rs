@@ -0,0 +1,144 @@
+use core::fmt;
+
+/// Errors of the fallible allocation functions. The panicking variants format
+/// the same error into their message, so the wording is defined only here.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AllocationError {
+    /// The allocation didn't fit in the remaining memory
+    OutOfMemory {
+        size_bytes: usize,
+        alignment: usize,
+        /// Bytes that would have been skipped to align the allocation
+        padding_bytes: usize,
+        remaining_bytes: usize,
+    },
+    /// The allocation is too large for any allocator, regardless of how much
+    /// memory is left
+    TooLarge { size_bytes: usize, alignment: usize },
+    /// The requested size or alignment of an allocator is invalid
+    InvalidLayout { size_bytes: usize, alignment: usize },
+    /// The memory block of an allocator couldn't be allocated
+    AllocationFailed { size_bytes: usize, alignment: usize },
+    /// The memory block of an allocator couldn't be locked in RAM
+    #[cfg(all(feature = "lock_pages", unix))]
+    PageLockFailed { size_bytes: usize, errno: i32 },
+}
+
+impl fmt::Display for AllocationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            AllocationError::OutOfMemory {
+                size_bytes,
+                alignment,
+                padding_bytes,
+                remaining_bytes,
+            } => write!(
+                f,
+                "Tried to allocate {} bytes aligned at {} (wasted {} padding bytes) with only {} remaining.",
+                size_bytes, alignment, padding_bytes, remaining_bytes
+            ),
+            AllocationError::TooLarge {
+                size_bytes,
+                alignment,
+            } => write!(
+                f,
+                "Tried to allocate {} bytes aligned at {}, which is too large for any allocator",
+                size_bytes, alignment
+            ),
+            AllocationError::InvalidLayout {
+                size_bytes,
+                alignment,
+            } => write!(
+                f,
+                "Cannot create an allocator with size {} aligned at {}",
+                size_bytes, alignment
+            ),
+            AllocationError::AllocationFailed {
+                size_bytes,
+                alignment,
+            } => write!(
+                f,
+                "Failed to allocate a block of {} bytes aligned at {}",
+                size_bytes, alignment
+            ),
+            #[cfg(all(feature = "lock_pages", unix))]
+            AllocationError::PageLockFailed { size_bytes, errno } => write!(
+                f,
+                "Failed to lock a block of {} bytes in memory (errno {})",
+                size_bytes, errno
+            ),
+        }
+    }
+}
+
+impl core::error::Error for AllocationError {}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::linear_allocator::{LinearAllocator, LinearAllocatorInternal};
+
+    #[test]
+    fn display() {
+        let alloc = LinearAllocator::new(1024);
+        let err = alloc.try_alloc_internal([0u8; 1025]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Tried to allocate 1025 bytes aligned at 1 (wasted 0 padding bytes) with only 1024 remaining."
+        );
+    }
+
+    #[test]
+    fn display_variants() {
+        let err = AllocationError::OutOfMemory {
+            size_bytes: 16,
+            alignment: 8,
+            padding_bytes: 4,
+            remaining_bytes: 12,
+        };
+        assert_eq!(
+            err.to_string(),
+            "Tried to allocate 16 bytes aligned at 8 (wasted 4 padding bytes) with only 12 remaining."
+        );
+        let err = AllocationError::TooLarge {
+            size_bytes: usize::MAX,
+            alignment: 1,
+        };
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Tried to allocate {} bytes aligned at 1, which is too large for any allocator",
+                usize::MAX
+            )
+        );
+        let err = AllocationError::InvalidLayout {
+            size_bytes: 0,
+            alignment: 64,
+        };
+        assert_eq!(
+            err.to_string(),
+            "Cannot create an allocator with size 0 aligned at 64"
+        );
+        let err = AllocationError::AllocationFailed {
+            size_bytes: 1024,
+            alignment: 64,
+        };
+        assert_eq!(
+            err.to_string(),
+            "Failed to allocate a block of 1024 bytes aligned at 64"
+        );
+    }
+
+    #[test]
+    fn boxed_error() {
+        fn alloc_boxed(alloc: &LinearAllocator) -> Result<(), Box<dyn std::error::Error>> {
+            let _ = alloc.try_alloc_internal([0u8; 1025])?;
+            Ok(())
+        }
+
+        let alloc = LinearAllocator::new(1024);
+        let err = alloc_boxed(&alloc).unwrap_err();
+        assert!(err.is::<AllocationError>());
+    }
+}
@@ -0,0 +1,195 @@
+use crate::{
+    error::AllocationError,
+    linear_allocator::{check_size, L1_CACHE_LINE_SIZE},
+};
+
+use core::{alloc::Layout, cell::Cell, ptr::NonNull};
+
+/// A linear allocator for uniform workloads, e.g. a pool of identical nodes,
+/// where every allocation is aligned at `ALIGN`. Sizes are rounded up to
+/// `ALIGN` so the start of the free block is always aligned and no alignment
+/// padding has to be computed per allocation. Types aligned at more than
+/// `ALIGN` are rejected at compile time.
+pub struct FastLinearAllocator<const ALIGN: usize> {
+    block_start: *mut u8,
+    layout: Layout,
+    size_bytes: usize,
+    // Offset of the start of the free block, always a multiple of ALIGN
+    next_alloc: Cell<usize>,
+}
+
+impl<const ALIGN: usize> FastLinearAllocator<ALIGN> {
+    /// Creates an allocator with a block of `size_bytes` aligned to the larger
+    /// of `ALIGN` and the L1 cache line size
+    pub fn new(size_bytes: usize) -> Self {
+        const { assert!(ALIGN.is_power_of_two(), "ALIGN has to be a power of two") };
+        assert_ne!(size_bytes, 0, "Cannot create an allocator with size 0");
+        // Limit so that we can assume allocation arithmetic can never overflow
+        assert!(size_bytes < isize::MAX as usize);
+
+        let layout = Layout::from_size_align(size_bytes, ALIGN.max(L1_CACHE_LINE_SIZE))
+            .expect("Failed to create memory layout");
+
+        // Safety:
+        // - layout has a non-zero size since size_bytes is not 0 and its construction succeeded
+        let block_start = unsafe { alloc::alloc::alloc(layout) };
+
+        if block_start.is_null() {
+            alloc::alloc::handle_alloc_error(layout);
+        }
+
+        Self {
+            block_start,
+            layout,
+            size_bytes,
+            next_alloc: Cell::new(0),
+        }
+    }
+
+    /// Returns the total size of the allocator's memory in bytes
+    pub fn capacity(&self) -> usize {
+        self.size_bytes
+    }
+
+    /// Returns the number of bytes allocated, including the rounding of sizes
+    pub fn used_bytes(&self) -> usize {
+        self.next_alloc.get()
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    /// Allocates and initializes `obj`, panicking if it doesn't fit
+    pub fn alloc<T: Sized>(&self, obj: T) -> &mut T {
+        match self.try_alloc(obj) {
+            Ok(ret) => ret,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    /// Allocates and initializes `obj`, or returns an error if it doesn't fit.
+    /// On failure, `obj` is dropped and the allocator is left untouched.
+    pub fn try_alloc<T: Sized>(&self, obj: T) -> Result<&mut T, AllocationError> {
+        const {
+            assert!(
+                core::mem::align_of::<T>() <= ALIGN,
+                "T is aligned at more than ALIGN"
+            )
+        };
+
+        let size_bytes = core::mem::size_of::<T>();
+        // Zero-sized types don't need memory, see LinearAllocator
+        if size_bytes == 0 {
+            let t_ptr = NonNull::<T>::dangling().as_ptr();
+            // Safety:
+            // - A dangling pointer is well-aligned and non-null, which is
+            //   enough for reads and writes of zero-sized types
+            unsafe {
+                t_ptr.write(obj);
+                return Ok(&mut *t_ptr);
+            }
+        }
+        check_size(size_bytes, ALIGN)?;
+
+        let offset = self.next_alloc.get();
+        let remaining_bytes = self.size_bytes - offset;
+        let rounded_size = size_bytes.next_multiple_of(ALIGN);
+        if rounded_size > remaining_bytes {
+            return Err(AllocationError::OutOfMemory {
+                size_bytes,
+                alignment: ALIGN,
+                padding_bytes: 0,
+                remaining_bytes,
+            });
+        }
+        self.next_alloc.set(offset + rounded_size);
+
+        // Safety:
+        // - offset is a multiple of ALIGN from the aligned block start, so the
+        //   pointer is aligned for T
+        // - We just verified that the object fits the block and it's not
+        //   referenced by earlier allocations
+        unsafe {
+            let t_ptr = self.block_start.add(offset) as *mut T;
+            t_ptr.write(obj);
+            Ok(&mut *t_ptr)
+        }
+    }
+
+    /// Rewinds the allocator back to the start of the block.
+    ///
+    /// **This does NOT run destructors.**
+    pub fn reset(&mut self) {
+        self.next_alloc.set(0);
+    }
+}
+
+impl<const ALIGN: usize> Drop for FastLinearAllocator<ALIGN> {
+    fn drop(&mut self) {
+        // Safety:
+        //  - self.block_start was allocated using the same allocator in new()
+        //  - self.layout is the layout it was allocated with
+        unsafe {
+            alloc::alloc::dealloc(self.block_start, self.layout);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn rounded_sizes() {
+        let alloc = FastLinearAllocator::<8>::new(64);
+
+        let a = alloc.alloc(0xABu8);
+        let b = alloc.alloc(0xCAFEBABEu32);
+        let c = alloc.alloc([0xCDu8; 9]);
+        assert_eq!((b as *mut u32 as usize) - (a as *mut u8 as usize), 8);
+        assert_eq!((c as *mut [u8; 9] as usize) % 8, 0);
+        assert_eq!(alloc.used_bytes(), 8 + 8 + 16);
+        assert_eq!(*a, 0xAB);
+        assert_eq!(*b, 0xCAFEBABEu32);
+        assert_eq!(c[8], 0xCD);
+
+        // Zero-sized types don't take up room
+        let _ = alloc.alloc(());
+        assert_eq!(alloc.used_bytes(), 32);
+    }
+
+    #[test]
+    fn exhaust() {
+        let mut alloc = FastLinearAllocator::<16>::new(64);
+
+        for i in 0..4u64 {
+            assert_eq!(*alloc.alloc(i), i);
+        }
+        match alloc.try_alloc(0u8) {
+            Err(AllocationError::OutOfMemory {
+                size_bytes,
+                alignment,
+                remaining_bytes,
+                ..
+            }) => {
+                assert_eq!(size_bytes, 1);
+                assert_eq!(alignment, 16);
+                assert_eq!(remaining_bytes, 0);
+            }
+            _ => panic!("Expected OutOfMemory"),
+        }
+
+        alloc.reset();
+        assert_eq!(alloc.used_bytes(), 0);
+        let _ = alloc.alloc([0u64; 8]);
+    }
+
+    #[test]
+    fn unaligned_capacity() {
+        // The last allocation doesn't fit after rounding
+        let alloc = FastLinearAllocator::<8>::new(12);
+        let _ = alloc.alloc(0u32);
+        assert!(alloc.try_alloc(0u32).is_err());
+        assert_eq!(alloc.used_bytes(), 8);
+    }
+}
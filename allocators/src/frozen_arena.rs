@@ -0,0 +1,129 @@
+use crate::linear_allocator::LinearAllocator;
+
+/// Read-only view of the memory of a [LinearAllocator] that can be shared
+/// between threads. Created with [LinearAllocator::freeze()].
+pub struct FrozenArena<'buf> {
+    allocator: LinearAllocator<'buf>,
+}
+
+// Safety:
+// - FrozenArena owns the allocator and its memory, which is released through
+//   the global allocator on drop, from whichever thread
+// - The allocator can't be bumped, rewound or otherwise mutated through a
+//   FrozenArena, so the offsets in its Cells are only ever read
+// - freeze() releases the OOM handler, backtraces and spilled allocations, and
+//   a frozen allocator can't get new ones, so it holds no other state that
+//   could be accessed from multiple threads
+// - Guards and root scopes borrow the allocator, so none can be alive
+unsafe impl Send for FrozenArena<'_> {}
+unsafe impl Sync for FrozenArena<'_> {}
+
+impl<'buf> FrozenArena<'buf> {
+    pub(crate) fn new(allocator: LinearAllocator<'buf>) -> Self {
+        Self { allocator }
+    }
+
+    /// Returns the number of bytes that were allocated before freezing
+    pub fn written_bytes(&self) -> usize {
+        self.allocator.peek_offset()
+    }
+
+    /// Returns a reference to the `T` at `offset` from the start of the arena.
+    /// # Safety
+    ///  - A `T` has to have been allocated at `offset`
+    pub unsafe fn get<T: Copy + Sync>(&self, offset: usize) -> &T {
+        assert!(
+            offset + core::mem::size_of::<T>() <= self.written_bytes(),
+            "offset is outside the written bytes"
+        );
+        // Safety:
+        // - We just checked that offset is within the written part of the block
+        let ptr = unsafe { self.allocator.as_ptr().add(offset) } as *const T;
+        assert!(ptr.is_aligned(), "offset is not aligned for T");
+        // Safety:
+        // - Caller guarantees that a T was allocated at ptr
+        // - ptr is aligned for T
+        // - The memory is never mutated again
+        unsafe { &*ptr }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::linear_allocator::LinearAllocatorInternal;
+
+    #[test]
+    fn read_from_thread() {
+        let alloc = LinearAllocator::new(1024);
+        let _ = alloc.alloc_internal(0xABu8);
+        let a_offset = alloc.peek_offset() + alloc.peek().align_offset(4);
+        let _ = alloc.alloc_internal(0xCAFEBABEu32);
+        let b_offset = alloc.peek_offset();
+        let _ = alloc.alloc_internal([0xDEADC0DEu32; 4]);
+
+        let arena = std::sync::Arc::new(alloc.freeze());
+        assert_eq!(arena.written_bytes(), 4 + 4 + 16);
+
+        let reader = {
+            let arena = arena.clone();
+            std::thread::spawn(move || unsafe {
+                (
+                    *arena.get::<u32>(a_offset),
+                    *arena.get::<[u32; 4]>(b_offset),
+                )
+            })
+        };
+        let (a, b) = reader.join().unwrap();
+        assert_eq!(a, 0xCAFEBABEu32);
+        assert_eq!(b, [0xDEADC0DEu32; 4]);
+        assert_eq!(unsafe { *arena.get::<u8>(0) }, 0xABu8);
+    }
+
+    #[test]
+    fn freeze_releases_state() {
+        let handler_data = std::sync::Arc::new(0u32);
+        let alloc = LinearAllocator::with_spill_to_heap(64);
+        let captured = handler_data.clone();
+        alloc.set_oom_handler(move |_, _, _| {
+            let _ = *captured;
+        });
+        let _ = alloc.alloc_internal([0xABu8; 64]);
+        let _ = alloc.alloc_internal(0u32);
+        assert_eq!(alloc.spilled_count(), 1);
+        assert_eq!(std::sync::Arc::strong_count(&handler_data), 2);
+
+        let arena = alloc.freeze();
+        assert_eq!(std::sync::Arc::strong_count(&handler_data), 1);
+        assert_eq!(arena.allocator.spilled_count(), 0);
+        assert_eq!(unsafe { *arena.get::<u8>(63) }, 0xAB);
+    }
+
+    #[should_panic(expected = "offset is outside the written bytes")]
+    #[test]
+    fn get_unwritten() {
+        let alloc = LinearAllocator::new(1024);
+        let _ = alloc.alloc_internal(0u32);
+        let arena = alloc.freeze();
+        let _ = unsafe { arena.get::<u64>(0) };
+    }
+
+    #[should_panic(expected = "Tried to freeze an allocator with allocations from the back")]
+    #[test]
+    fn freeze_back_allocations() {
+        let alloc = LinearAllocator::new(1024);
+        let _ = alloc.alloc_internal(0u32);
+        let _ = alloc.alloc_internal_back(0u32);
+        let _ = alloc.freeze();
+    }
+
+    #[should_panic(expected = "offset is not aligned for T")]
+    #[test]
+    fn get_unaligned() {
+        let alloc = LinearAllocator::new(1024);
+        let _ = alloc.alloc_internal([0u32; 2]);
+        let arena = alloc.freeze();
+        let _ = unsafe { arena.get::<u32>(1) };
+    }
+}
@@ -0,0 +1,317 @@
+use crate::{
+    error::AllocationError,
+    linear_allocator::{check_size, LinearAllocator, LinearAllocatorInternal, L1_CACHE_LINE_SIZE},
+};
+
+use alloc::{vec, vec::Vec};
+use core::{
+    alloc::Layout,
+    cell::{Cell, RefCell},
+    mem::MaybeUninit,
+};
+
+/// A linear allocator that spills over to a new, larger block when the current
+/// one is full instead of running out of memory. Existing allocations stay where
+/// they are, but consecutive allocations are no longer guaranteed to be
+/// contiguous in memory.
+pub struct GrowableLinearAllocator {
+    // Blocks are only dropped with the allocator and kept for reuse on rewind.
+    // The memory they hold doesn't move even if the Vec reallocates.
+    blocks: RefCell<Vec<LinearAllocator<'static>>>,
+    // Index of the block that is currently bumped
+    current: Cell<usize>,
+    // Set while a root ScopedScratch is alive, as it assumes to be the only user
+    scoped: Cell<bool>,
+}
+
+impl GrowableLinearAllocator {
+    /// Creates an allocator with an initial block of `size_bytes`
+    pub fn new(size_bytes: usize) -> Self {
+        Self {
+            blocks: RefCell::new(vec![LinearAllocator::new(size_bytes)]),
+            current: Cell::new(0),
+            scoped: Cell::new(false),
+        }
+    }
+
+    /// Returns the number of blocks the allocator has allocated so far
+    pub fn block_count(&self) -> usize {
+        self.blocks.borrow().len()
+    }
+
+    /// Returns the total size of all the blocks in bytes
+    pub fn capacity(&self) -> usize {
+        self.blocks.borrow().iter().map(|b| b.capacity()).sum()
+    }
+
+    /// Rewinds the allocator back to the start of its first block. The blocks
+    /// are kept for reuse.
+    ///
+    /// **This does NOT run destructors.**
+    pub fn reset(&mut self) {
+        for block in self.blocks.get_mut().iter_mut() {
+            block.reset();
+        }
+        self.current.set(0);
+    }
+
+    // Moves to a block that has room for size_bytes aligned at alignment,
+    // allocating a new one if none of the following blocks do
+    fn advance(&self, size_bytes: usize, alignment: usize) -> Result<(), AllocationError> {
+        check_size(size_bytes, alignment)?;
+        let mut blocks = self.blocks.borrow_mut();
+
+        let current = self.current.get();
+        if blocks[current].remaining_aligned(alignment) >= size_bytes {
+            return Ok(());
+        }
+
+        // The blocks after the current one are unused after a rewind
+        for i in (current + 1)..blocks.len() {
+            if blocks[i].remaining_aligned(alignment) >= size_bytes {
+                self.current.set(i);
+                return Ok(());
+            }
+        }
+
+        // Double the size, or more if needed to fit the allocation after aligning
+        let last_size = blocks.last().map(|b| b.capacity()).unwrap_or(0);
+        let block_size = last_size
+            .saturating_mul(2)
+            .max(size_bytes.saturating_add(alignment));
+        if block_size >= (isize::MAX / 2) as usize {
+            return Err(AllocationError::AllocationFailed {
+                size_bytes: block_size,
+                alignment,
+            });
+        }
+        blocks.push(LinearAllocator::try_with_alignment(
+            block_size,
+            alignment.max(L1_CACHE_LINE_SIZE),
+        )?);
+        self.current.set(blocks.len() - 1);
+
+        Ok(())
+    }
+
+    // Returns the index of the block that holds ptr, the end of a block counts
+    // as it's returned by peek() when the block is full
+    fn block_of(&self, ptr: *mut u8) -> Option<usize> {
+        let blocks = self.blocks.borrow();
+        (0..=self.current.get()).rev().find(|&i| {
+            let block = &blocks[i];
+            block.contains(ptr) || (ptr as usize) == (block.as_ptr() as usize) + block.capacity()
+        })
+    }
+}
+
+impl LinearAllocatorInternal for GrowableLinearAllocator {
+    #[allow(clippy::mut_from_ref)]
+    fn try_alloc_aligned<T: Sized>(&self, obj: T, align: usize) -> Result<&mut T, AllocationError> {
+        assert!(align.is_power_of_two(), "align has to be a power of two");
+        self.advance(
+            core::mem::size_of::<T>(),
+            core::mem::align_of::<T>().max(align),
+        )?;
+
+        let blocks = self.blocks.borrow();
+        let ret = blocks[self.current.get()].try_alloc_aligned(obj, align)? as *mut T;
+        // Safety:
+        // - ret points to the memory of a block, which doesn't move or get
+        //   deallocated before self is dropped
+        Ok(unsafe { &mut *ret })
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    fn try_alloc_slice_uninit<T: Sized>(
+        &self,
+        len: usize,
+    ) -> Result<&mut [MaybeUninit<T>], AllocationError> {
+        if len == 0 {
+            return Ok(&mut []);
+        }
+
+        let size_bytes = core::mem::size_of::<T>().saturating_mul(len);
+        self.advance(size_bytes, core::mem::align_of::<T>())?;
+
+        let blocks = self.blocks.borrow();
+        let ret =
+            blocks[self.current.get()].try_alloc_slice_uninit::<T>(len)? as *mut [MaybeUninit<T>];
+        // Safety:
+        // - ret points to the memory of a block, which doesn't move or get
+        //   deallocated before self is dropped
+        Ok(unsafe { &mut *ret })
+    }
+
+    fn try_alloc_layout(&self, layout: Layout) -> Result<*mut u8, AllocationError> {
+        if layout.size() == 0 {
+            return Ok(core::ptr::without_provenance_mut(layout.align()));
+        }
+        self.advance(layout.size(), layout.align())?;
+        self.blocks.borrow()[self.current.get()].try_alloc_layout(layout)
+    }
+
+    unsafe fn rewind(&self, alloc: *mut u8) {
+        let index = self
+            .block_of(alloc)
+            .expect("alloc doesn't belong to this allocator");
+
+        let blocks = self.blocks.borrow();
+        // Safety:
+        // - The caller is responsible for the contract of rewind()
+        // - The blocks after index were allocated from after alloc, so they are
+        //   rewound completely
+        unsafe {
+            for block in &blocks[(index + 1)..=self.current.get()] {
+                block.rewind(block.as_ptr() as *mut u8);
+            }
+            blocks[index].rewind(alloc);
+        }
+        self.current.set(index);
+    }
+
+    fn peek(&self) -> *mut u8 {
+        self.blocks.borrow()[self.current.get()].peek()
+    }
+
+    fn bytes_since(&self, start: *mut u8) -> usize {
+        let index = self
+            .block_of(start)
+            .expect("start doesn't belong to this allocator");
+
+        // Blocks are left as they were when the allocator spilled over from them
+        let blocks = self.blocks.borrow();
+        let first = (blocks[index].peek() as usize) - (start as usize);
+        blocks[(index + 1)..=self.current.get()]
+            .iter()
+            .fold(first, |sum, block| sum + block.peek_offset())
+    }
+
+    fn acquire_scope(&self) {
+        assert!(
+            !self.scoped.replace(true),
+            "Allocator is already used by another root ScopedScratch"
+        );
+    }
+
+    fn release_scope(&self) {
+        self.scoped.set(false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn bytes_since() {
+        let alloc = GrowableLinearAllocator::new(64);
+
+        let _ = alloc.alloc_internal(0u8);
+        let start = alloc.peek();
+        let _ = alloc.alloc_internal([0u8; 60]);
+        // Spills over to a new block
+        let _ = alloc.alloc_internal([0u8; 16]);
+        assert_eq!(alloc.block_count(), 2);
+        assert_eq!(alloc.bytes_since(start), 60 + 16);
+    }
+
+    #[test]
+    fn grow() {
+        let alloc = GrowableLinearAllocator::new(64);
+
+        let a = alloc.alloc_internal([0xABu8; 60]);
+        assert_eq!(alloc.block_count(), 1);
+        let b = alloc.alloc_internal(0xCAFEBABEu64);
+        assert_eq!(alloc.block_count(), 2);
+        assert_eq!(alloc.capacity(), 64 + 128);
+        // Earlier allocations stay valid
+        assert_eq!(a[59], 0xABu8);
+        assert_eq!(*b, 0xCAFEBABEu64);
+
+        // Allocations larger than double the previous block get a block that fits
+        let c = alloc.alloc_slice(1000, |i| i as u32);
+        assert_eq!(c[999], 999);
+        assert_eq!(alloc.block_count(), 3);
+        assert_eq!((c.as_ptr() as usize) % std::mem::align_of::<u32>(), 0);
+    }
+
+    #[test]
+    fn grow_allocation_failed() {
+        let alloc = GrowableLinearAllocator::new(64);
+        let _ = alloc.alloc_internal(0u8);
+
+        // A block this large can't be allocated, which is returned as an error
+        let layout = Layout::from_size_align(1 << 60, 8).unwrap();
+        assert!(matches!(
+            alloc.try_alloc_layout(layout),
+            Err(AllocationError::AllocationFailed { .. })
+        ));
+        assert_eq!(alloc.block_count(), 1);
+        assert_eq!(alloc.capacity(), 64);
+    }
+
+    #[test]
+    fn over_aligned() {
+        #[repr(align(256))]
+        struct A {
+            data: u8,
+        }
+
+        let alloc = GrowableLinearAllocator::new(64);
+        let _ = alloc.alloc_internal(0u8);
+        let a = alloc.alloc_internal(A { data: 0xAB });
+        assert_eq!(a.data, 0xAB);
+        assert_eq!((a as *const A as usize) % 256, 0);
+    }
+
+    #[test]
+    fn rewind() {
+        let alloc = GrowableLinearAllocator::new(64);
+
+        let _ = alloc.alloc_internal(0u8);
+        let target = alloc.peek();
+        let _ = alloc.alloc_internal([0u8; 60]);
+        let _ = alloc.alloc_internal([0u8; 100]);
+        assert_eq!(alloc.block_count(), 2);
+
+        unsafe { alloc.rewind(target) };
+        assert_eq!(alloc.peek(), target);
+
+        // The second block is reused
+        let _ = alloc.alloc_internal([0u8; 60]);
+        let _ = alloc.alloc_internal([0u8; 100]);
+        assert_eq!(alloc.block_count(), 2);
+    }
+
+    #[test]
+    fn rewind_full_block() {
+        let alloc = GrowableLinearAllocator::new(64);
+
+        let _ = alloc.alloc_internal([0u8; 64]);
+        let target = alloc.peek();
+        let _ = alloc.alloc_internal(0u32);
+        unsafe { alloc.rewind(target) };
+        assert_eq!(alloc.peek(), target);
+    }
+
+    #[should_panic(expected = "alloc doesn't belong to this allocator")]
+    #[test]
+    fn rewind_other() {
+        let alloc = GrowableLinearAllocator::new(64);
+        let other = LinearAllocator::new(64);
+        unsafe { alloc.rewind(other.peek()) };
+    }
+
+    #[test]
+    fn reset() {
+        let mut alloc = GrowableLinearAllocator::new(64);
+
+        let start = alloc.peek();
+        let _ = alloc.alloc_internal([0u8; 100]);
+        alloc.reset();
+        assert_eq!(alloc.peek(), start);
+        assert_eq!(alloc.block_count(), 2);
+    }
+}
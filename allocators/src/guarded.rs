@@ -0,0 +1,113 @@
+use crate::linear_allocator::LinearAllocator;
+
+/// A reference to an object in a [LinearAllocator], created with
+/// [LinearAllocator::alloc_guarded()]. In debug builds, the allocator panics if
+/// it's rewound over the object while the guard is alive, instead of leaving a
+/// dangling reference. Release builds don't track guards, so this is only a
+/// plain reference there. The object is not dropped with the guard.
+pub struct Guarded<'a, T> {
+    value: &'a mut T,
+    #[cfg(debug_assertions)]
+    allocator: &'a LinearAllocator<'a>,
+}
+
+impl<'a, T> Guarded<'a, T> {
+    pub(crate) fn new(allocator: &'a LinearAllocator<'a>, obj: T) -> Self {
+        // Release builds don't track the guard, so a root ScopedScratch would
+        // rewind over the object unnoticed
+        allocator.assert_unscoped();
+        let value = allocator.alloc_ptr(obj);
+        #[cfg(debug_assertions)]
+        allocator.add_guard(value.as_ptr() as *mut u8);
+        Self {
+            // Safety:
+            // - The pointer is to an object that was just allocated and is only
+            //   referenced by the guard
+            value: unsafe { &mut *value.as_ptr() },
+            #[cfg(debug_assertions)]
+            allocator,
+        }
+    }
+}
+
+impl<T> core::ops::Deref for Guarded<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<T> core::ops::DerefMut for Guarded<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.value
+    }
+}
+
+#[cfg(debug_assertions)]
+impl<T> Drop for Guarded<'_, T> {
+    fn drop(&mut self) {
+        self.allocator.remove_guard(self.value as *mut T as *mut u8);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::linear_allocator::LinearAllocatorInternal;
+
+    #[test]
+    fn deref() {
+        let alloc = LinearAllocator::new(1024);
+        let mut a = alloc.alloc_guarded(0xCAFEBABEu32);
+        assert_eq!(*a, 0xCAFEBABEu32);
+        *a = 0xDEADBEEF;
+        assert_eq!(*a, 0xDEADBEEFu32);
+    }
+
+    #[test]
+    fn rewind_after_drop() {
+        let alloc = LinearAllocator::new(1024);
+        let start = alloc.peek();
+        {
+            let _a = alloc.alloc_guarded(0u32);
+            // Rewinding up to the object is fine
+            let end = alloc.peek();
+            let _ = alloc.alloc_internal(0u64);
+            unsafe { alloc.rewind(end) };
+        }
+        unsafe { alloc.rewind(start) };
+        assert_eq!(alloc.peek(), start);
+    }
+
+    #[should_panic(
+        expected = "Tried to allocate past the scopes of an allocator held by a ScopedScratch"
+    )]
+    #[test]
+    fn scoped() {
+        let alloc = LinearAllocator::new(1024);
+        let _scratch = crate::ScopedScratch::new(&alloc);
+        let _a = alloc.alloc_guarded(0u32);
+    }
+
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Tried to rewind over an object that is borrowed through a Guarded")]
+    #[test]
+    fn rewind_over_guard() {
+        let alloc = LinearAllocator::new(1024);
+        let start = alloc.peek();
+        let _a = alloc.alloc_guarded(0u32);
+        unsafe { alloc.rewind(start) };
+    }
+
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Tried to rewind over an object that is borrowed through a Guarded")]
+    #[test]
+    fn rewind_offset_over_guard() {
+        let alloc = LinearAllocator::new(1024);
+        let _ = alloc.alloc_internal(0u8);
+        let _a = alloc.alloc_guarded(0u32);
+        unsafe { alloc.rewind_offset(2) };
+    }
+}
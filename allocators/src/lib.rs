@@ -1,5 +1,38 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+#![cfg_attr(feature = "allocator_api", feature(allocator_api))]
+
+extern crate alloc;
+
+mod arena_box;
+mod arena_vec;
+mod atomic_linear_allocator;
+mod error;
+mod fast_linear_allocator;
+mod frozen_arena;
+mod growable_linear_allocator;
+mod guarded;
 mod linear_allocator;
+mod pod_scratch;
+mod ring_allocator;
 mod scoped_scratch;
+mod stack_allocator;
+#[cfg(all(feature = "mmap", unix))]
+mod virtual_linear_allocator;
 
-pub use linear_allocator::LinearAllocator;
-pub use scoped_scratch::ScopedScratch;
+pub use arena_box::ArenaBox;
+pub use arena_vec::ArenaVec;
+pub use atomic_linear_allocator::AtomicLinearAllocator;
+pub use error::AllocationError;
+pub use fast_linear_allocator::FastLinearAllocator;
+pub use frozen_arena::FrozenArena;
+pub use growable_linear_allocator::GrowableLinearAllocator;
+pub use guarded::Guarded;
+#[cfg(feature = "stats")]
+pub use linear_allocator::Stats;
+pub use linear_allocator::{Direction, Index, LinearAllocator, Marker};
+pub use pod_scratch::PodScratch;
+pub use ring_allocator::RingAllocator;
+pub use scoped_scratch::{Checkpoint, ScopedScratch};
+pub use stack_allocator::StackAllocator;
+#[cfg(all(feature = "mmap", unix))]
+pub use virtual_linear_allocator::VirtualLinearAllocator;
@@ -1,302 +1,3406 @@
+use crate::{
+    arena_box::ArenaBox, arena_vec::ArenaVec, error::AllocationError, frozen_arena::FrozenArena,
+    guarded::Guarded,
+};
+
+use alloc::{boxed::Box, vec::Vec};
+use core::{
+    alloc::Layout,
+    cell::Cell,
+    marker::PhantomData,
+    mem::{ManuallyDrop, MaybeUninit},
+    ptr::NonNull,
+};
 use static_assertions::{const_assert_eq, const_assert_ne};
-use std::{alloc::Layout, cell::Cell};
 
-pub struct LinearAllocator {
+pub struct LinearAllocator<'buf> {
     block_start: *mut u8,
-    layout: Layout,
+    backing: Backing,
     size_bytes: usize,
     // Interior mutability because alloc_internal() and rewind() need to work on
     // immutable references so that we can allocate multiple objects
     next_alloc: Cell<*mut u8>,
+    // End of the free block, allocations from the back grow down from the end
+    // of the memory towards next_alloc
+    next_alloc_back: Cell<*mut u8>,
+    // Largest used_bytes() seen after an allocation, not lowered by rewinds
+    high_water: Cell<usize>,
+    // Set while a root ScopedScratch is alive, as it assumes to be the only user
+    // of the front of the block
+    scoped: Cell<bool>,
+    // Called with the requested size, alignment and remaining bytes before
+    // panicking on an allocation that doesn't fit
+    oom_handler: Cell<Option<OomHandler>>,
+    // Heap allocations made when the block is full, only in allocators from
+    // with_spill_to_heap()
+    spilled: Option<Cell<Vec<(*mut u8, Layout)>>>,
+    // Which end of the block allocations bump from, see with_direction()
+    direction: Direction,
+    // Objects borrowed through a live Guarded
+    #[cfg(debug_assertions)]
+    guards: Cell<Vec<*mut u8>>,
+    #[cfg(feature = "stats")]
+    alloc_count: Cell<usize>,
+    // Alignment padding added by all allocations, not lowered by rewinds
+    #[cfg(feature = "stats")]
+    wasted_padding: Cell<usize>,
+    // Offsets and call sites of the live allocations
+    #[cfg(feature = "track_backtraces")]
+    backtraces: Cell<Vec<(usize, std::backtrace::Backtrace)>>,
+    // Ties the allocator to the buffer passed into from_buffer(), 'static otherwise
+    _buffer: PhantomData<&'buf mut [u8]>,
+}
+
+// Send and Sync as the allocator can be shared between threads in a FrozenArena
+type OomHandler = Box<dyn Fn(usize, usize, usize) + Send + Sync>;
+
+// Where the memory block came from, which determines how it is released
+enum Backing {
+    // Allocated in new() with the layout
+    Heap(Layout),
+    // Allocated with the layout and locked in RAM in with_locked_pages()
+    #[cfg(all(feature = "lock_pages", unix))]
+    LockedHeap(Layout),
+    // Storage taken over from a Vec<u8> in from_vec(), block_start might be
+    // offset from ptr for alignment
+    Vec {
+        ptr: *mut u8,
+        capacity: usize,
+    },
+    // Buffer borrowed in from_buffer(), not released by the allocator
+    Borrowed,
+}
+
+/// Which way a [LinearAllocator] bumps, see [LinearAllocator::with_direction()]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// From the start of the block towards the end
+    Up,
+    /// From the end of the block towards the start, like a hardware stack
+    Down,
+}
+
+/// A snapshot of the start of the free block, see [LinearAllocator::mark()]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Marker {
+    ptr: *mut u8,
+}
+
+/// A typed handle to an object in a [LinearAllocator], see
+/// [LinearAllocator::alloc_indexed()]. Holds the byte offset of the object so
+/// it doesn't borrow the allocator.
+pub struct Index<T> {
+    offset: u32,
+    _type: PhantomData<fn() -> T>,
+}
+
+// Derives would require T to implement the traits
+impl<T> Clone for Index<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Index<T> {}
+
+impl<T> PartialEq for Index<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.offset == other.offset
+    }
+}
+
+impl<T> Eq for Index<T> {}
+
+impl<T> core::fmt::Debug for Index<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_tuple("Index").field(&self.offset).finish()
+    }
+}
+
+/// A snapshot of the usage of a [LinearAllocator], see [LinearAllocator::stats()]
+#[cfg(feature = "stats")]
+#[derive(Clone, Copy, Debug)]
+pub struct Stats {
+    pub capacity: usize,
+    pub used: usize,
+    pub remaining: usize,
+    pub high_water: usize,
+    pub alloc_count: usize,
+    pub wasted_padding: usize,
 }
 
+// Written over rewound memory in debug builds
+#[cfg(debug_assertions)]
+const POISON_BYTE: u8 = 0xDD;
+
+// Allocations have to be smaller than this so that offsets within a block
+// never overflow isize, even after adding alignment padding
+pub(crate) const MAX_ALLOCATION_BYTES: usize = (isize::MAX / 2) as usize;
+
 // This applies for most ARM, x86 and x64, but notably not for Apple M1 that has 128B lines
-const L1_CACHE_LINE_SIZE: usize = 64;
+pub(crate) const L1_CACHE_LINE_SIZE: usize = 64;
+
+// Smallest common page size, touching every 4KB also touches every larger page
+const PREFAULT_STRIDE: usize = 4096;
+
+impl LinearAllocator<'static> {
+    /// Creates an allocator with a block of `size_bytes` aligned to the L1 cache line size
+    pub fn new(size_bytes: usize) -> Self {
+        const ALIGN: usize = L1_CACHE_LINE_SIZE;
+        // align shouldn't be 0
+        const_assert_ne!(ALIGN, 0);
+        // align should be a power of two
+        const_assert_eq!(ALIGN & (ALIGN - 1), 0);
+
+        Self::with_alignment(size_bytes, ALIGN)
+    }
+
+    /// Creates an allocator with a block of `size_bytes` aligned to `align`.
+    /// This only affects the start of the block, individual allocations are
+    /// still aligned according to their types.
+    pub fn with_alignment(size_bytes: usize, align: usize) -> Self {
+        assert_ne!(size_bytes, 0, "Cannot create an allocator with size 0");
+        // Limit so that we can assume allocation arithmetic can never overflow
+        assert!(size_bytes < isize::MAX as usize);
+        assert!(
+            align.is_power_of_two(),
+            "Alignment has to be a non-zero power of two"
+        );
+
+        match Self::try_with_alignment(size_bytes, align) {
+            Ok(ret) => ret,
+            // Since we checked the arguments, this can only be a failed allocation
+            Err(_) => alloc::alloc::handle_alloc_error(
+                Layout::from_size_align(size_bytes, align).expect("Failed to create memory layout"),
+            ),
+        }
+    }
+
+    /// Creates an allocator with a block of `data.len()` bytes that holds a copy
+    /// of `data` as its allocated front, e.g. from [to_vec()]. Offsets of the
+    /// objects in `data` stay the same, and their alignment is kept as long as
+    /// the original allocator's block was aligned to the L1 cache line size.
+    /// Panics if `data` is empty.
+    pub fn from_bytes(data: &[u8]) -> Self {
+        let ret = Self::new(data.len());
+        // Safety:
+        // - The block was just allocated with data.len() bytes and can't
+        //   overlap with data
+        unsafe {
+            core::ptr::copy_nonoverlapping(data.as_ptr(), ret.block_start, data.len());
+            ret.next_alloc.set(ret.block_start.add(data.len()));
+        }
+        ret.update_high_water();
+        ret
+    }
+
+    /// Creates an allocator that falls back to the global allocator when its
+    /// block is full instead of running out of memory. Spilled allocations stay
+    /// valid until [reset()], [clear()] or drop, even if the allocator is
+    /// rewound over the point where they were made.
+    pub fn with_spill_to_heap(size_bytes: usize) -> Self {
+        let mut ret = Self::new(size_bytes);
+        ret.spilled = Some(Cell::new(Vec::new()));
+        ret
+    }
+
+    /// Creates an allocator that bumps in `direction`. Allocators that grow
+    /// [Direction::Down] start at the end of the block and align each
+    /// allocation down, and [peek()] and [rewind()] work on the lowest
+    /// allocated address instead. Back allocations would share the stack that
+    /// scopes rewind, so they panic along with the APIs that work on offsets of
+    /// the front of the block, e.g. [alloc_internal_back()], [rewind_offset()],
+    /// [alloc_indexed()], [remaining_slice()] and [freeze()].
+    pub fn with_direction(size_bytes: usize, direction: Direction) -> Self {
+        let mut ret = Self::new(size_bytes);
+        ret.direction = direction;
+        ret
+    }
+
+    /// Like [new()], but returns an error instead of panicking on an invalid
+    /// size or aborting if the memory can't be allocated.
+    pub fn try_new(size_bytes: usize) -> Result<Self, AllocationError> {
+        Self::try_with_alignment(size_bytes, L1_CACHE_LINE_SIZE)
+    }
+
+    pub(crate) fn try_with_alignment(
+        size_bytes: usize,
+        align: usize,
+    ) -> Result<Self, AllocationError> {
+        // Limit so that we can assume allocation arithmetic can never overflow
+        if size_bytes == 0 || size_bytes >= isize::MAX as usize {
+            return Err(AllocationError::InvalidLayout {
+                size_bytes,
+                alignment: align,
+            });
+        }
+
+        let layout = Layout::from_size_align(size_bytes, align).map_err(|_| {
+            AllocationError::InvalidLayout {
+                size_bytes,
+                alignment: align,
+            }
+        })?;
+
+        // Safety:
+        // - layout has a non-zero size since size_bytes is not 0 and its construction succeeded
+        let block_start = unsafe { alloc::alloc::alloc(layout) };
+
+        if block_start.is_null() {
+            return Err(AllocationError::AllocationFailed {
+                size_bytes,
+                alignment: align,
+            });
+        }
+
+        Ok(Self {
+            block_start,
+            backing: Backing::Heap(layout),
+            size_bytes,
+            next_alloc: Cell::new(block_start),
+            // Safety:
+            // - One byte past the end of the allocation is still valid
+            next_alloc_back: Cell::new(unsafe { block_start.add(size_bytes) }),
+            high_water: Cell::new(0),
+            scoped: Cell::new(false),
+            oom_handler: Cell::new(None),
+            spilled: None,
+            direction: Direction::Up,
+            #[cfg(debug_assertions)]
+            guards: Cell::new(Vec::new()),
+            #[cfg(feature = "stats")]
+            alloc_count: Cell::new(0),
+            #[cfg(feature = "stats")]
+            wasted_padding: Cell::new(0),
+            #[cfg(feature = "track_backtraces")]
+            backtraces: Cell::new(Vec::new()),
+            _buffer: PhantomData,
+        })
+    }
+
+    /// Creates an allocator that bumps within the storage of `buf`, avoiding a
+    /// separate heap allocation. The whole capacity is used, minus the bytes
+    /// skipped to align the start of the block to the cache line size.
+    /// The storage is freed on drop, or can be reclaimed with [into_vec()].
+    pub fn from_vec(buf: Vec<u8>) -> Self {
+        let capacity = buf.capacity();
+        let align_offset = buf.as_ptr().align_offset(L1_CACHE_LINE_SIZE);
+        assert_ne!(align_offset, usize::MAX);
+        assert!(
+            capacity > align_offset,
+            "Vec with capacity {} has no room after aligning to {}",
+            capacity,
+            L1_CACHE_LINE_SIZE
+        );
+
+        // Ownership of the storage is tracked in backing from here on
+        let ptr = ManuallyDrop::new(buf).as_mut_ptr();
+        // Safety:
+        // - We just checked that align_offset is within the capacity of the Vec
+        let block_start = unsafe { ptr.add(align_offset) };
+        let size_bytes = capacity - align_offset;
+
+        Self {
+            block_start,
+            backing: Backing::Vec { ptr, capacity },
+            size_bytes,
+            next_alloc: Cell::new(block_start),
+            // Safety:
+            // - One byte past the end of the allocation is still valid
+            next_alloc_back: Cell::new(unsafe { block_start.add(size_bytes) }),
+            high_water: Cell::new(0),
+            scoped: Cell::new(false),
+            oom_handler: Cell::new(None),
+            spilled: None,
+            direction: Direction::Up,
+            #[cfg(debug_assertions)]
+            guards: Cell::new(Vec::new()),
+            #[cfg(feature = "stats")]
+            alloc_count: Cell::new(0),
+            #[cfg(feature = "stats")]
+            wasted_padding: Cell::new(0),
+            #[cfg(feature = "track_backtraces")]
+            backtraces: Cell::new(Vec::new()),
+            _buffer: PhantomData,
+        }
+    }
+
+    /// Hands back the storage of an allocator created with [from_vec()].
+    /// The returned Vec is empty since allocations don't necessarily initialize
+    /// all of the bytes, but it retains the original capacity.
+    /// Returns the allocator as the error if it doesn't hold a Vec.
+    // The allocator grows past the lint threshold with the debug features
+    #[allow(clippy::result_large_err)]
+    pub fn into_vec(self) -> Result<Vec<u8>, Self> {
+        match self.backing {
+            Backing::Vec { ptr, capacity } => {
+                // Drop would free the storage
+                core::mem::forget(self);
+                // Safety:
+                // - ptr and capacity are from the Vec that was passed into from_vec()
+                // - Length 0 doesn't claim any of the bytes to be initialized
+                Ok(unsafe { Vec::from_raw_parts(ptr, 0, capacity) })
+            }
+            Backing::Heap(_) | Backing::Borrowed => Err(self),
+            #[cfg(all(feature = "lock_pages", unix))]
+            Backing::LockedHeap(_) => Err(self),
+        }
+    }
+
+    /// Like [try_new()], but also locks the block in RAM with `mlock` so that
+    /// allocations never page fault. The block is unlocked on drop. Returns an
+    /// error if the pages can't be locked, e.g. when the size exceeds
+    /// `RLIMIT_MEMLOCK`. Locks don't nest, so other memory sharing the first
+    /// or last page of the block is also unlocked on drop.
+    #[cfg(all(feature = "lock_pages", unix))]
+    pub fn with_locked_pages(size_bytes: usize) -> Result<Self, AllocationError> {
+        let mut ret = Self::try_new(size_bytes)?;
+        let Backing::Heap(layout) = ret.backing else {
+            unreachable!("try_new() always allocates from the heap");
+        };
+
+        // Safety:
+        // - The block is a valid allocation of size_bytes
+        if unsafe { libc::mlock(ret.block_start as *const libc::c_void, size_bytes) } != 0 {
+            return Err(AllocationError::PageLockFailed {
+                size_bytes,
+                errno: std::io::Error::last_os_error().raw_os_error().unwrap_or(0),
+            });
+        }
+        ret.backing = Backing::LockedHeap(layout);
+
+        Ok(ret)
+    }
+}
+
+impl<'buf> LinearAllocator<'buf> {
+    /// Creates an allocator that bumps within `buf`, e.g. a static or stack
+    /// array, without touching the global allocator. The whole buffer is used
+    /// as is so the start of the block is only guaranteed to be byte aligned.
+    /// The buffer is borrowed for the lifetime of the allocator and is not
+    /// released on drop.
+    pub fn from_buffer(buf: &'buf mut [u8]) -> Self {
+        // Safety:
+        // - The buffer is borrowed for 'buf
+        unsafe { Self::from_raw_parts(buf.as_mut_ptr(), buf.len()) }
+    }
+
+    /// Splits the free block into disjoint sub-arenas of `sizes` bytes, e.g. to
+    /// give each subsystem a fixed budget that others can't eat into. Each
+    /// sub-arena is an independent allocator that fails on its own when full.
+    /// They borrow this allocator, whose block is released once it is dropped
+    /// as usual. The sub-arenas start right after each other so they are only
+    /// guaranteed to be byte aligned. Panics if a size is 0 or the sizes don't
+    /// fit the free block.
+    ///
+    /// **Allocations in the sub-arenas are NOT dropped and their memory is
+    /// free again in this allocator once they are gone.**
+    pub fn split(&mut self, sizes: &[usize]) -> Vec<LinearAllocator<'_>> {
+        self.assert_grows_up();
+        assert!(!sizes.contains(&0), "Cannot create a sub-arena with size 0");
+        let total_bytes = sizes
+            .iter()
+            .try_fold(0usize, |sum, &size| sum.checked_add(size));
+        assert!(
+            total_bytes.is_some_and(|bytes| bytes <= self.remaining_bytes()),
+            "Sub-arenas don't fit the {} bytes of the free block",
+            self.remaining_bytes()
+        );
+
+        let mut start = self.next_alloc.get();
+        sizes
+            .iter()
+            .map(|&size| {
+                // Safety:
+                // - The sub-arenas are disjoint ranges within the free block
+                // - The free block is exclusively borrowed for the lifetime of
+                //   the sub-arenas
+                let ret = unsafe { LinearAllocator::from_raw_parts(start, size) };
+                // Safety:
+                // - We verified that the sizes fit the free block
+                start = unsafe { start.add(size) };
+                ret
+            })
+            .collect()
+    }
+
+    // Creates an allocator that bumps within the size_bytes at block_start
+    // without releasing them on drop
+    // Safety:
+    // - The memory has to be valid for reads and writes for 'buf and not be
+    //   accessed through other pointers during it
+    unsafe fn from_raw_parts(block_start: *mut u8, size_bytes: usize) -> Self {
+        assert_ne!(size_bytes, 0, "Cannot create an allocator with size 0");
+        // Limit so that we can assume allocation arithmetic can never overflow
+        assert!(size_bytes < isize::MAX as usize);
+
+        Self {
+            block_start,
+            backing: Backing::Borrowed,
+            size_bytes,
+            next_alloc: Cell::new(block_start),
+            // Safety:
+            // - One byte past the end of the slice is still valid
+            next_alloc_back: Cell::new(unsafe { block_start.add(size_bytes) }),
+            high_water: Cell::new(0),
+            scoped: Cell::new(false),
+            oom_handler: Cell::new(None),
+            spilled: None,
+            direction: Direction::Up,
+            #[cfg(debug_assertions)]
+            guards: Cell::new(Vec::new()),
+            #[cfg(feature = "stats")]
+            alloc_count: Cell::new(0),
+            #[cfg(feature = "stats")]
+            wasted_padding: Cell::new(0),
+            #[cfg(feature = "track_backtraces")]
+            backtraces: Cell::new(Vec::new()),
+            _buffer: PhantomData,
+        }
+    }
+
+    /// Returns the total size of the allocator's memory in bytes
+    pub fn capacity(&self) -> usize {
+        self.size_bytes
+    }
+
+    /// Returns a pointer to the start of the allocator's memory, e.g. for
+    /// tooling that inspects the raw bytes of the arena. The block spans
+    /// [capacity()] bytes.
+    ///
+    /// Reading through the pointer is only valid while no mutable references
+    /// to objects in the block are alive, and only for bytes that have been
+    /// initialized. Padding and the free block may be uninitialized.
+    pub fn as_ptr(&self) -> *const u8 {
+        self.block_start
+    }
+
+    /// Returns the number of bytes outside the free block, including alignment
+    /// padding. This covers allocations from both the front and the back.
+    pub fn used_bytes(&self) -> usize {
+        self.peek_offset() + (self.size_bytes - self.back_offset())
+    }
+
+    /// Returns the number of bytes in the free block
+    pub fn remaining_bytes(&self) -> usize {
+        self.size_bytes - self.used_bytes()
+    }
+
+    /// Panics if anything is allocated from the allocator, e.g. to check that
+    /// all scopes have given their memory back at the end of a test
+    pub fn assert_empty(&self) {
+        assert_eq!(self.used_bytes(), 0, "Expected the allocator to be empty");
+    }
+
+    /// Returns the memory allocated from the front of the block, from its start
+    /// up to [peek()], e.g. for checksumming an arena of POD objects.
+    /// Allocations from the back aren't included.
+    /// # Safety
+    ///  - No mutable references to objects in the region can be alive while
+    ///    the returned slice is, as it aliases them
+    ///  - Every byte in the region has to be initialized. This excludes
+    ///    alignment padding between allocations, padding within the allocated
+    ///    types and uninitialized reservations.
+    pub unsafe fn used_bytes_slice(&self) -> &[u8] {
+        self.assert_grows_up();
+        // Safety:
+        // - block_start is valid for peek_offset() bytes of the block
+        // - The caller is responsible for aliasing and initialization
+        unsafe { core::slice::from_raw_parts(self.block_start, self.peek_offset()) }
+    }
+
+    /// Copies the memory allocated from the front of the block into a Vec, e.g.
+    /// to persist an arena of POD objects that refer to each other by offset.
+    /// It can be loaded back with [from_bytes()].
+    /// # Safety
+    ///  - The same rules apply as for [used_bytes_slice()]
+    pub unsafe fn to_vec(&self) -> Vec<u8> {
+        // Safety:
+        // - The caller is responsible for the contract of used_bytes_slice()
+        unsafe { self.used_bytes_slice() }.to_vec()
+    }
+
+    /// Returns the largest [used_bytes()] has been since the allocator was
+    /// created. Rewinding and resetting don't lower it, so it can be used to
+    /// size an allocator for a workload.
+    pub fn high_water_mark(&self) -> usize {
+        self.high_water.get()
+    }
+
+    /// Returns `true` if `ptr` points to the allocator's memory
+    pub fn contains(&self, ptr: *const u8) -> bool {
+        (ptr as usize) >= (self.block_start as usize)
+            && (ptr as usize) < (self.block_start as usize) + self.size_bytes
+    }
+
+    /// Returns how many bytes are left for an allocation aligned at `align`,
+    /// i.e. the free tail of the block minus the padding the alignment would add.
+    pub fn remaining_aligned(&self, align: usize) -> usize {
+        self.assert_grows_up();
+        assert!(align.is_power_of_two(), "align has to be a power of two");
+
+        let align_offset = self.next_alloc.get().align_offset(align);
+        assert_ne!(align_offset, usize::MAX);
+
+        self.remaining_bytes().saturating_sub(align_offset)
+    }
+
+    /// Returns the offset of the start of the free block from the start of the
+    /// allocator's memory. Unlike [peek()], this stays meaningful if the
+    /// underlying memory is moved.
+    pub fn peek_offset(&self) -> usize {
+        // Safety:
+        // - next_alloc is derived from self.block_start and within the allocation
+        //   (or one byte past it), see alloc_internal()
+        unsafe { self.next_alloc.get().offset_from(self.block_start) as usize }
+    }
+
+    // Offset of the end of the free block from the start of the allocator's memory
+    fn back_offset(&self) -> usize {
+        // Safety:
+        // - next_alloc_back is derived from self.block_start and within the
+        //   allocation (or one byte past it), see try_bump_back()
+        unsafe { self.next_alloc_back.get().offset_from(self.block_start) as usize }
+    }
+
+    /// Rewinds the allocator back to `offset` from the start of its memory.
+    /// # Safety
+    ///  - `offset` has to be from [peek_offset()] or the offset of an allocation
+    ///  - The same drop and reference rules apply as for [rewind()]
+    pub unsafe fn rewind_offset(&self, offset: usize) {
+        self.assert_grows_up();
+        assert!(
+            offset <= self.size_bytes,
+            "offset doesn't belong to this allocator"
+        );
+        // Safety:
+        // - We just checked that offset is within the allocation or one byte past it
+        let alloc = unsafe { self.block_start.add(offset) };
+        self.assert_unguarded(alloc);
+        self.poison(alloc, self.next_alloc.get());
+        self.next_alloc.replace(alloc);
+        self.forget_rewound();
+    }
+
+    /// Like [rewind()], but first hands the memory being released, from `alloc`
+    /// to the start of the free block, to `on_region`. This gives a single place
+    /// to clean up the rewound objects, e.g. to drop them or to scrub secrets.
+    /// The region includes alignment padding, so it might not be initialized.
+    /// # Safety
+    ///  - The same rules apply as for [rewind()], `on_region` runs before any
+    ///    of the memory is released
+    pub unsafe fn rewind_with(
+        &self,
+        alloc: *mut u8,
+        on_region: impl FnOnce(&mut [MaybeUninit<u8>]),
+    ) {
+        self.assert_grows_up();
+        let next_alloc = self.next_alloc.get();
+        assert!(
+            (self.contains(alloc) && alloc <= next_alloc) || alloc == next_alloc,
+            "alloc doesn't belong to this allocator"
+        );
+        // Safety:
+        // - alloc is within the allocated front of the block, which ends at next_alloc
+        // - The caller guarantees that no references to the region are alive
+        // - MaybeUninit doesn't require the memory to be initialized
+        let region = unsafe {
+            core::slice::from_raw_parts_mut(
+                alloc as *mut MaybeUninit<u8>,
+                next_alloc.offset_from(alloc) as usize,
+            )
+        };
+        on_region(region);
+        // Safety:
+        // - The caller is responsible for the contract of rewind()
+        unsafe { self.rewind(alloc) };
+    }
+
+    /// Returns the free block as uninitialized bytes so that it can be written
+    /// directly, e.g. by an encoder. The written bytes are claimed with [commit()].
+    pub fn remaining_slice(&mut self) -> &mut [MaybeUninit<u8>] {
+        self.assert_grows_up();
+        let len = self.remaining_bytes();
+        // Safety:
+        // - The free block is within the allocation and not referenced by any
+        //   allocation
+        // - The exclusive borrow prevents allocations while the slice is alive
+        // - MaybeUninit doesn't require the memory to be initialized
+        unsafe {
+            core::slice::from_raw_parts_mut(self.next_alloc.get() as *mut MaybeUninit<u8>, len)
+        }
+    }
+
+    /// Claims the first `bytes` of the free block as allocated, typically after
+    /// writing them through [remaining_slice()]. Panics if `bytes` is larger
+    /// than the free block.
+    pub fn commit(&mut self, bytes: usize) {
+        self.assert_grows_up();
+        assert!(
+            bytes <= self.remaining_bytes(),
+            "Tried to commit {} bytes with only {} remaining.",
+            bytes,
+            self.remaining_bytes()
+        );
+        // Safety:
+        // - We just checked that the result is within the free block
+        self.next_alloc
+            .set(unsafe { self.next_alloc.get().add(bytes) });
+        self.update_high_water();
+    }
+
+    /// Writes a byte to each page of the free block so that the OS maps them in
+    /// right away, e.g. before timing a benchmark or in latency-critical
+    /// initialization. Otherwise the first allocation that reaches a page also
+    /// pays for its page fault. Allocated objects are left untouched.
+    pub fn prefault(&self) {
+        let start = self.next_alloc.get();
+        for offset in (0..self.remaining_bytes()).step_by(PREFAULT_STRIDE) {
+            // Safety:
+            // - The offset is within the free block, which isn't referenced by
+            //   any allocation
+            // - Volatile so that the write isn't optimized out
+            unsafe { start.add(offset).write_volatile(0) };
+        }
+    }
+
+    /// Returns `true` if a `T` would fit in the free block
+    pub fn fits<T: Sized>(&self) -> bool {
+        self.fits_layout(Layout::new::<T>())
+    }
+
+    /// Returns `true` if an allocation with `layout` would fit in the free block
+    pub fn fits_layout(&self, layout: Layout) -> bool {
+        self.assert_grows_up();
+        // Mirrors try_alloc_internal()
+        if layout.size() == 0 {
+            return true;
+        }
+        self.fit(layout.size(), layout.align()).is_ok()
+    }
+
+    /// Reserves `layout.size()` bytes aligned at `layout.align()` without
+    /// initializing them, e.g. for buffers passed to C APIs. Returns `None` if
+    /// the reservation doesn't fit. The memory stays valid until the allocator
+    /// is rewound over it, which includes dropping a [ScopedScratch](crate::ScopedScratch)
+    /// that was created before the reservation.
+    pub fn try_reserve(&self, layout: Layout) -> Option<NonNull<u8>> {
+        let new_alloc = self.try_bump(layout.size(), layout.align()).ok()?;
+        // Safety:
+        // - try_bump() returns pointers within the block, which is never null
+        Some(unsafe { NonNull::new_unchecked(new_alloc) })
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    /// Reserves `layout.size()` bytes aligned at `layout.align()`, e.g. for
+    /// objects whose type is only known at runtime. Panics if the bytes don't
+    /// fit or if the allocator is held by a [ScopedScratch](crate::ScopedScratch).
+    /// Zero-sized layouts don't touch the allocator.
+    pub fn alloc_layout(&self, layout: Layout) -> &mut [MaybeUninit<u8>] {
+        self.assert_unscoped();
+        let ptr = match self.try_alloc_layout(layout) {
+            Ok(ptr) => ptr,
+            Err(err) => self.alloc_failed(err),
+        };
+        // Safety:
+        // - ptr was just reserved for layout.size() bytes, or is dangling and
+        //   aligned for a zero-sized layout
+        // - MaybeUninit doesn't require the memory to be initialized
+        unsafe { core::slice::from_raw_parts_mut(ptr as *mut MaybeUninit<u8>, layout.size()) }
+    }
+
+    /// Allocates and initializes `obj` like [alloc_internal()], but returns a
+    /// pointer instead of a reference, e.g. for handing the object to C. The
+    /// same validity rules apply as for [try_reserve()]. The caller must not
+    /// create a `&mut T` from it that overlaps with other references to the
+    /// object. `obj` is never dropped by the allocator.
+    pub fn alloc_ptr<T: Sized>(&self, obj: T) -> NonNull<T> {
+        NonNull::from(self.alloc_internal(obj))
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    /// Allocates and initializes `obj` like [alloc_internal()], also returning
+    /// its offset from the start of the allocator's memory. Offsets stay valid
+    /// when the bytes are copied out, e.g. with [used_bytes_slice()], and can be
+    /// used in place of pointers in relocatable data. Zero-sized objects get
+    /// the offset of the start of the free block. Panics if the object spills
+    /// to the heap, see [with_spill_to_heap()], or if the allocator is held by
+    /// a [ScopedScratch](crate::ScopedScratch).
+    pub fn alloc_at_offset<T: Sized>(&self, obj: T) -> (&mut T, usize) {
+        self.assert_unscoped();
+        let ret = self.alloc_internal(obj);
+        let offset = if core::mem::size_of::<T>() == 0 {
+            self.peek_offset()
+        } else {
+            let ptr = ret as *mut T as *mut u8;
+            assert!(
+                self.contains(ptr),
+                "Allocations that spilled to the heap don't have an offset"
+            );
+            (ptr as usize) - (self.block_start as usize)
+        };
+        (ret, offset)
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    /// Allocates and initializes `obj` like [alloc_internal()], also returning
+    /// an [Index] that can be used to access it later with [get()] and
+    /// [get_mut()] without holding a borrow, e.g. for nodes of a graph that
+    /// refer to each other. Panics if the object doesn't start within the first
+    /// 4GB of the block, or if the allocator is held by a
+    /// [ScopedScratch](crate::ScopedScratch).
+    pub fn alloc_indexed<T: Sized>(&self, obj: T) -> (Index<T>, &mut T) {
+        self.assert_grows_up();
+        self.assert_unscoped();
+        let (ret, offset) = self.alloc_at_offset(obj);
+        let index = Index {
+            offset: u32::try_from(offset).expect("Index offset doesn't fit in u32"),
+            _type: PhantomData,
+        };
+        (index, ret)
+    }
+
+    /// Returns the object at `index`.
+    /// # Safety
+    ///  - `index` has to be from [alloc_indexed()] on this allocator, and the
+    ///    allocator can't have been rewound over the object since
+    ///  - No mutable references to the object can be alive while the returned
+    ///    one is
+    pub unsafe fn get<T: Sized>(&self, index: Index<T>) -> &T {
+        // Safety:
+        // - The caller is responsible for the contract of get_ptr()
+        unsafe { &*self.get_ptr(index) }
+    }
+
+    /// Returns the object at `index` mutably. The allocator is borrowed
+    /// exclusively, so no other references from it can be alive.
+    /// # Safety
+    ///  - `index` has to be from [alloc_indexed()] on this allocator, and the
+    ///    allocator can't have been rewound over the object since
+    pub unsafe fn get_mut<T: Sized>(&mut self, index: Index<T>) -> &mut T {
+        // Safety:
+        // - The caller is responsible for the contract of get_ptr()
+        // - self is borrowed exclusively
+        unsafe { &mut *self.get_ptr(index) }
+    }
+
+    // Safety:
+    // - index has to be a live object from alloc_indexed() on this allocator
+    unsafe fn get_ptr<T: Sized>(&self, index: Index<T>) -> *mut T {
+        // Zero-sized objects aren't stored in the block, see alloc_internal()
+        if core::mem::size_of::<T>() == 0 {
+            return NonNull::dangling().as_ptr();
+        }
+        let offset = index.offset as usize;
+        assert!(
+            offset + core::mem::size_of::<T>() <= self.peek_offset(),
+            "index is past the allocated objects"
+        );
+        // Safety:
+        // - The object is within the allocated part of the block
+        unsafe { self.block_start.add(offset) as *mut T }
+    }
+
+    /// Returns a marker to the start of the free block that can later be
+    /// rewound back to with [rewind_to()].
+    pub fn mark(&self) -> Marker {
+        Marker { ptr: self.peek() }
+    }
+
+    /// Rewinds the allocator back to `marker`, releasing everything allocated
+    /// from the front after it was created.
+    /// # Safety
+    ///  - `marker` has to be from [mark()] on this allocator
+    ///  - The same drop and reference rules apply as for [rewind()]
+    pub unsafe fn rewind_to(&self, marker: Marker) {
+        // Safety:
+        // - Markers are only created from peek()
+        // - The caller is responsible for the rest of the contract
+        unsafe { self.rewind(marker.ptr) };
+    }
+
+    /// Consumes the allocator into a read-only view of its memory that can be
+    /// shared between threads. The OOM handler, recorded backtraces and
+    /// allocations that spilled to the heap are released, as only the block
+    /// is readable through the view. Panics if objects were allocated from the
+    /// back, which the view doesn't cover.
+    pub fn freeze(mut self) -> FrozenArena<'buf> {
+        self.assert_grows_up();
+        assert_eq!(
+            self.back_offset(),
+            self.size_bytes,
+            "Tried to freeze an allocator with allocations from the back"
+        );
+        // FrozenArena relies on the allocator holding no state besides the
+        // block and its offsets
+        self.oom_handler.set(None);
+        #[cfg(feature = "track_backtraces")]
+        self.backtraces.set(Vec::new());
+        self.free_spilled();
+        FrozenArena::new(self)
+    }
+
+    /// Creates a builder that appends objects to the end of the allocator,
+    /// see [ArenaVec].
+    pub fn vec_builder<T>(&self) -> ArenaVec<'_, T> {
+        ArenaVec::new(self)
+    }
+
+    /// Returns `true` if `r` ends where the next allocation from the front
+    /// would start, or starts there on allocators that grow [Direction::Down],
+    /// i.e. it is the most recent one and can be rewound over without affecting
+    /// other allocations. Zero-sized objects don't take up room in the
+    /// allocator and are never the last.
+    pub fn is_last<T: ?Sized>(&self, r: &T) -> bool {
+        let size = core::mem::size_of_val(r);
+        let start = r as *const T as *const u8;
+        let bound = match self.direction {
+            Direction::Up => start.wrapping_add(size),
+            Direction::Down => start,
+        };
+        size > 0 && bound == self.peek()
+    }
+
+    /// Skips the free block forward to the next multiple of `align`, so that the
+    /// next allocation starts aligned regardless of its type, e.g. a page for
+    /// DMA. The skipped bytes are wasted until the allocator is rewound over
+    /// them. Panics if the padding doesn't fit. `align` has to be a power of two.
+    pub fn align_to(&self, align: usize) {
+        self.assert_grows_up();
+        assert!(align.is_power_of_two(), "align has to be a power of two");
+        let align_offset = match self.fit(0, align) {
+            Ok(align_offset) => align_offset,
+            Err(err) => self.alloc_failed(err),
+        };
+
+        // Safety:
+        // - We just verified that the padding fits the free block
+        self.next_alloc
+            .set(unsafe { self.next_alloc.get().add(align_offset) });
+        self.update_high_water();
+        #[cfg(feature = "stats")]
+        self.wasted_padding
+            .set(self.wasted_padding.get() + align_offset);
+    }
+
+    /// Allocates `obj` behind an owned handle that drops it and gives the
+    /// memory back when dropped, see [ArenaBox]. Panics if `obj` doesn't fit or
+    /// if the allocator is held by a [ScopedScratch](crate::ScopedScratch).
+    pub fn alloc_owned<T>(&self, obj: T) -> ArenaBox<'_, T> {
+        ArenaBox::new(self, obj)
+    }
+
+    /// Sets a hook that is called with the requested size, alignment and
+    /// remaining bytes when an allocation doesn't fit, right before the
+    /// allocator panics. Fallible allocations return the error without calling
+    /// it. Replaces the previous handler. The handler has to be thread safe as
+    /// the allocator can be shared between threads after a [freeze()].
+    pub fn set_oom_handler(&self, f: impl Fn(usize, usize, usize) + Send + Sync + 'static) {
+        self.oom_handler.set(Some(Box::new(f)));
+    }
+
+    /// Allocates `obj` behind a guard that catches rewinds over it in debug
+    /// builds while the guard is alive, see [Guarded]. Panics if the allocator
+    /// is held by a [ScopedScratch](crate::ScopedScratch).
+    pub fn alloc_guarded<T>(&self, obj: T) -> Guarded<'_, T> {
+        Guarded::new(self, obj)
+    }
+
+    #[cfg(debug_assertions)]
+    pub(crate) fn add_guard(&self, ptr: *mut u8) {
+        let mut guards = self.guards.take();
+        guards.push(ptr);
+        self.guards.set(guards);
+    }
+
+    #[cfg(debug_assertions)]
+    pub(crate) fn remove_guard(&self, ptr: *mut u8) {
+        let mut guards = self.guards.take();
+        if let Some(i) = guards.iter().position(|&guard| guard == ptr) {
+            guards.swap_remove(i);
+        }
+        self.guards.set(guards);
+    }
+
+    // Panics in debug builds if rewinding to alloc would release an object
+    // that is borrowed through a Guarded
+    #[allow(unused_variables)]
+    fn assert_unguarded(&self, alloc: *mut u8) {
+        #[cfg(debug_assertions)]
+        {
+            let guards = self.guards.take();
+            let (start, end) = match self.direction {
+                Direction::Up => (alloc, self.next_alloc.get()),
+                Direction::Down => (self.next_alloc_back.get(), alloc),
+            };
+            let guarded = guards.iter().any(|&guard| guard >= start && guard < end);
+            self.guards.set(guards);
+            assert!(
+                !guarded,
+                "Tried to rewind over an object that is borrowed through a Guarded"
+            );
+        }
+    }
+
+    // Panics if the allocator grows down, for APIs that work on the front of
+    // the block
+    fn assert_grows_up(&self) {
+        assert_eq!(
+            self.direction,
+            Direction::Up,
+            "Not supported by allocators that grow down"
+        );
+    }
+
+    // Panics if a root ScopedScratch holds the allocator, for allocations that
+    // borrow the allocator itself, as dropping the scope would rewind over them
+    pub(crate) fn assert_unscoped(&self) {
+        assert!(
+            !self.scoped.get(),
+            "Tried to allocate past the scopes of an allocator held by a ScopedScratch"
+        );
+    }
+
+    pub(crate) fn is_scoped(&self) -> bool {
+        self.scoped.get()
+    }
+
+    // Reserves size_bytes aligned at alignment from the free block
+    fn try_bump(&self, size_bytes: usize, alignment: usize) -> Result<*mut u8, AllocationError> {
+        if self.direction == Direction::Down {
+            return self.try_bump_back(size_bytes, alignment);
+        }
+        let align_offset = match self.fit(size_bytes, alignment) {
+            Ok(align_offset) => align_offset,
+            Err(err) => return self.try_spill(size_bytes, alignment, err),
+        };
+
+        // Safety:
+        // - self.next_alloc has been verified to be within the allocation either
+        //   by alloc_internal() or rewind(), and we just verified that the aligned
+        //   object fits the allocation
+        // - Maximum held block size is under isize::MAX so offsets within it can't overflow isize
+        // - Rust allocations never wrap around the address space
+        let new_alloc = unsafe {
+            let new_alloc = self.next_alloc.get().add(align_offset);
+            self.next_alloc.replace(new_alloc.add(size_bytes));
+            new_alloc
+        };
+
+        self.update_high_water();
+        self.count_allocation(new_alloc, align_offset);
+
+        Ok(new_alloc)
+    }
+
+    /// Allocates and initializes `obj` through an exclusive reference, panicking
+    /// if it doesn't fit. The returned reference borrows the allocator, so this
+    /// suits an owner that uses each object before allocating the next one.
+    pub fn alloc_mut<T: Sized>(&mut self, obj: T) -> &mut T {
+        let size_bytes = core::mem::size_of::<T>();
+        if size_bytes == 0 || self.direction == Direction::Down {
+            return self.alloc_internal(obj);
+        }
+
+        let align_offset = match self.fit(size_bytes, core::mem::align_of::<T>()) {
+            Ok(align_offset) => align_offset,
+            Err(AllocationError::OutOfMemory { .. }) if self.spilled.is_some() => {
+                return self.alloc_internal(obj);
+            }
+            Err(err) => self.alloc_failed(err),
+        };
+
+        let next_alloc = self.next_alloc.get_mut();
+        // Safety:
+        // - next_alloc is within the allocation and we just verified that the
+        //   aligned object fits, see try_bump()
+        let new_alloc = unsafe {
+            let new_alloc = next_alloc.add(align_offset);
+            *next_alloc = new_alloc.add(size_bytes);
+            new_alloc
+        };
+
+        self.update_high_water();
+        self.count_allocation(new_alloc, align_offset);
+
+        // Safety:
+        // - new_alloc is a pointer to at least size_of::<T>() bytes of the block
+        //   aligned for T
+        // - The allocator is borrowed exclusively for the lifetime of the reference
+        unsafe {
+            let t_ptr = new_alloc as *mut T;
+            t_ptr.write(obj);
+            &mut *t_ptr
+        }
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    /// Allocates and initializes `obj` from the back of the free block, panicking
+    /// if it doesn't fit. The front and the back grow towards each other, so
+    /// long-lived data can be kept at one end while the other is rewound.
+    /// Back allocations are only released by [reset()] and their destructors
+    /// are never run. Panics on allocators that grow [Direction::Down].
+    pub fn alloc_internal_back<T: Sized>(&self, obj: T) -> &mut T {
+        match self.try_alloc_internal_back(obj) {
+            Ok(ret) => ret,
+            Err(err) => self.alloc_failed(err),
+        }
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    /// Like [alloc_internal_back()], but returns an error if `obj` doesn't fit.
+    /// On failure, `obj` is dropped and the allocator is left untouched.
+    pub fn try_alloc_internal_back<T: Sized>(&self, obj: T) -> Result<&mut T, AllocationError> {
+        self.assert_grows_up();
+        if core::mem::size_of::<T>() == 0 {
+            return self.try_alloc_internal(obj);
+        }
+
+        let new_alloc =
+            self.try_bump_back(core::mem::size_of::<T>(), core::mem::align_of::<T>())?;
+
+        // Safety:
+        // - new_alloc is a pointer to at least size_of::<T>() bytes of the block
+        //   from self.block_start and this allocator can't shared between threads
+        // - We aligned new_alloc for T
+        unsafe {
+            let t_ptr = new_alloc as *mut T;
+            t_ptr.write(obj);
+            Ok(&mut *t_ptr)
+        }
+    }
+
+    // Returns the padding needed to align the next allocation of size_bytes at
+    // alignment, or the error describing why it doesn't fit
+    fn fit(&self, size_bytes: usize, alignment: usize) -> Result<usize, AllocationError> {
+        // Makes sure new_size never overflows
+        check_size(size_bytes, alignment)?;
+
+        let next_alloc = self.next_alloc.get();
+        let align_offset = next_alloc.align_offset(alignment);
+        assert_ne!(align_offset, usize::MAX);
+
+        // Safety:
+        // - self.block_start is at the start of the allocation and next_alloc
+        //   has been verified to be within the allocation (or one byte past it)
+        //   either by alloc_internal() or rewind()
+        // - We assume next_alloc is derived from self.block_start because it's either
+        //   - the same as self.block_start
+        //   - derived from a previous self.next_alloc
+        //   - from rewind() that has safety rules expecting the input to be
+        //     - from peek()
+        //       - some previous self.next_alloc
+        //     - pointer to an object from alloc_internal()
+        //       - derived from some previous self.next_alloc
+        // - Distance between two *mut u8 is always a multiple of u8
+        // - Maximum held block size is under isize::MAX so distances within it can't overflow isize
+        // - Rust allocations never wrap around the address space
+        let previous_size = unsafe { next_alloc.offset_from(self.block_start) as usize };
+
+        // The size check above makes sure this can't overflow since
+        // previous_size <= self.size_bytes < isize::MAX
+        let new_size = previous_size + align_offset + size_bytes;
+        let back_offset = self.back_offset();
+        if new_size > back_offset {
+            return Err(AllocationError::OutOfMemory {
+                size_bytes,
+                alignment,
+                padding_bytes: align_offset,
+                remaining_bytes: back_offset - previous_size,
+            });
+        }
+        Ok(align_offset)
+    }
+
+    // Like try_bump(), but allocates from the end of the free block downwards
+    fn try_bump_back(
+        &self,
+        size_bytes: usize,
+        alignment: usize,
+    ) -> Result<*mut u8, AllocationError> {
+        check_size(size_bytes, alignment)?;
+
+        let front_offset = self.peek_offset();
+        let back_offset = self.back_offset();
+        let remaining_bytes = back_offset - front_offset;
+
+        // Align the start of the allocation down, the padding ends up between
+        // the new allocation and the previous one from the back
+        let new_offset = back_offset.checked_sub(size_bytes).and_then(|offset| {
+            let misalignment = (self.block_start as usize + offset) % alignment;
+            offset.checked_sub(misalignment)
+        });
+        let new_offset = match new_offset {
+            Some(offset) if offset >= front_offset => offset,
+            _ => {
+                // Padding the allocation would have needed, wrapping is fine
+                // since alignment is a power of two
+                let padding_bytes =
+                    (self.block_start as usize + back_offset).wrapping_sub(size_bytes) % alignment;
+                let err = AllocationError::OutOfMemory {
+                    size_bytes,
+                    alignment,
+                    padding_bytes,
+                    remaining_bytes,
+                };
+                return self.try_spill(size_bytes, alignment, err);
+            }
+        };
+
+        // Safety:
+        // - We just verified that new_offset is within the free block
+        let new_alloc = unsafe { self.block_start.add(new_offset) };
+        self.next_alloc_back.set(new_alloc);
+
+        self.update_high_water();
+        self.count_allocation(new_alloc, back_offset - new_offset - size_bytes);
+
+        Ok(new_alloc)
+    }
+
+    // Allocates size_bytes aligned at alignment from the global allocator if
+    // the allocator spills to the heap and err is from a full block, returns
+    // err otherwise
+    fn try_spill(
+        &self,
+        size_bytes: usize,
+        alignment: usize,
+        err: AllocationError,
+    ) -> Result<*mut u8, AllocationError> {
+        let (Some(spilled), AllocationError::OutOfMemory { .. }) = (&self.spilled, err) else {
+            return Err(err);
+        };
+        // Zero-sized allocations still get a unique address
+        let layout = Layout::from_size_align(size_bytes.max(1), alignment).map_err(|_| err)?;
+
+        // Safety:
+        // - layout has a non-zero size
+        let ret = unsafe { alloc::alloc::alloc(layout) };
+        if ret.is_null() {
+            return Err(AllocationError::AllocationFailed {
+                size_bytes,
+                alignment,
+            });
+        }
+
+        let mut allocations = spilled.take();
+        allocations.push((ret, layout));
+        spilled.set(allocations);
+
+        Ok(ret)
+    }
+
+    /// Returns the number of allocations that spilled over to the heap since
+    /// the allocator was last reset, see [with_spill_to_heap()]
+    pub fn spilled_count(&self) -> usize {
+        self.spilled.as_ref().map_or(0, |spilled| {
+            let allocations = spilled.take();
+            let count = allocations.len();
+            spilled.set(allocations);
+            count
+        })
+    }
+
+    // Frees the heap allocations made by try_spill()
+    fn free_spilled(&mut self) {
+        if let Some(spilled) = &mut self.spilled {
+            for (ptr, layout) in spilled.get_mut().drain(..) {
+                // Safety:
+                // - ptr was allocated with layout in try_spill()
+                // - The exclusive borrow means there can't be references to it
+                unsafe { alloc::alloc::dealloc(ptr, layout) };
+            }
+        }
+    }
+
+    fn update_high_water(&self) {
+        let used_bytes = self.used_bytes();
+        if used_bytes > self.high_water.get() {
+            self.high_water.set(used_bytes);
+        }
+    }
+
+    // Updates the allocation statistics of the stats feature and records the
+    // call site of new_alloc for the track_backtraces feature
+    #[allow(unused_variables)]
+    fn count_allocation(&self, new_alloc: *mut u8, padding_bytes: usize) {
+        #[cfg(feature = "stats")]
+        {
+            self.alloc_count.set(self.alloc_count.get() + 1);
+            self.wasted_padding
+                .set(self.wasted_padding.get() + padding_bytes);
+        }
+        #[cfg(feature = "track_backtraces")]
+        {
+            let mut backtraces = self.backtraces.take();
+            backtraces.push((
+                (new_alloc as usize) - (self.block_start as usize),
+                std::backtrace::Backtrace::force_capture(),
+            ));
+            self.backtraces.set(backtraces);
+        }
+    }
+
+    // Drops the backtraces of front allocations that were rewound over
+    fn forget_rewound(&self) {
+        #[cfg(feature = "track_backtraces")]
+        {
+            let front_offset = self.peek_offset();
+            let back_offset = self.back_offset();
+            let mut backtraces = self.backtraces.take();
+            backtraces.retain(|&(offset, _)| offset < front_offset || offset >= back_offset);
+            self.backtraces.set(backtraces);
+        }
+    }
+
+    /// Takes the offsets and call sites of the allocations that haven't been
+    /// rewound over, e.g. to find out what filled up the allocator. Backtraces
+    /// can't be cloned, so each allocation is only reported once.
+    #[cfg(feature = "track_backtraces")]
+    pub fn take_allocations(&self) -> Vec<(usize, std::backtrace::Backtrace)> {
+        self.backtraces.take()
+    }
+
+    /// Rewinds the allocator back to the start of its memory.
+    ///
+    /// **This does NOT run destructors.** Objects that need Drop and were
+    /// allocated directly from the allocator are leaked. It is intended for
+    /// arenas of Copy types, or for when the caller has already dropped
+    /// everything. Taking `&mut self` guarantees no references to the
+    /// allocations are alive.
+    pub fn reset(&mut self) {
+        // Allocators that grow down only bump the back, which is reset below
+        if self.direction == Direction::Up {
+            // Safety:
+            //  - block_start is the first possible allocation
+            //  - The exclusive borrow means there can't be references to any allocations
+            unsafe { self.rewind(self.block_start) };
+        }
+        // Safety:
+        // - One byte past the end of the allocation is still valid
+        let end = unsafe { self.block_start.add(self.size_bytes) };
+        self.poison(self.next_alloc_back.get(), end);
+        self.next_alloc_back.set(end);
+        self.forget_rewound();
+        self.free_spilled();
+    }
+
+    /// Returns the allocator to the state it was created in. Unlike [reset()],
+    /// which keeps the [high_water_mark()] for sizing the allocator over the
+    /// whole run, this also zeroes it along with the counters of the stats
+    /// feature, e.g. for measuring each iteration of a test separately.
+    ///
+    /// **This does NOT run destructors.**
+    pub fn clear(&mut self) {
+        self.reset();
+        self.high_water.set(0);
+        #[cfg(feature = "stats")]
+        {
+            self.alloc_count.set(0);
+            self.wasted_padding.set(0);
+        }
+    }
+
+    // Overwrites released memory in [start, end) with POISON_BYTE in debug builds
+    // so that reads through stale references are easy to spot
+    #[allow(unused_variables)]
+    fn poison(&self, start: *mut u8, end: *mut u8) {
+        #[cfg(debug_assertions)]
+        if start < end {
+            // Safety:
+            // - Callers pass a range within the block
+            // - The range is released so it's not referenced by live allocations
+            unsafe { core::ptr::write_bytes(start, POISON_BYTE, end.offset_from(start) as usize) };
+        }
+    }
+
+    /// Returns the number of allocations made over the lifetime of the allocator.
+    /// Rewinding doesn't decrease the count.
+    #[cfg(feature = "stats")]
+    pub fn allocation_count(&self) -> usize {
+        self.alloc_count.get()
+    }
+
+    /// Returns the number of allocations made since [allocation_count()]
+    /// returned `marker_count`.
+    #[cfg(feature = "stats")]
+    pub fn allocations_since(&self, marker_count: usize) -> usize {
+        let count = self.alloc_count.get();
+        assert!(
+            marker_count <= count,
+            "marker_count is ahead of the allocation count"
+        );
+        count - marker_count
+    }
+
+    /// Returns the number of bytes lost to alignment padding over the lifetime
+    /// of the allocator. A large count compared to [used_bytes()] suggests that
+    /// grouping allocations by alignment would save space. Rewinding doesn't
+    /// decrease the count.
+    #[cfg(feature = "stats")]
+    pub fn wasted_padding(&self) -> usize {
+        self.wasted_padding.get()
+    }
+
+    /// Returns the current usage numbers of the allocator in one go
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> Stats {
+        Stats {
+            capacity: self.capacity(),
+            used: self.used_bytes(),
+            remaining: self.remaining_bytes(),
+            high_water: self.high_water_mark(),
+            alloc_count: self.allocation_count(),
+            wasted_padding: self.wasted_padding(),
+        }
+    }
+}
+
+impl core::fmt::Debug for LinearAllocator<'_> {
+    // Only reports the state of the allocator, the allocated memory might be uninitialized
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let alignment = match self.backing {
+            Backing::Heap(layout) => layout.align(),
+            #[cfg(all(feature = "lock_pages", unix))]
+            Backing::LockedHeap(layout) => layout.align(),
+            Backing::Vec { .. } => L1_CACHE_LINE_SIZE,
+            Backing::Borrowed => 1,
+        };
+        f.debug_struct("LinearAllocator")
+            .field("block_start", &self.block_start)
+            .field("next_alloc", &self.next_alloc.get())
+            .field("size_bytes", &self.size_bytes)
+            .field("used_bytes", &self.used_bytes())
+            .field("remaining_bytes", &self.remaining_bytes())
+            .field("alignment", &alignment)
+            .finish()
+    }
+}
+
+impl Drop for LinearAllocator<'_> {
+    fn drop(&mut self) {
+        self.free_spilled();
+        match self.backing {
+            // Safety:
+            //  - self.block_start was allocated using the same allocator in new()
+            //  - layout is the layout it was allocated with
+            Backing::Heap(layout) => unsafe {
+                alloc::alloc::dealloc(self.block_start, layout);
+            },
+            // Safety:
+            //  - self.block_start was allocated and locked with size_bytes in
+            //    with_locked_pages()
+            //  - layout is the layout it was allocated with
+            #[cfg(all(feature = "lock_pages", unix))]
+            Backing::LockedHeap(layout) => unsafe {
+                libc::munlock(self.block_start as *const libc::c_void, self.size_bytes);
+                alloc::alloc::dealloc(self.block_start, layout);
+            },
+            // Safety:
+            //  - ptr and capacity are from the Vec that was passed into from_vec()
+            Backing::Vec { ptr, capacity } => unsafe {
+                drop(Vec::from_raw_parts(ptr, 0, capacity));
+            },
+            // The buffer is owned by the caller
+            Backing::Borrowed => (),
+        }
+    }
+}
+
+// This interface is not exposed outside the library with the goal of being safe all around
+pub trait LinearAllocatorInternal {
+    // Interior mutability required by interface
+    // The references will be to non-overlapping memory as long as [rewind()] is not misused.
+    #[allow(clippy::mut_from_ref)]
+    /// Allocates and initializes `obj`, panicking if it doesn't fit
+    fn alloc_internal<T: Sized>(&self, obj: T) -> &mut T {
+        match self.try_alloc_internal(obj) {
+            Ok(ret) => ret,
+            Err(err) => self.alloc_failed(err),
+        }
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    /// Allocates and initializes `obj`, or returns an error if it doesn't fit.
+    /// On failure, `obj` is dropped and the allocator is left untouched.
+    fn try_alloc_internal<T: Sized>(&self, obj: T) -> Result<&mut T, AllocationError> {
+        self.try_alloc_aligned(obj, 1)
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    /// Allocates and initializes `obj` aligned to at least `align`, panicking
+    /// if it doesn't fit. `align` has to be a power of two.
+    fn alloc_aligned<T: Sized>(&self, obj: T, align: usize) -> &mut T {
+        match self.try_alloc_aligned(obj, align) {
+            Ok(ret) => ret,
+            Err(err) => self.alloc_failed(err),
+        }
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    /// Like [try_alloc_internal()], but aligns `obj` to the larger of `align`
+    /// and its natural alignment. `align` has to be a power of two.
+    fn try_alloc_aligned<T: Sized>(&self, obj: T, align: usize) -> Result<&mut T, AllocationError>;
+
+    #[allow(clippy::mut_from_ref)]
+    /// Reserves uninitialized memory for `len` objects of type `T`.
+    /// An empty slice is returned for `len == 0` without touching the allocator.
+    fn try_alloc_slice_uninit<T: Sized>(
+        &self,
+        len: usize,
+    ) -> Result<&mut [MaybeUninit<T>], AllocationError>;
+
+    /// Reserves uninitialized memory for `layout` and returns its start.
+    /// Zero-sized layouts get a dangling, aligned pointer without touching the
+    /// allocator.
+    fn try_alloc_layout(&self, layout: Layout) -> Result<*mut u8, AllocationError>;
+
+    #[allow(clippy::mut_from_ref)]
+    /// Reserves uninitialized memory for a `T`, panicking if it doesn't fit.
+    /// Large objects can be written in place through the returned slot instead
+    /// of moving them in from the stack.
+    fn alloc_uninit<T: Sized>(&self) -> &mut MaybeUninit<T> {
+        match self.try_alloc_slice_uninit::<T>(1) {
+            Ok(slot) => &mut slot[0],
+            Err(err) => self.alloc_failed(err),
+        }
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    /// Reserves room for a `T` and only then constructs it with `f`, panicking
+    /// if it doesn't fit.
+    fn alloc_with<T: Sized>(&self, f: impl FnOnce() -> T) -> &mut T {
+        match self.try_alloc_with(f) {
+            Ok(ret) => ret,
+            Err(err) => self.alloc_failed(err),
+        }
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    /// Like [alloc_with()], but returns an error if a `T` doesn't fit.
+    /// `f` is not called on failure.
+    fn try_alloc_with<T: Sized>(&self, f: impl FnOnce() -> T) -> Result<&mut T, AllocationError> {
+        let slot = self.try_alloc_slice_uninit::<T>(1)?;
+        Ok(slot[0].write(f()))
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    /// Allocates a slice of `len` objects, initializing each with `init(index)`.
+    /// Panics if the slice doesn't fit. `len == 0` doesn't touch the allocator.
+    fn alloc_slice<T: Sized>(&self, len: usize, init: impl FnMut(usize) -> T) -> &mut [T] {
+        match self.try_alloc_slice(len, init) {
+            Ok(ret) => ret,
+            Err(err) => self.alloc_failed(err),
+        }
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    /// Allocates an array of `N` objects, initializing each with `init(index)`.
+    /// Panics if the array doesn't fit.
+    fn alloc_array<T: Sized, const N: usize>(&self, init: impl FnMut(usize) -> T) -> &mut [T; N] {
+        let slice = self.alloc_slice(N, init);
+        // Safety:
+        // - The slice has exactly N elements and [T; N] has the same layout as [T]
+        unsafe { &mut *(slice.as_mut_ptr() as *mut [T; N]) }
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    /// Like [alloc_slice()], but returns an error if the slice doesn't fit.
+    /// `init` is not called on failure.
+    fn try_alloc_slice<T: Sized>(
+        &self,
+        len: usize,
+        init: impl FnMut(usize) -> T,
+    ) -> Result<&mut [T], AllocationError> {
+        let slice = self.try_alloc_slice_uninit::<T>(len)?;
+        Ok(init_slice(slice, init))
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    /// Allocates `header` followed by a slice of `len` objects, initializing
+    /// each with `init(index)`, in a single allocation. Panics if they don't fit.
+    fn alloc_slice_header<H: Sized, T: Sized>(
+        &self,
+        header: H,
+        len: usize,
+        init: impl FnMut(usize) -> T,
+    ) -> (&mut H, &mut [T]) {
+        match self.try_alloc_slice_header(header, len, init) {
+            Ok(ret) => ret,
+            Err(err) => self.alloc_failed(err),
+        }
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    /// Like [alloc_slice_header()], but returns an error if the allocation
+    /// doesn't fit. `header` is dropped and `init` is not called on failure.
+    fn try_alloc_slice_header<H: Sized, T: Sized>(
+        &self,
+        header: H,
+        len: usize,
+        init: impl FnMut(usize) -> T,
+    ) -> Result<(&mut H, &mut [T]), AllocationError> {
+        let (layout, slice_offset) = Layout::array::<T>(len)
+            .and_then(|slice| Layout::new::<H>().extend(slice))
+            .expect("Slice header layout overflows isize");
+        let ptr = self.try_alloc_layout(layout)?;
+
+        // Safety:
+        // - ptr was just reserved for layout, which holds H at the start and
+        //   len objects of T aligned at slice_offset
+        // - MaybeUninit doesn't require the memory to be initialized
+        unsafe {
+            let header_ptr = ptr as *mut H;
+            header_ptr.write(header);
+            let slice = core::slice::from_raw_parts_mut(
+                ptr.wrapping_add(slice_offset) as *mut MaybeUninit<T>,
+                len,
+            );
+            Ok((&mut *header_ptr, init_slice(slice, init)))
+        }
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    /// Copies `s` into the allocator. Empty strings don't touch the allocator.
+    fn alloc_str(&self, s: &str) -> &mut str {
+        let bytes = match self.try_alloc_slice_uninit::<u8>(s.len()) {
+            Ok(bytes) => bytes,
+            Err(err) => self.alloc_failed(err),
+        };
+        // Safety:
+        // - bytes was just allocated with room for s.len() bytes and can't
+        //   overlap with s
+        // - The copied bytes are valid UTF-8 since they come from a str
+        unsafe {
+            core::ptr::copy_nonoverlapping(s.as_ptr(), bytes.as_mut_ptr() as *mut u8, s.len());
+            core::str::from_utf8_unchecked_mut(core::slice::from_raw_parts_mut(
+                bytes.as_mut_ptr() as *mut u8,
+                s.len(),
+            ))
+        }
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    /// Copies `src` into a new slice with a single bulk copy, panicking if it
+    /// doesn't fit. An empty `src` doesn't touch the allocator.
+    fn alloc_slice_copy<T: Copy>(&self, src: &[T]) -> &mut [T] {
+        let slots = match self.try_alloc_slice_uninit::<T>(src.len()) {
+            Ok(slots) => slots,
+            Err(err) => self.alloc_failed(err),
+        };
+        // Safety:
+        // - slots was just allocated with room for src.len() objects aligned
+        //   for T and can't overlap with src
+        // - Copy types can be duplicated bitwise
+        unsafe {
+            core::ptr::copy_nonoverlapping(src.as_ptr(), slots.as_mut_ptr() as *mut T, src.len());
+            core::slice::from_raw_parts_mut(slots.as_mut_ptr() as *mut T, src.len())
+        }
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    /// Reserves `len` bytes and fills them with zeros, e.g. for IO buffers.
+    /// Panics if the bytes don't fit. `len == 0` doesn't touch the allocator.
+    fn alloc_bytes_zeroed(&self, len: usize) -> &mut [u8] {
+        let bytes = match self.try_alloc_slice_uninit::<u8>(len) {
+            Ok(bytes) => bytes,
+            Err(err) => self.alloc_failed(err),
+        };
+        // Safety:
+        // - bytes was just allocated with room for len bytes
+        // - Zeroed bytes are valid u8s
+        unsafe {
+            core::ptr::write_bytes(bytes.as_mut_ptr(), 0, len);
+            core::slice::from_raw_parts_mut(bytes.as_mut_ptr() as *mut u8, len)
+        }
+    }
+
+    /// Rewinds the allocator back to `alloc`.
+    /// # Safety
+    ///  - `alloc` has to be a pointer to an allocation from [alloc_internal()]
+    ///    or a pointer returned by [peek()].
+    ///  - Caller is responsible for calling drop on objects returned by
+    ///    [alloc_internal()] that will be rewound over, if they don't implement Copy
+    ///  - Caller also needs to ensure that any references held to the rewound
+    ///    objects are dropped
+    ///
+    /// Panics if `alloc` is outside the allocator's free block and front
+    /// allocations. The check is kept in release builds as it's a single
+    /// comparison against a rewind to a bad pointer corrupting all later allocations.
+    unsafe fn rewind(&self, alloc: *mut u8);
+
+    /// Returns the pointer to the start of the free block
+    fn peek(&self) -> *mut u8;
+
+    /// Panics with `err`, which failed an allocation that can't return it
+    fn alloc_failed(&self, err: AllocationError) -> ! {
+        panic!("{}", err)
+    }
+
+    /// Returns the number of bytes allocated after `start`, including padding.
+    /// `start` has to be a pointer returned by [peek()] that hasn't been
+    /// rewound over.
+    fn bytes_since(&self, start: *mut u8) -> usize {
+        (self.peek() as usize) - (start as usize)
+    }
+
+    /// Marks the allocator as held by a root ScopedScratch, panicking if
+    /// another one already holds it
+    fn acquire_scope(&self);
+
+    /// Releases the allocator from the root ScopedScratch that held it
+    fn release_scope(&self);
+}
+
+impl LinearAllocatorInternal for LinearAllocator<'_> {
+    #[allow(clippy::mut_from_ref)]
+    fn try_alloc_aligned<T: Sized>(&self, obj: T, align: usize) -> Result<&mut T, AllocationError> {
+        assert!(align.is_power_of_two(), "align has to be a power of two");
+        let alignment = core::mem::align_of::<T>().max(align);
+
+        // Zero-sized types don't need memory so they don't touch the bump pointer,
+        // similar to how Vec handles them
+        if core::mem::size_of::<T>() == 0 {
+            let t_ptr = if alignment == core::mem::align_of::<T>() {
+                NonNull::<T>::dangling().as_ptr()
+            } else {
+                core::ptr::without_provenance_mut::<T>(alignment)
+            };
+            // Safety:
+            // - A dangling pointer is well-aligned and non-null, which is
+            //   enough for reads and writes of zero-sized types
+            unsafe {
+                t_ptr.write(obj);
+                return Ok(&mut *t_ptr);
+            }
+        }
+
+        let new_alloc = self.try_bump(core::mem::size_of::<T>(), alignment)?;
+
+        // Safety:
+        // - new_alloc is a pointer to at least size_of::<T>() bytes of the block
+        //   from self.block_start and this allocator can't shared between threads
+        // - We aligned new_alloc for T and align
+        unsafe {
+            let t_ptr = new_alloc as *mut T;
+            t_ptr.write(obj);
+            Ok(&mut *t_ptr)
+        }
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    fn try_alloc_slice_uninit<T: Sized>(
+        &self,
+        len: usize,
+    ) -> Result<&mut [MaybeUninit<T>], AllocationError> {
+        if len == 0 {
+            return Ok(&mut []);
+        }
+        if core::mem::size_of::<T>() == 0 {
+            // Safety:
+            // - A dangling pointer is well-aligned and non-null, and the slice
+            //   spans zero bytes for any len
+            return Ok(unsafe {
+                core::slice::from_raw_parts_mut(NonNull::<MaybeUninit<T>>::dangling().as_ptr(), len)
+            });
+        }
+
+        // Saturated sizes are caught by the size check in try_bump()
+        let size_bytes = core::mem::size_of::<T>().saturating_mul(len);
+        let new_alloc = self.try_bump(size_bytes, core::mem::align_of::<T>())?;
+
+        // Safety:
+        // - new_alloc is a pointer to at least len * size_of::<T>() bytes of the
+        //   block from self.block_start and aligned for T
+        // - MaybeUninit doesn't require the memory to be initialized
+        unsafe {
+            Ok(core::slice::from_raw_parts_mut(
+                new_alloc as *mut MaybeUninit<T>,
+                len,
+            ))
+        }
+    }
+
+    fn try_alloc_layout(&self, layout: Layout) -> Result<*mut u8, AllocationError> {
+        if layout.size() == 0 {
+            return Ok(core::ptr::without_provenance_mut(layout.align()));
+        }
+        self.try_bump(layout.size(), layout.align())
+    }
+
+    unsafe fn rewind(&self, alloc: *mut u8) {
+        if self.direction == Direction::Down {
+            // Safety:
+            // - One byte past the end of the allocation is still valid
+            let end = unsafe { self.block_start.add(self.size_bytes) };
+            let next_alloc_back = self.next_alloc_back.get();
+            assert!(
+                alloc >= next_alloc_back && alloc <= end,
+                "alloc doesn't belong to this allocator"
+            );
+            self.assert_unguarded(alloc);
+            self.poison(next_alloc_back, alloc);
+            self.next_alloc_back.replace(alloc);
+            self.forget_rewound();
+            return;
+        }
+
+        // Let's be nice and catch the obvious error, a bad pointer would corrupt
+        // every allocation after this so it's worth checking in release too
+        // Reference lifetimes and allocated structs needing Drop are truly the
+        // responsibility of the caller
+        // The end of the free block is valid since peek() returns it when the
+        // allocator is full
+        assert!(
+            (self.contains(alloc) || alloc == self.next_alloc_back.get())
+                && alloc <= self.next_alloc_back.get(),
+            "alloc doesn't belong to this allocator"
+        );
+        self.assert_unguarded(alloc);
+        self.poison(alloc, self.next_alloc.get());
+        self.next_alloc.replace(alloc);
+        self.forget_rewound();
+    }
+
+    fn peek(&self) -> *mut u8 {
+        match self.direction {
+            Direction::Up => self.next_alloc.get(),
+            Direction::Down => self.next_alloc_back.get(),
+        }
+    }
+
+    fn bytes_since(&self, start: *mut u8) -> usize {
+        (self.peek() as usize).abs_diff(start as usize)
+    }
+
+    fn alloc_failed(&self, err: AllocationError) -> ! {
+        if let AllocationError::OutOfMemory {
+            size_bytes,
+            alignment,
+            remaining_bytes,
+            ..
+        } = err
+        {
+            // Taken out for the call as Cell doesn't hand out references
+            if let Some(handler) = self.oom_handler.take() {
+                handler(size_bytes, alignment, remaining_bytes);
+                self.oom_handler.set(Some(handler));
+            }
+        }
+        panic!("{}", err)
+    }
+
+    fn acquire_scope(&self) {
+        assert!(
+            !self.scoped.replace(true),
+            "Allocator is already used by another root ScopedScratch"
+        );
+    }
+
+    fn release_scope(&self) {
+        self.scoped.set(false);
+    }
+}
+
+/// Allows using the allocator for std collections on nightly. Deallocation only
+/// reclaims memory if it was the most recent allocation.
+///
+/// ```
+/// #![feature(allocator_api)]
+/// use allocators::LinearAllocator;
+///
+/// let alloc = LinearAllocator::new(1024);
+/// let mut v: Vec<u32, &LinearAllocator> = Vec::new_in(&alloc);
+/// for i in 0..10 {
+///     v.push(i);
+/// }
+/// assert_eq!(v.iter().sum::<u32>(), 45);
+/// ```
+#[cfg(feature = "allocator_api")]
+unsafe impl core::alloc::Allocator for &LinearAllocator<'_> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, core::alloc::AllocError> {
+        // A root ScopedScratch would rewind over the allocation
+        if self.scoped.get() {
+            return Err(core::alloc::AllocError);
+        }
+        let new_alloc = self.try_reserve(layout).ok_or(core::alloc::AllocError)?;
+        Ok(NonNull::slice_from_raw_parts(new_alloc, layout.size()))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        // Reclaim the memory if this was the last allocation, leak otherwise.
+        // Safety:
+        // - ptr was allocated with layout by allocate() so the end is within the block
+        if !self.scoped.get() && ptr.as_ptr().add(layout.size()) == self.next_alloc.get() {
+            self.next_alloc.set(ptr.as_ptr());
+        }
+    }
+}
+
+// Returns an error if size_bytes is too large to allocate from any block
+pub(crate) fn check_size(size_bytes: usize, alignment: usize) -> Result<(), AllocationError> {
+    if size_bytes >= MAX_ALLOCATION_BYTES {
+        return Err(AllocationError::TooLarge {
+            size_bytes,
+            alignment,
+        });
+    }
+    Ok(())
+}
+
+// Initializes each element of slice with f(index)
+fn init_slice<T>(slice: &mut [MaybeUninit<T>], mut f: impl FnMut(usize) -> T) -> &mut [T] {
+    for (i, elem) in slice.iter_mut().enumerate() {
+        elem.write(f(i));
+    }
+    // Safety:
+    // - All elements were just initialized
+    // - MaybeUninit<T> has the same layout as T
+    unsafe { &mut *(slice as *mut [MaybeUninit<T>] as *mut [T]) }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::mem::{align_of, size_of};
+
+    #[test]
+    fn alloc_u8() {
+        let alloc = LinearAllocator::new(1024);
+
+        let a = alloc.alloc_internal(0xABu8);
+        assert_eq!(*a, 0xABu8);
+        assert_eq!(a as *const u8, alloc.block_start);
+        assert_eq!(
+            unsafe { alloc.next_alloc.get().offset_from(alloc.block_start) },
+            size_of::<u8>() as isize
+        );
+    }
+
+    #[test]
+    fn alloc_pod() {
+        let alloc = LinearAllocator::new(1024);
+
+        #[allow(dead_code)]
+        struct A {
+            data: u32,
+        }
+
+        let a = alloc.alloc_internal(A { data: 0xDEADC0DE });
+        assert_eq!(a.data, 0xDEADC0DE);
+        assert_eq!(a as *const A as *const u8, alloc.block_start);
+        assert_eq!(
+            unsafe { alloc.next_alloc.get().offset_from(alloc.block_start) },
+            size_of::<A>() as isize
+        );
+    }
+
+    #[test]
+    fn alloc_drop() {
+        let alloc = LinearAllocator::new(1024);
+
+        #[allow(dead_code)]
+        struct A {
+            data: Vec<u32>,
+        }
+
+        let a = alloc.alloc_internal(A {
+            data: vec![0xC0FFEEEE],
+        });
+        assert_eq!(a.data.len(), 1);
+        assert_eq!(a.data[0], 0xC0FFEEEE);
+        assert_eq!(a as *const A as *const u8, alloc.block_start);
+        assert_eq!(
+            unsafe { alloc.next_alloc.get().offset_from(alloc.block_start) },
+            size_of::<A>() as isize
+        );
+
+        unsafe { std::ptr::drop_in_place(a) };
+    }
+
+    #[test]
+    fn two_allocs() {
+        let alloc = LinearAllocator::new(1024);
+
+        let a = alloc.alloc_internal(0xCAFEBABEu32);
+        let b = alloc.alloc_internal(0xDEADCAFEu32);
+        let a_ptr = a as *const u32;
+        let b_ptr = b as *const u32;
+        assert_eq!(*a, 0xCAFEBABEu32);
+        assert_eq!(*b, 0xDEADCAFEu32);
+        assert_eq!(unsafe { b_ptr.offset_from(a_ptr) }, 1);
+        assert_eq!(
+            unsafe { alloc.next_alloc.get().offset_from(alloc.block_start) },
+            size_of::<u32>() as isize * 2
+        );
+    }
+
+    #[should_panic(
+        expected = "Tried to allocate 1025 bytes aligned at 1 (wasted 0 padding bytes) with only 1024 remaining."
+    )]
+    #[test]
+    fn overflow_first() {
+        let alloc = LinearAllocator::new(1024);
+        let _ = alloc.alloc_internal([0u8; 1025]);
+    }
+
+    #[should_panic(
+        expected = "Tried to allocate 1000 bytes aligned at 4 (wasted 0 padding bytes) with only 768 remaining."
+    )]
+    #[test]
+    fn overflow_second() {
+        let alloc = LinearAllocator::new(1024);
+        let _ = alloc.alloc_internal([0u8; 256]);
+        let _ = alloc.alloc_internal([0u32; 250]);
+    }
+
+    #[test]
+    fn try_alloc_internal() {
+        let alloc = LinearAllocator::new(1024);
+
+        let a = alloc.try_alloc_internal(0xCAFEBABEu32).unwrap();
+        assert_eq!(*a, 0xCAFEBABEu32);
+        assert_eq!(a as *const u32 as *const u8, alloc.block_start);
+    }
+
+    #[test]
+    fn try_alloc_internal_oom() {
+        let alloc = LinearAllocator::new(1024);
+        let _ = alloc.alloc_internal([0u8; 256]);
+        let peek = alloc.peek();
+
+        match alloc.try_alloc_internal([0u32; 250]) {
+            Err(err) => assert_eq!(
+                err,
+                AllocationError::OutOfMemory {
+                    size_bytes: 1000,
+                    alignment: 4,
+                    padding_bytes: 0,
+                    remaining_bytes: 768
+                }
+            ),
+            Ok(_) => panic!("Allocation should have failed"),
+        }
+        assert_eq!(alloc.peek(), peek);
+
+        // Failed allocations drop the object
+        let dropped = std::cell::Cell::new(false);
+        struct A<'a> {
+            _data: [u8; 1024],
+            dropped: &'a std::cell::Cell<bool>,
+        }
+        impl Drop for A<'_> {
+            fn drop(&mut self) {
+                self.dropped.set(true);
+            }
+        }
+        assert!(alloc
+            .try_alloc_internal(A {
+                _data: [0u8; 1024],
+                dropped: &dropped,
+            })
+            .is_err());
+        assert!(dropped.get());
+        assert_eq!(alloc.peek(), peek);
+    }
+
+    #[test]
+    fn different_alignment() {
+        let alloc = LinearAllocator::new(1024);
+
+        #[allow(dead_code)]
+        #[repr(C)]
+        struct A {
+            data: u8,
+        }
+        #[allow(dead_code)]
+        #[repr(C)]
+        struct B {
+            data: u64,
+        }
+        assert_ne!(size_of::<A>() % align_of::<B>(), 0);
+
+        let _ = alloc.alloc_internal(A { data: 0 });
+        let b = alloc.alloc_internal(B { data: 0 });
+        assert_eq!((b as *const B as usize) % align_of::<B>(), 0);
+    }
+
+    #[test]
+    fn alloc_aligned() {
+        let alloc = LinearAllocator::new(1024);
+
+        let _ = alloc.alloc_internal(0u8);
+        let a = alloc.alloc_aligned([1.0f32; 8], 32);
+        assert_eq!((a.as_ptr() as usize) % 32, 0);
+        assert_eq!(a[7], 1.0f32);
+        let b = alloc.alloc_aligned(0xABu8, 256);
+        assert_eq!((b as *const u8 as usize) % 256, 0);
+        assert_eq!(*b, 0xABu8);
+        // The natural alignment wins if it's larger
+        let c = alloc.alloc_aligned(0u64, 1);
+        assert_eq!((c as *const u64 as usize) % 8, 0);
+        let d = alloc.alloc_aligned((), 64);
+        assert_eq!((d as *const () as usize) % 64, 0);
+    }
+
+    #[should_panic(expected = "align has to be a power of two")]
+    #[test]
+    fn alloc_aligned_npot() {
+        let alloc = LinearAllocator::new(1024);
+        let _ = alloc.alloc_aligned(0u32, 24);
+    }
+
+    #[test]
+    fn rewind() {
+        let alloc = LinearAllocator::new(1024);
+
+        let _ = alloc.alloc_internal(0u8);
+        let target = alloc.peek();
+        let _ = alloc.alloc_internal(0u64);
+        assert_ne!(alloc.next_alloc.get(), target);
+        unsafe { alloc.rewind(target) };
+        assert_eq!(alloc.next_alloc.get(), target);
+    }
+
+    #[test]
+    fn alloc_zst() {
+        struct Empty;
+
+        let alloc = LinearAllocator::new(1024);
+
+        let _ = alloc.alloc_internal(0u8);
+        let target = alloc.peek();
+        let a = alloc.alloc_internal(());
+        let b = alloc.alloc_internal(Empty);
+        let c = alloc.alloc_slice(16, |_| Empty);
+        assert_eq!(c.len(), 16);
+        assert_eq!(alloc.peek(), target);
+        assert_eq!(*a, ());
+        let _ = b;
+
+        let _ = alloc.alloc_internal(0u64);
+        let _ = alloc.alloc_internal(());
+        assert_ne!(alloc.peek(), target);
+        unsafe { alloc.rewind(target) };
+        assert_eq!(alloc.peek(), target);
+        let _ = alloc.alloc_internal(());
+        assert_eq!(alloc.peek(), target);
+    }
+
+    #[test]
+    fn alloc_mut() {
+        let mut alloc = LinearAllocator::new(64);
+
+        let _ = alloc.alloc_mut(0xABu8);
+        let a = alloc.alloc_mut(0xCAFEBABEu32);
+        assert_eq!(*a, 0xCAFEBABEu32);
+        let a_ptr = a as *const u32;
+        assert_eq!((a_ptr as usize) % align_of::<u32>(), 0);
+        assert_eq!(alloc.used_bytes(), 8);
+        assert_eq!(alloc.high_water_mark(), 8);
+        assert_eq!(unsafe { *a_ptr }, 0xCAFEBABEu32);
+
+        let _ = alloc.alloc_mut(());
+        assert_eq!(alloc.used_bytes(), 8);
+    }
+
+    #[should_panic(
+        expected = "Tried to allocate 64 bytes aligned at 1 (wasted 0 padding bytes) with only 63 remaining."
+    )]
+    #[test]
+    fn alloc_mut_overflow() {
+        let mut alloc = LinearAllocator::new(64);
+        let _ = alloc.alloc_mut(0u8);
+        let _ = alloc.alloc_mut([0u8; 64]);
+    }
+
+    #[test]
+    fn alloc_back() {
+        let mut alloc = LinearAllocator::new(64);
+
+        let a = alloc.alloc_internal_back(0xABu8);
+        assert_eq!(*a, 0xABu8);
+        assert_eq!(a as *const u8, unsafe { alloc.block_start.add(63) });
+        let b = alloc.alloc_internal_back(0xCAFEBABEu32);
+        assert_eq!(*b, 0xCAFEBABEu32);
+        assert_eq!(b as *const u32 as *const u8, unsafe {
+            alloc.block_start.add(56)
+        });
+        assert_eq!(alloc.used_bytes(), 8);
+        assert_eq!(alloc.remaining_bytes(), 56);
+
+        // Fill from both ends until the pointers meet
+        for i in 0..6 {
+            let _ = alloc.alloc_internal(i as u32);
+            let _ = alloc.alloc_internal_back(i as u32);
+        }
+        let _ = alloc.alloc_internal(0u32);
+        let _ = alloc.alloc_internal_back(0u32);
+        assert_eq!(alloc.remaining_bytes(), 0);
+        assert_eq!(alloc.peek(), alloc.next_alloc_back.get());
+        assert!(alloc.try_alloc_internal(0u8).is_err());
+        assert!(alloc.try_alloc_internal_back(0u8).is_err());
+        assert_eq!(alloc.high_water_mark(), 64);
+
+        alloc.reset();
+        assert_eq!(alloc.used_bytes(), 0);
+        assert_eq!(alloc.remaining_bytes(), 64);
+    }
+
+    #[should_panic(
+        expected = "Tried to allocate 8 bytes aligned at 8 (wasted 4 padding bytes) with only 11 remaining."
+    )]
+    #[test]
+    fn alloc_back_overflow() {
+        let alloc = LinearAllocator::new(64);
+
+        let _ = alloc.alloc_internal([0u8; 49]);
+        let _ = alloc.alloc_internal_back(0u32);
+        // Fits in the remaining bytes but not when aligned
+        let _ = alloc.alloc_internal_back(0u64);
+    }
+
+    #[test]
+    fn assert_empty() {
+        let alloc = LinearAllocator::new(1024);
+        alloc.assert_empty();
+        {
+            let scratch = crate::ScopedScratch::new(&alloc);
+            let _ = scratch.alloc(0u32);
+        }
+        alloc.assert_empty();
+    }
+
+    #[should_panic(expected = "Expected the allocator to be empty")]
+    #[test]
+    fn assert_empty_fail() {
+        let alloc = LinearAllocator::new(1024);
+        let _ = alloc.alloc_internal_back(0u8);
+        alloc.assert_empty();
+    }
+
+    #[test]
+    fn spill_to_heap() {
+        let mut alloc = LinearAllocator::with_spill_to_heap(64);
+
+        let a = alloc.alloc_internal([0xABu8; 60]);
+        assert_eq!(alloc.spilled_count(), 0);
+        let b = alloc.alloc_internal(0xCAFEBABEu64);
+        let c = alloc.alloc_internal_back([0xCDu8; 16]);
+        assert!(!alloc.contains(b as *mut u64 as *const u8));
+        assert!(!alloc.contains(c.as_ptr()));
+        assert_eq!(alloc.spilled_count(), 2);
+        assert_eq!(a[59], 0xAB);
+        assert_eq!(*b, 0xCAFEBABEu64);
+        assert_eq!(c[15], 0xCD);
+
+        // Rewinding doesn't free the spilled allocations
+        let start = alloc.as_ptr() as *mut u8;
+        unsafe { alloc.rewind(start) };
+        assert_eq!(*b, 0xCAFEBABEu64);
+        assert_eq!(alloc.spilled_count(), 2);
+
+        // Too large allocations still fail
+        assert!(matches!(
+            alloc.try_alloc_slice_uninit::<u8>(MAX_ALLOCATION_BYTES),
+            Err(AllocationError::TooLarge { .. })
+        ));
+
+        let _ = alloc.alloc_mut([0u8; 128]);
+        assert_eq!(alloc.spilled_count(), 3);
+        alloc.reset();
+        assert_eq!(alloc.spilled_count(), 0);
+        let _ = alloc.alloc_internal([0u8; 128]);
+        assert_eq!(alloc.spilled_count(), 1);
+    }
+
+    #[test]
+    fn contains() {
+        let alloc = LinearAllocator::new(1024);
+        let other = LinearAllocator::new(1024);
+
+        let a = alloc.alloc_internal(0u32);
+        assert!(alloc.contains(a as *const u32 as *const u8));
+        assert!(!other.contains(a as *const u32 as *const u8));
+        assert!(alloc.contains(alloc.block_start));
+        assert!(alloc.contains(unsafe { alloc.block_start.add(1023) }));
+        assert!(!alloc.contains(unsafe { alloc.block_start.add(1024) }));
+        assert!(!alloc.contains(std::ptr::null()));
+    }
+
+    #[test]
+    fn as_ptr() {
+        let alloc = LinearAllocator::new(1024);
+        let _ = alloc.alloc_internal(0u64);
+        let offset = alloc.peek_offset();
+        let _ = alloc.alloc_internal(0xABu8);
+
+        let block = unsafe { core::slice::from_raw_parts(alloc.as_ptr(), alloc.capacity()) };
+        assert_eq!(block[offset], 0xAB);
+    }
+
+    #[should_panic(expected = "alloc doesn't belong to this allocator")]
+    #[test]
+    fn rewind_assert_below() {
+        let alloc = LinearAllocator::new(1024);
+        unsafe { alloc.rewind(std::ptr::dangling_mut::<u8>()) };
+    }
+
+    #[should_panic(expected = "alloc doesn't belong to this allocator")]
+    #[test]
+    fn rewind_assert_above() {
+        let alloc = LinearAllocator::new(1024);
+        unsafe { alloc.rewind(alloc.peek().offset(1025)) }
+    }
+
+    #[should_panic(expected = "alloc doesn't belong to this allocator")]
+    #[test]
+    fn rewind_assert_back() {
+        let alloc = LinearAllocator::new(1024);
+        let a = alloc.alloc_internal_back(0u32);
+        unsafe { alloc.rewind((a as *mut u32 as *mut u8).add(1)) }
+    }
+
+    #[test]
+    fn rewind_full() {
+        let alloc = LinearAllocator::new(1024);
+        let _ = alloc.alloc_internal([0u8; 1024]);
+        let end = alloc.peek();
+        unsafe { alloc.rewind(alloc.block_start) };
+        unsafe { alloc.rewind(end) };
+        assert_eq!(alloc.remaining_bytes(), 0);
+    }
+
+    #[test]
+    fn alloc_slice_uninit() {
+        let alloc = LinearAllocator::new(1024);
+
+        let _ = alloc.alloc_internal(0u8);
+        let a = alloc.try_alloc_slice_uninit::<u32>(4).unwrap();
+        assert_eq!(a.len(), 4);
+        assert_eq!((a.as_ptr() as usize) % align_of::<u32>(), 0);
+        assert_eq!(
+            alloc.peek_offset(),
+            align_of::<u32>() + 4 * size_of::<u32>()
+        );
+
+        let b = alloc.try_alloc_slice_uninit::<u64>(0).unwrap();
+        assert!(b.is_empty());
+        assert_eq!(
+            alloc.peek_offset(),
+            align_of::<u32>() + 4 * size_of::<u32>()
+        );
+
+        assert!(alloc.try_alloc_slice_uninit::<u64>(128).is_err());
+    }
+
+    #[test]
+    fn alloc_with() {
+        let alloc = LinearAllocator::new(1024);
+
+        let _ = alloc.alloc_internal(0u8);
+        let a = alloc.alloc_with(|| [0xCAFEBABEu32; 16]);
+        assert_eq!(a[15], 0xCAFEBABEu32);
+        assert_eq!((a.as_ptr() as usize) % align_of::<u32>(), 0);
+        assert_eq!(alloc.used_bytes(), 4 + 64);
+    }
+
+    #[test]
+    fn try_alloc_with_oom() {
+        let alloc = LinearAllocator::new(64);
+        let _ = alloc.alloc_internal([0u8; 60]);
+        let peek = alloc.peek();
+
+        let mut called = false;
+        assert!(alloc
+            .try_alloc_with(|| {
+                called = true;
+                0u64
+            })
+            .is_err());
+        assert!(!called);
+        assert_eq!(alloc.peek(), peek);
+    }
+
+    #[test]
+    fn alloc_uninit() {
+        struct A {
+            a: u8,
+            b: [u64; 128],
+        }
+
+        let alloc = LinearAllocator::new(2048);
+
+        let _ = alloc.alloc_internal(0u8);
+        let slot = alloc.alloc_uninit::<A>();
+        assert_eq!((slot.as_ptr() as usize) % align_of::<A>(), 0);
+        let ptr = slot.as_mut_ptr();
+        // Safety:
+        // - ptr points to the uninitialized slot and is aligned for A
+        unsafe {
+            std::ptr::addr_of_mut!((*ptr).a).write(0xAB);
+            for (i, b) in (*std::ptr::addr_of_mut!((*ptr).b)).iter_mut().enumerate() {
+                *b = i as u64;
+            }
+        }
+        let a = unsafe { slot.assume_init_ref() };
+        assert_eq!(a.a, 0xAB);
+        assert_eq!(a.b[127], 127);
+        assert_eq!(alloc.used_bytes(), 8 + size_of::<A>());
+    }
+
+    #[test]
+    fn alloc_slice() {
+        let alloc = LinearAllocator::new(1024);
+
+        let _ = alloc.alloc_internal(0u8);
+        let a = alloc.alloc_slice(4, |i| i as u32 * 2);
+        assert_eq!(a, &[0, 2, 4, 6]);
+        assert_eq!((a.as_ptr() as usize) % align_of::<u32>(), 0);
+        assert_eq!(alloc.used_bytes(), 4 + 4 * size_of::<u32>());
+
+        let b = alloc.alloc_slice(0, |_| -> u64 { panic!("Shouldn't be called") });
+        assert!(b.is_empty());
+        assert_eq!(alloc.used_bytes(), 4 + 4 * size_of::<u32>());
+    }
+
+    #[test]
+    fn alloc_array() {
+        let alloc = LinearAllocator::new(1024);
+
+        let _ = alloc.alloc_internal(0u8);
+        let start = alloc.used_bytes();
+        let a: &mut [u32; 16] = alloc.alloc_array(|i| i as u32);
+        assert_eq!((a.as_ptr() as usize) % align_of::<u32>(), 0);
+        assert_eq!(a[15], 15);
+        // Only the alignment padding of the first allocation is added
+        assert_eq!(alloc.used_bytes() - start, 3 + 64);
+
+        let b: &mut [u64; 0] = alloc.alloc_array(|_| 0);
+        assert!(b.is_empty());
+        assert_eq!(alloc.used_bytes() - start, 3 + 64);
+    }
+
+    #[should_panic(
+        expected = "Tried to allocate 1024 bytes aligned at 8 (wasted 7 padding bytes) with only 1023 remaining."
+    )]
+    #[test]
+    fn alloc_slice_overflow() {
+        let alloc = LinearAllocator::new(1024);
+        let _ = alloc.alloc_internal(0u8);
+        let _ = alloc.alloc_slice(128, |_| 0u64);
+    }
+
+    #[test]
+    fn is_last() {
+        let alloc = LinearAllocator::new(1024);
+
+        let a = alloc.alloc_internal(0xCAFEBABEu32);
+        assert!(alloc.is_last(a));
+        let b = alloc.alloc_internal(0u64);
+        assert!(!alloc.is_last(a));
+        assert!(alloc.is_last(b));
+        // Back allocations don't affect the front
+        let _ = alloc.alloc_internal_back(0u8);
+        assert!(alloc.is_last(b));
+    }
+
+    #[test]
+    fn is_last_grow_down() {
+        let alloc = LinearAllocator::with_direction(1024, Direction::Down);
+
+        let a = alloc.alloc_internal(0xCAFEBABEu32);
+        assert!(alloc.is_last(a));
+        let b = alloc.alloc_internal(0u64);
+        assert!(!alloc.is_last(a));
+        assert!(alloc.is_last(b));
+        let _ = alloc.alloc_internal(());
+        assert!(alloc.is_last(b));
+    }
+
+    #[test]
+    fn alloc_str() {
+        let alloc = LinearAllocator::new(1024);
+
+        let a = alloc.alloc_str("Hello");
+        assert_eq!(a, "Hello");
+        assert_eq!(a.as_ptr(), alloc.block_start);
+        assert_eq!(alloc.used_bytes(), 5);
+
+        let b = alloc.alloc_str("");
+        assert_eq!(b, "");
+        assert_eq!(alloc.used_bytes(), 5);
+    }
+
+    #[test]
+    fn alloc_bytes_zeroed() {
+        let alloc = LinearAllocator::new(1024);
+
+        let _ = alloc.alloc_internal(0xABu8);
+        // Dirty the memory so that the zeros don't come from the heap
+        let dirty = alloc.alloc_internal([0xFFu8; 64]);
+        let start = dirty.as_mut_ptr();
+        unsafe { alloc.rewind(start) };
+
+        let a = alloc.alloc_bytes_zeroed(64);
+        assert_eq!(a.as_ptr(), start as *const u8);
+        assert!(a.iter().all(|&b| b == 0));
+        assert_eq!(alloc.used_bytes(), 65);
+
+        let b = alloc.alloc_bytes_zeroed(0);
+        assert!(b.is_empty());
+        assert_eq!(alloc.used_bytes(), 65);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    fn poison() {
+        let mut alloc = LinearAllocator::new(1024);
+
+        let _ = alloc.alloc_internal(0xABu8);
+        let target = alloc.peek();
+        let a = alloc.alloc_internal(0xCAFEBABEu32) as *mut u32 as *const u8;
+        let end = alloc.peek();
+        unsafe { alloc.rewind(target) };
+        let rewound =
+            unsafe { std::slice::from_raw_parts(target, end.offset_from(target) as usize) };
+        assert!(rewound.iter().all(|&b| b == POISON_BYTE));
+        assert_eq!(unsafe { *alloc.block_start }, 0xABu8);
+        assert_eq!(unsafe { *a }, POISON_BYTE);
+
+        let _ = alloc.alloc_internal(0u64);
+        let b = alloc.alloc_internal_back(0u32) as *mut u32 as *const u8;
+        alloc.reset();
+        assert_eq!(unsafe { *alloc.block_start }, POISON_BYTE);
+        assert_eq!(unsafe { *b }, POISON_BYTE);
+    }
+
+    #[test]
+    fn remaining_slice() {
+        let mut alloc = LinearAllocator::new(64);
+
+        let _ = alloc.alloc_internal(0u8);
+        let tail = alloc.remaining_slice();
+        assert_eq!(tail.len(), 63);
+        for (i, b) in tail.iter_mut().take(5).enumerate() {
+            b.write(i as u8);
+        }
+        let written = tail.as_ptr() as *const u8;
+        alloc.commit(5);
+        assert_eq!(alloc.used_bytes(), 6);
+        assert_eq!(
+            unsafe { std::slice::from_raw_parts(written, 5) },
+            &[0, 1, 2, 3, 4]
+        );
+
+        let a = alloc.alloc_internal(0xABu8);
+        assert_eq!(a as *const u8, unsafe { written.add(5) });
+
+        let _ = alloc.alloc_internal_back(0u32);
+        assert_eq!(alloc.remaining_slice().len(), 53);
+    }
+
+    #[should_panic(expected = "Tried to commit 65 bytes with only 64 remaining.")]
+    #[test]
+    fn commit_overflow() {
+        let mut alloc = LinearAllocator::new(64);
+        alloc.commit(65);
+    }
+
+    #[test]
+    fn fits() {
+        let alloc = LinearAllocator::new(64);
+
+        let _ = alloc.alloc_internal(0u8);
+        assert!(alloc.fits::<[u8; 63]>());
+        assert!(!alloc.fits::<[u8; 64]>());
+        // Alignment padding is taken into account
+        assert!(alloc.fits::<[u32; 15]>());
+        assert!(!alloc.fits::<[u32; 16]>());
+        assert!(!alloc.fits_layout(Layout::from_size_align(60, 8).unwrap()));
+        assert!(alloc.fits_layout(Layout::from_size_align(56, 8).unwrap()));
+        assert!(!alloc.fits_layout(Layout::from_size_align(isize::MAX as usize, 1).unwrap()));
+        assert!(alloc.fits::<()>());
+
+        let _ = alloc.alloc_internal([0u32; 15]);
+        assert!(!alloc.fits::<u8>());
+        assert!(alloc.try_alloc_internal(0u8).is_err());
+    }
+
+    // Close to the largest type size rustc accepts on 64-bit targets
+    const HUGE: usize = 1 << 40;
+
+    #[test]
+    fn oom_handler() {
+        use std::{
+            panic,
+            sync::{Arc, Mutex},
+        };
+
+        let called = Arc::new(Mutex::new(None));
+        let alloc = LinearAllocator::new(64);
+        let _ = alloc.alloc_internal([0u8; 60]);
+        let handler_called = called.clone();
+        alloc.set_oom_handler(move |size, align, remaining| {
+            *handler_called.lock().unwrap() = Some((size, align, remaining))
+        });
+
+        // Fallible allocations don't call the handler
+        assert!(alloc.try_alloc_internal(0u64).is_err());
+        assert_eq!(*called.lock().unwrap(), None);
+
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            let _ = alloc.alloc_internal(0u64);
+        }));
+        assert!(result.is_err());
+        assert_eq!(*called.lock().unwrap(), Some((8, 8, 4)));
+    }
+
+    #[test]
+    fn too_large() {
+        let alloc = LinearAllocator::new(1024);
+
+        // A single object of the type merely doesn't fit
+        assert!(matches!(
+            alloc.try_alloc_slice_uninit::<[u8; HUGE]>(1),
+            Err(AllocationError::OutOfMemory { .. })
+        ));
+        assert_eq!(
+            alloc
+                .try_alloc_slice_uninit::<[u8; HUGE]>(1 << 22)
+                .unwrap_err(),
+            AllocationError::TooLarge {
+                size_bytes: 1 << 62,
+                alignment: 1
+            }
+        );
+        // Sizes that overflow are saturated
+        assert_eq!(
+            alloc
+                .try_alloc_slice_uninit::<u64>(usize::MAX / 4)
+                .unwrap_err(),
+            AllocationError::TooLarge {
+                size_bytes: usize::MAX,
+                alignment: 8
+            }
+        );
+        assert_eq!(alloc.used_bytes(), 0);
+    }
+
+    #[should_panic(expected = "which is too large for any allocator")]
+    #[test]
+    fn too_large_panic() {
+        let alloc = LinearAllocator::new(1024);
+        let _ = alloc.alloc_slice(1 << 22, |_| [0u8; HUGE]);
+    }
+
+    #[test]
+    fn alloc_ptr() {
+        let alloc = LinearAllocator::new(1024);
+
+        let a = alloc.alloc_ptr(0xCAFEBABEu32);
+        assert_eq!(a.as_ptr() as *mut u8, alloc.peek().wrapping_sub(4));
+        unsafe {
+            assert_eq!(a.as_ptr().read(), 0xCAFEBABEu32);
+            a.as_ptr().write(0xDEADBEEF);
+            assert_eq!(*a.as_ref(), 0xDEADBEEFu32);
+        }
+    }
+
+    #[test]
+    fn alloc_at_offset() {
+        let alloc = LinearAllocator::new(1024);
+
+        let (a, a_offset) = alloc.alloc_at_offset(0xABu8);
+        assert_eq!(a_offset, 0);
+        let (b, b_offset) = alloc.alloc_at_offset(0xCAFEBABEu32);
+        // Padded to the alignment of u32
+        assert_eq!(b_offset, 4);
+        let (_, c_offset) = alloc.alloc_at_offset(());
+        assert_eq!(c_offset, 8);
+        let (c, c_offset) = alloc.alloc_at_offset(0xDEADBEEFu64);
+        assert_eq!(c_offset, 8);
+        assert_eq!(alloc.peek_offset(), 16);
+
+        let start = alloc.as_ptr();
+        assert_eq!(start.wrapping_add(a_offset), a as *mut u8 as *const u8);
+        assert_eq!(start.wrapping_add(b_offset), b as *mut u32 as *const u8);
+        assert_eq!(start.wrapping_add(c_offset), c as *mut u64 as *const u8);
+    }
+
+    #[should_panic(
+        expected = "Tried to allocate past the scopes of an allocator held by a ScopedScratch"
+    )]
+    #[test]
+    fn alloc_at_offset_scoped() {
+        let alloc = LinearAllocator::new(1024);
+        let _scratch = crate::ScopedScratch::new(&alloc);
+        // Dropping the scope would rewind over the object
+        let _ = alloc.alloc_at_offset(0u32);
+    }
+
+    #[test]
+    fn alloc_indexed() {
+        struct Node {
+            value: u32,
+            next: Option<Index<Node>>,
+        }
+
+        let mut alloc = LinearAllocator::new(1024);
+
+        let (a, _) = alloc.alloc_indexed(Node {
+            value: 1,
+            next: None,
+        });
+        let (b, _) = alloc.alloc_indexed(Node {
+            value: 2,
+            next: Some(a),
+        });
+        assert_ne!(a, b);
+        unsafe { alloc.get_mut(a) }.next = Some(b);
+
+        // Walk the cycle
+        let mut node = unsafe { alloc.get(a) };
+        let mut values = Vec::new();
+        for _ in 0..4 {
+            values.push(node.value);
+            node = unsafe { alloc.get(node.next.unwrap()) };
+        }
+        assert_eq!(values, [1, 2, 1, 2]);
+    }
+
+    #[should_panic(
+        expected = "Tried to allocate past the scopes of an allocator held by a ScopedScratch"
+    )]
+    #[test]
+    fn alloc_indexed_scoped() {
+        let alloc = LinearAllocator::new(1024);
+        let scratch = crate::ScopedScratch::new(&alloc);
+        let _ = scratch.alloc(0u32);
+        // Dropping the scope would rewind over the object
+        let _ = alloc.alloc_indexed(0u32);
+    }
+
+    #[should_panic(expected = "index is past the allocated objects")]
+    #[test]
+    fn get_rewound() {
+        let mut alloc = LinearAllocator::new(1024);
+        let (a, _) = alloc.alloc_indexed(0u32);
+        alloc.clear();
+        let _ = unsafe { alloc.get(a) };
+    }
+
+    #[test]
+    fn align_to() {
+        let alloc = LinearAllocator::new(4 * 4096);
+
+        let _ = alloc.alloc_internal(0u8);
+        alloc.align_to(4096);
+        assert_eq!((alloc.peek() as usize) % 4096, 0);
+        let a = alloc.alloc_layout(Layout::from_size_align(100, 1).unwrap());
+        assert_eq!((a.as_ptr() as usize) % 4096, 0);
+
+        // Already aligned
+        let peek = alloc.peek();
+        alloc.align_to(4);
+        assert_eq!(alloc.peek(), peek);
+    }
+
+    #[should_panic(expected = "Tried to allocate 0 bytes aligned at 128")]
+    #[test]
+    fn align_to_overflow() {
+        let alloc = LinearAllocator::with_alignment(64, 128);
+        let _ = alloc.alloc_internal(0u8);
+        alloc.align_to(128);
+    }
+
+    #[test]
+    fn alloc_layout() {
+        let alloc = LinearAllocator::new(1024);
+
+        let _ = alloc.alloc_internal(0u8);
+        let a = alloc.alloc_layout(Layout::from_size_align(24, 8).unwrap());
+        assert_eq!(a.len(), 24);
+        assert_eq!((a.as_ptr() as usize) % 8, 0);
+        assert_eq!(alloc.used_bytes(), 8 + 24);
+
+        let b = alloc.alloc_layout(Layout::from_size_align(0, 64).unwrap());
+        assert!(b.is_empty());
+        assert_eq!((b.as_ptr() as usize) % 64, 0);
+        assert_eq!(alloc.used_bytes(), 8 + 24);
+    }
+
+    #[should_panic(expected = "Tried to allocate 1024 bytes")]
+    #[test]
+    fn alloc_layout_overflow() {
+        let alloc = LinearAllocator::new(1024);
+        let _ = alloc.alloc_internal(0u8);
+        let _ = alloc.alloc_layout(Layout::from_size_align(1024, 1).unwrap());
+    }
+
+    #[should_panic(
+        expected = "Tried to allocate past the scopes of an allocator held by a ScopedScratch"
+    )]
+    #[test]
+    fn alloc_layout_scoped() {
+        let alloc = LinearAllocator::new(1024);
+        let _scratch = crate::ScopedScratch::new(&alloc);
+        let _ = alloc.alloc_layout(Layout::new::<u32>());
+    }
+
+    #[test]
+    fn try_reserve() {
+        let alloc = LinearAllocator::new(1024);
+
+        let _ = alloc.alloc_internal(0u8);
+        let layout = Layout::from_size_align(37, 16).unwrap();
+        let a = alloc.try_reserve(layout).unwrap();
+        assert_eq!((a.as_ptr() as usize) % 16, 0);
+        assert_eq!(alloc.peek(), a.as_ptr().wrapping_add(37));
+        assert_eq!(alloc.used_bytes(), 16 + 37);
+
+        let peek = alloc.peek();
+        assert!(alloc
+            .try_reserve(Layout::from_size_align(1024, 1).unwrap())
+            .is_none());
+        assert!(alloc
+            .try_reserve(Layout::from_size_align(isize::MAX as usize - 1, 1).unwrap())
+            .is_none());
+        assert_eq!(alloc.peek(), peek);
+    }
+
+    #[test]
+    fn rewind_to() {
+        let alloc = LinearAllocator::new(1024);
+
+        let _ = alloc.alloc_internal(0u8);
+        let marker = alloc.mark();
+        for i in 0..4 {
+            let _ = alloc.alloc_internal(i as u64);
+            assert_ne!(alloc.mark(), marker);
+            unsafe { alloc.rewind_to(marker) };
+            assert_eq!(alloc.mark(), marker);
+            assert_eq!(alloc.used_bytes(), 1);
+        }
+    }
+
+    #[should_panic(expected = "alloc doesn't belong to this allocator")]
+    #[test]
+    fn rewind_to_other() {
+        let alloc = LinearAllocator::new(1024);
+        let other = LinearAllocator::new(1024);
+        unsafe { alloc.rewind_to(other.mark()) };
+    }
+
+    #[test]
+    fn rewind_with() {
+        let alloc = LinearAllocator::new(1024);
+
+        let _ = alloc.alloc_internal(0u8);
+        let target = alloc.peek();
+        let _ = alloc.alloc_internal(0xCAFEBABEu32);
+        let _ = alloc.alloc_internal(0xDEADBEEFu64);
+
+        let mut region_len = 0;
+        unsafe {
+            alloc.rewind_with(target, |region| {
+                assert_eq!(region.as_mut_ptr() as *mut u8, target);
+                region.fill(MaybeUninit::new(0));
+                region_len = region.len();
+                assert!(region.iter().all(|b| b.assume_init() == 0));
+            })
+        };
+        // Includes the padding before both allocations
+        assert_eq!(region_len, 3 + 4 + 8);
+        assert_eq!(alloc.peek(), target);
+
+        // An empty region at the start of the free block
+        unsafe { alloc.rewind_with(target, |region| assert!(region.is_empty())) };
+    }
+
+    #[test]
+    fn rewind_offset() {
+        let alloc = LinearAllocator::new(1024);
+
+        let _ = alloc.alloc_internal(0u8);
+        let target = alloc.peek_offset();
+        assert_eq!(target, 1);
+        let _ = alloc.alloc_internal(0u64);
+        assert_ne!(alloc.peek_offset(), target);
+        unsafe { alloc.rewind_offset(target) };
+        assert_eq!(alloc.peek_offset(), target);
+        assert_eq!(alloc.next_alloc.get(), unsafe { alloc.block_start.add(1) });
+    }
+
+    #[test]
+    fn rewind_offset_end() {
+        let alloc = LinearAllocator::new(1024);
+        unsafe { alloc.rewind_offset(1024) };
+        assert_eq!(alloc.peek_offset(), 1024);
+    }
+
+    #[should_panic(expected = "offset doesn't belong to this allocator")]
+    #[test]
+    fn rewind_offset_assert_above() {
+        let alloc = LinearAllocator::new(1024);
+        unsafe { alloc.rewind_offset(1025) }
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn allocations_since() {
+        let alloc = LinearAllocator::new(1024);
+        assert_eq!(alloc.allocation_count(), 0);
+
+        let _ = alloc.alloc_internal(0u8);
+        let marker = alloc.allocation_count();
+        assert_eq!(marker, 1);
+
+        let _ = alloc.alloc_internal(0u32);
+        let _ = alloc.alloc_internal(0u64);
+        assert!(alloc.try_alloc_internal([0u8; 1024]).is_err());
+        assert_eq!(alloc.allocations_since(marker), 2);
+        assert_eq!(alloc.allocation_count(), 3);
+
+        // Rewinding doesn't undo the count
+        unsafe { alloc.rewind_offset(0) };
+        assert_eq!(alloc.allocations_since(marker), 2);
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn stats() {
+        let alloc = LinearAllocator::new(1024);
+
+        let _ = alloc.alloc_internal(0u8);
+        let _ = alloc.alloc_internal(0u64);
+        let _ = alloc.alloc_internal_back(0u32);
+        let stats = alloc.stats();
+        assert_eq!(stats.capacity, 1024);
+        assert_eq!(stats.used, 16 + 4);
+        assert_eq!(stats.used + stats.remaining, stats.capacity);
+        assert_eq!(stats.high_water, stats.used);
+        assert_eq!(stats.alloc_count, 3);
+        assert_eq!(stats.wasted_padding, 7);
+
+        unsafe { alloc.rewind_offset(0) };
+        let stats = alloc.stats();
+        assert_eq!(stats.used, 4);
+        assert_eq!(stats.used + stats.remaining, stats.capacity);
+        assert_eq!(stats.high_water, 16 + 4);
+        assert_eq!(stats.alloc_count, 3);
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn wasted_padding() {
+        let alloc = LinearAllocator::new(1024);
+
+        for _ in 0..4 {
+            let _ = alloc.alloc_internal(0u8);
+            let _ = alloc.alloc_internal(0u64);
+        }
+        assert_eq!(alloc.wasted_padding(), 4 * 7);
+        assert_eq!(alloc.used_bytes(), 4 * 16);
+
+        // Grouped by alignment, the same allocations don't need padding
+        unsafe { alloc.rewind_offset(0) };
+        for _ in 0..4 {
+            let _ = alloc.alloc_internal(0u64);
+        }
+        for _ in 0..4 {
+            let _ = alloc.alloc_internal(0u8);
+        }
+        assert_eq!(alloc.wasted_padding(), 4 * 7);
+        assert_eq!(alloc.used_bytes(), 4 * 8 + 4);
+
+        // Padding between back allocations counts too
+        let _ = alloc.alloc_internal_back(0u8);
+        let _ = alloc.alloc_internal_back(0u32);
+        assert_eq!(alloc.wasted_padding(), 4 * 7 + 3);
+    }
+
+    #[cfg(feature = "track_backtraces")]
+    #[test]
+    fn take_allocations() {
+        let alloc = LinearAllocator::new(1024);
+
+        let _ = alloc.alloc_internal(0u8);
+        let target = alloc.peek();
+        let _ = alloc.alloc_internal(0u32);
+        let _ = alloc.alloc_internal_back(0u64);
+        let allocations = alloc.take_allocations();
+        assert_eq!(allocations.len(), 3);
+        assert_eq!(allocations[0].0, 0);
+        assert_eq!(allocations[1].0, 4);
+        assert_eq!(allocations[2].0, 1024 - 8);
+        assert!(alloc.take_allocations().is_empty());
+
+        let _ = alloc.alloc_internal(0u32);
+        let _ = alloc.alloc_internal(0u32);
+        unsafe { alloc.rewind(target) };
+        // Only the rewound allocations are forgotten
+        let _ = alloc.alloc_internal(0u16);
+        let allocations = alloc.take_allocations();
+        assert_eq!(allocations.len(), 1);
+        assert_eq!(allocations[0].0, 2);
+    }
+
+    #[test]
+    fn to_vec() {
+        struct Leaf {
+            value: u32,
+        }
+        struct Root {
+            value: u32,
+            leaf: Index<Leaf>,
+        }
+
+        let alloc = LinearAllocator::new(1024);
+        let (leaf, _) = alloc.alloc_indexed(Leaf { value: 1 });
+        let (root, _) = alloc.alloc_indexed(Root { value: 2, leaf });
 
-impl LinearAllocator {
-    pub fn new(size_bytes: usize) -> Self {
-        assert_ne!(size_bytes, 0, "Cannot create an allocator with size 0");
-        // Limit so that we can assume allocation arithmetic can never overflow
-        assert!(size_bytes < isize::MAX as usize);
+        // The objects are laid out without padding
+        let bytes = unsafe { alloc.to_vec() };
+        assert_eq!(bytes.len(), 4 + 8);
 
-        const ALIGN: usize = L1_CACHE_LINE_SIZE;
-        // align shouldn't be 0
-        const_assert_ne!(ALIGN, 0);
-        // align should be a power of two
-        const_assert_eq!(ALIGN & (ALIGN - 1), 0);
-        // Since we check align ourselves, this should only fail on overflow.
-        let layout =
-            Layout::from_size_align(size_bytes, ALIGN).expect("Failed to create memory layout");
+        let loaded = LinearAllocator::from_bytes(&bytes);
+        assert_eq!(loaded.used_bytes(), bytes.len());
+        assert_eq!(loaded.capacity(), bytes.len());
+        let root = unsafe { loaded.get(root) };
+        assert_eq!(root.value, 2);
+        assert_eq!(unsafe { loaded.get(root.leaf) }.value, 1);
+        assert!(loaded.try_alloc_internal(0u8).is_err());
+    }
 
-        // Safety:
-        // - layout has a non-zero size since size_bytes is not 0 and its construction succeeded
-        let block_start = unsafe { std::alloc::alloc(layout) };
+    #[test]
+    fn clear() {
+        let mut alloc = LinearAllocator::new(1024);
 
-        if block_start.is_null() {
-            std::alloc::handle_alloc_error(layout);
+        for _ in 0..4 {
+            let _ = alloc.alloc_internal(0u8);
+            let _ = alloc.alloc_internal(0u64);
         }
+        let _ = alloc.alloc_internal_back(0u32);
+        assert_eq!(alloc.high_water_mark(), 4 * 16 + 4);
 
-        Self {
-            block_start,
-            layout,
-            size_bytes,
-            next_alloc: Cell::new(block_start),
+        alloc.clear();
+        assert_eq!(alloc.used_bytes(), 0);
+        assert_eq!(alloc.high_water_mark(), 0);
+        #[cfg(feature = "stats")]
+        {
+            let stats = alloc.stats();
+            assert_eq!(stats.used, 0);
+            assert_eq!(stats.high_water, 0);
+            assert_eq!(stats.alloc_count, 0);
+            assert_eq!(stats.wasted_padding, 0);
         }
     }
-}
 
-impl Drop for LinearAllocator {
-    fn drop(&mut self) {
-        // Safety:
-        //  - self.block_start was allocated using the same allocator in new()
-        //  - self.layout is the layout it was allocated with
-        unsafe {
-            std::alloc::dealloc(self.block_start, self.layout);
-        }
+    #[test]
+    fn reset() {
+        let mut alloc = LinearAllocator::new(1024);
+
+        let _ = alloc.alloc_internal(0u8);
+        let _ = alloc.alloc_internal(0u64);
+        assert_ne!(alloc.used_bytes(), 0);
+        alloc.reset();
+        assert_eq!(alloc.used_bytes(), 0);
+
+        let a = alloc.alloc_internal(0xCAFEBABEu32);
+        assert_eq!(a as *const u32 as *const u8, alloc.block_start);
     }
-}
 
-// This interface is not exposed outside the library with the goal of being safe all around
-pub trait LinearAllocatorInternal {
-    // Interior mutability required by interface
-    // The references will be to non-overlapping memory as long as [rewind()] is not misused.
-    #[allow(clippy::mut_from_ref)]
-    /// Allocates and initializes `obj`
-    fn alloc_internal<T: Sized>(&self, obj: T) -> &mut T;
+    #[test]
+    fn debug() {
+        let alloc = LinearAllocator::with_alignment(1024, 128);
+        let _ = alloc.alloc_internal(0u64);
 
-    /// Rewinds the allocator back to `alloc`.
-    /// # Safety
-    ///  - `alloc` has to be a pointer to an allocation from [alloc_internal()]
-    ///     or a pointer returned by [peek()].
-    ///  - Caller is responsible for calling drop on objects returned by
-    ///    [alloc_internal()] that will be rewound over, if they don't implement Copy
-    ///  - Caller also needs to ensure that any references held to the rewound
-    ///    objects are dropped
-    unsafe fn rewind(&self, alloc: *mut u8);
+        let s = format!("{:?}", alloc);
+        assert!(s.starts_with("LinearAllocator { block_start: 0x"));
+        assert!(
+            s.ends_with("size_bytes: 1024, used_bytes: 8, remaining_bytes: 1016, alignment: 128 }")
+        );
+    }
 
-    /// Returns the pointer to the start of the free block
-    fn peek(&self) -> *mut u8;
-}
+    #[test]
+    fn usage() {
+        let alloc = LinearAllocator::new(1024);
+        assert_eq!(alloc.capacity(), 1024);
+        assert_eq!(alloc.used_bytes(), 0);
+        assert_eq!(alloc.remaining_bytes(), 1024);
 
-impl LinearAllocatorInternal for LinearAllocator {
-    #[allow(clippy::mut_from_ref)]
-    fn alloc_internal<T: Sized>(&self, obj: T) -> &mut T {
-        let size_bytes = std::mem::size_of::<T>();
-        let alignment = std::mem::align_of::<T>();
-        // Make sure new_size never overflows
-        // size is always a multiple of alignment
-        assert!(size_bytes < (isize::MAX / 2) as usize);
+        let _ = alloc.alloc_internal(0u8);
+        let _ = alloc.alloc_internal(0u32);
+        assert_eq!(alloc.capacity(), 1024);
+        assert_eq!(alloc.used_bytes(), 8);
+        assert_eq!(alloc.remaining_bytes(), 1016);
 
-        let next_alloc = self.next_alloc.get();
-        let align_offset = next_alloc.align_offset(alignment);
-        assert_ne!(align_offset, usize::MAX);
+        let _ = alloc.alloc_internal([0u8; 1016]);
+        assert_eq!(alloc.used_bytes(), 1024);
+        assert_eq!(alloc.remaining_bytes(), 0);
+    }
 
-        // Safety:
-        // - self.block_start is at the start of the allocation and next_alloc
-        //   has been verified to be within the allocation (or one byte past it)
-        //   either by alloc_internal() or rewind()
-        // - We assume next_alloc is derived from self.block_start because it's either
-        //   - the same as self.block_start
-        //   - derived from a previous self.next_alloc
-        //   - from rewind() that has safety rules expecting the input to be
-        //     - from peek()
-        //       - some previous self.next_alloc
-        //     - pointer to an object from alloc_internal()
-        //       - derived from some previous self.next_alloc
-        // - Distance between two *mut u8 is always a multiple of u8
-        // - Maximum held block size is under isize::MAX so distances within it can't overflow isize
-        // - Rust allocations never wrap around the address space
-        let previous_size = unsafe { next_alloc.offset_from(self.block_start) as usize };
+    #[test]
+    fn high_water_mark() {
+        let mut alloc = LinearAllocator::new(1024);
+        assert_eq!(alloc.high_water_mark(), 0);
 
-        // The asserts above make sure this can't overflow since
-        // previous_size <= self.size_bytes < isize::MAX
-        let new_size = previous_size + align_offset + size_bytes;
-        if new_size > self.size_bytes {
-            let remaining_bytes = self.size_bytes - previous_size;
-            panic!(
-                "Tried to allocate {} bytes aligned at {} with only {} remaining.",
-                size_bytes, alignment, remaining_bytes
-            );
-        }
+        let _ = alloc.alloc_internal(0u8);
+        let target = alloc.peek();
+        let _ = alloc.alloc_internal([0u8; 100]);
+        assert_eq!(alloc.high_water_mark(), 101);
 
-        // Safety:
-        // - self.next_alloc has been verified to be within the allocation either
-        //   by alloc_internal() or rewind(), and we just verified that the aligned
-        //   object fits the allocation
-        // - Maximum held block size is under isize::MAX so offsets within it can't overflow isize
-        // - Rust allocations never wrap around the address space
-        let new_alloc = unsafe {
-            let new_alloc = self.next_alloc.get().add(align_offset);
-            self.next_alloc.replace(new_alloc.add(size_bytes));
-            new_alloc
-        };
+        unsafe { alloc.rewind(target) };
+        assert_eq!(alloc.high_water_mark(), 101);
+        let _ = alloc.alloc_internal(0u32);
+        assert_eq!(alloc.used_bytes(), 8);
+        assert_eq!(alloc.high_water_mark(), 101);
 
-        // Safety:
-        // - new_alloc is a pointer to at least size_of::<T>() bytes of the block
-        //   from self.block_start and this allocator can't shared between threads
-        // - We aligned new_alloc for T
-        unsafe {
-            let t_ptr = new_alloc as *mut T;
-            t_ptr.write(obj);
-            &mut *t_ptr
-        }
+        alloc.reset();
+        assert_eq!(alloc.high_water_mark(), 101);
+        let _ = alloc.alloc_internal([0u8; 200]);
+        assert_eq!(alloc.high_water_mark(), 200);
     }
 
-    unsafe fn rewind(&self, alloc: *mut u8) {
-        // Let's be nice and catch the obvious error
-        // Reference lifetimes and allocated structs needing Drop are truly the
-        // responsibility of the caller
-        debug_assert!(
-            (alloc as usize) >= (self.block_start as usize)
-                && (alloc as usize) < (self.block_start as usize) + self.size_bytes,
-            "alloc doesn't belong to this allocator"
-        );
-        self.next_alloc.replace(alloc);
-    }
+    #[cfg(feature = "allocator_api")]
+    #[test]
+    fn allocator_api() {
+        let alloc = LinearAllocator::new(1024);
 
-    fn peek(&self) -> *mut u8 {
-        self.next_alloc.get()
-    }
-}
+        let _ = alloc.alloc_internal(0u8);
+        let target = alloc.peek();
+        {
+            let mut v: Vec<u64, &LinearAllocator> = Vec::with_capacity_in(4, &alloc);
+            v.extend(0..4);
+            assert_eq!(v.as_ptr() as *mut u8, unsafe { target.add(7) });
+            // Growing copies to a new allocation
+            v.extend(4..16);
+            assert_eq!(v.iter().sum::<u64>(), 120);
+        }
+        // Only the last allocation is reclaimed
+        assert_ne!(alloc.peek(), target);
 
-#[cfg(test)]
-mod tests {
+        let peek = alloc.peek();
+        let b = Box::new_in(0xCAFEBABEu32, &alloc);
+        assert_eq!(*b, 0xCAFEBABEu32);
+        drop(b);
+        assert_eq!(alloc.peek(), peek);
 
-    use super::*;
-    use std::mem::{align_of, drop, size_of};
+        // A root scope would rewind over the allocations
+        alloc.acquire_scope();
+        assert!(Box::try_new_in(0u32, &alloc).is_err());
+        alloc.release_scope();
+    }
 
     #[test]
-    fn alloc_u8() {
+    fn remaining_aligned() {
         let alloc = LinearAllocator::new(1024);
+        assert_eq!(alloc.remaining_aligned(1), 1024);
+        assert_eq!(alloc.remaining_aligned(64), 1024);
 
-        let a = alloc.alloc_internal(0xABu8);
-        assert_eq!(*a, 0xABu8);
-        assert_eq!(a as *const u8, alloc.block_start);
-        assert_eq!(
-            unsafe { alloc.next_alloc.get().offset_from(alloc.block_start) },
-            size_of::<u8>() as isize
-        );
+        // Block start is aligned at 64 so the next allocation is at offset 1000
+        let _ = alloc.alloc_internal([0u8; 1000]);
+        assert_eq!(alloc.remaining_aligned(1), 24);
+        assert_eq!(alloc.remaining_aligned(16), 16);
+        assert_eq!(alloc.remaining_aligned(64), 0);
+
+        let _ = alloc.alloc_internal([0u8; 20]);
+        assert_eq!(alloc.remaining_aligned(1), 4);
+        assert_eq!(alloc.remaining_aligned(8), 0);
+        assert_eq!(alloc.remaining_aligned(64), 0);
     }
 
+    #[should_panic(expected = "align has to be a power of two")]
     #[test]
-    fn alloc_pod() {
+    fn remaining_aligned_npot() {
         let alloc = LinearAllocator::new(1024);
+        let _ = alloc.remaining_aligned(3);
+    }
 
-        #[allow(dead_code)]
-        struct A {
-            data: u32,
+    #[test]
+    fn with_alignment() {
+        for align in [1, 16, 128, 4096] {
+            let alloc = LinearAllocator::with_alignment(1024, align);
+            assert_eq!((alloc.block_start as usize) % align, 0);
+            assert_eq!(alloc.capacity(), 1024);
         }
+    }
+
+    #[should_panic(expected = "Alignment has to be a non-zero power of two")]
+    #[test]
+    fn with_alignment_npot() {
+        let _ = LinearAllocator::with_alignment(1024, 48);
+    }
+
+    #[should_panic(expected = "Alignment has to be a non-zero power of two")]
+    #[test]
+    fn with_alignment_zero() {
+        let _ = LinearAllocator::with_alignment(1024, 0);
+    }
+
+    #[test]
+    fn try_new() {
+        let alloc = LinearAllocator::try_new(1024).unwrap();
+        assert_eq!(alloc.capacity(), 1024);
+        assert_eq!((alloc.block_start as usize) % L1_CACHE_LINE_SIZE, 0);
 
-        let a = alloc.alloc_internal(A { data: 0xDEADC0DE });
-        assert_eq!(a.data, 0xDEADC0DE);
-        assert_eq!(a as *const A as *const u8, alloc.block_start);
         assert_eq!(
-            unsafe { alloc.next_alloc.get().offset_from(alloc.block_start) },
-            size_of::<A>() as isize
+            LinearAllocator::try_new(0).unwrap_err().to_string(),
+            "Cannot create an allocator with size 0 aligned at 64"
         );
+        assert!(matches!(
+            LinearAllocator::try_new(isize::MAX as usize),
+            Err(AllocationError::InvalidLayout { .. })
+        ));
+        // Valid size but too large to allocate
+        assert!(matches!(
+            LinearAllocator::try_new(isize::MAX as usize - 1024),
+            Err(AllocationError::AllocationFailed { .. })
+        ));
     }
 
     #[test]
-    fn alloc_drop() {
-        let alloc = LinearAllocator::new(1024);
-
-        #[allow(dead_code)]
-        struct A {
-            data: Vec<u32>,
-        }
+    fn from_vec() {
+        let buf = Vec::<u8>::with_capacity(1024);
+        let buf_ptr = buf.as_ptr();
+        let buf_capacity = buf.capacity();
 
-        let a = alloc.alloc_internal(A {
-            data: vec![0xC0FFEEEE],
-        });
-        assert_eq!(a.data.len(), 1);
-        assert_eq!(a.data[0], 0xC0FFEEEE);
-        assert_eq!(a as *const A as *const u8, alloc.block_start);
+        let alloc = LinearAllocator::from_vec(buf);
+        assert_eq!((alloc.block_start as usize) % L1_CACHE_LINE_SIZE, 0);
         assert_eq!(
-            unsafe { alloc.next_alloc.get().offset_from(alloc.block_start) },
-            size_of::<A>() as isize
+            unsafe { alloc.block_start.offset_from(buf_ptr) } as usize + alloc.size_bytes,
+            buf_capacity
         );
 
-        drop(a);
+        let a = alloc.alloc_internal(0xCAFEBABEu32);
+        assert_eq!(*a, 0xCAFEBABEu32);
+        assert_eq!(a as *const u32 as *const u8, alloc.block_start);
+
+        let buf = alloc.into_vec().ok().unwrap();
+        assert_eq!(buf.as_ptr(), buf_ptr);
+        assert_eq!(buf.capacity(), buf_capacity);
+        assert!(buf.is_empty());
     }
 
     #[test]
-    fn two_allocs() {
+    fn into_vec_heap() {
         let alloc = LinearAllocator::new(1024);
+        assert!(alloc.into_vec().is_err());
+    }
 
+    #[cfg(all(feature = "lock_pages", unix))]
+    #[ignore = "mlock fails without a large enough RLIMIT_MEMLOCK"]
+    #[test]
+    fn with_locked_pages() {
+        let alloc = LinearAllocator::with_locked_pages(4096).unwrap();
         let a = alloc.alloc_internal(0xCAFEBABEu32);
-        let b = alloc.alloc_internal(0xDEADCAFEu32);
-        let a_ptr = a as *const u32;
-        let b_ptr = b as *const u32;
         assert_eq!(*a, 0xCAFEBABEu32);
-        assert_eq!(*b, 0xDEADCAFEu32);
-        assert_eq!(unsafe { b_ptr.offset_from(a_ptr) }, 1);
+        assert!(alloc.into_vec().is_err());
+    }
+
+    #[should_panic(expected = "Vec with capacity 0 has no room after aligning to 64")]
+    #[test]
+    fn from_vec_empty() {
+        let _ = LinearAllocator::from_vec(Vec::new());
+    }
+
+    #[test]
+    fn from_buffer() {
+        let mut buf = [0u8; 1024];
+        let buf_ptr = buf.as_mut_ptr();
+        {
+            let alloc = LinearAllocator::from_buffer(&mut buf);
+            assert_eq!(alloc.block_start, buf_ptr);
+            assert_eq!(alloc.capacity(), 1024);
+
+            let a = alloc.alloc_internal(0xABu8);
+            assert_eq!(a as *const u8, buf_ptr);
+            let b = alloc.alloc_internal(0xCAFEBABEu32);
+            assert_eq!(*b, 0xCAFEBABEu32);
+            assert!(alloc.contains(b as *const u32 as *const u8));
+            assert!(alloc.used_bytes() >= 5);
+
+            assert!(alloc.try_alloc_internal([0u8; 1024]).is_err());
+        }
+        // The buffer is still usable after the allocator is gone
+        assert_eq!(buf[0], 0xAB);
+    }
+
+    #[test]
+    fn grow_down() {
+        let mut alloc = LinearAllocator::with_direction(1024, Direction::Down);
+        let end = alloc.block_start.wrapping_add(1024);
+        assert_eq!(alloc.peek(), end);
+
+        // The first allocation lands at the top of the block
+        let a = alloc.alloc_internal(0xCAFEBABEu32);
+        assert_eq!(*a, 0xCAFEBABEu32);
+        assert_eq!(a as *mut u32 as *mut u8, end.wrapping_sub(4));
+        let start = alloc.peek();
+
+        // Aligned down below the previous allocation
+        let b = alloc.alloc_internal(0xABu8);
+        let c = alloc.alloc_internal(0xDEADBEEFu32);
+        assert_eq!(b as *mut u8, end.wrapping_sub(5));
+        assert_eq!(c as *mut u32 as *mut u8, end.wrapping_sub(12));
+        assert_eq!(alloc.used_bytes(), 12);
+        assert_eq!(alloc.bytes_since(start), 8);
+
+        unsafe { alloc.rewind(start) };
+        assert_eq!(alloc.peek(), start);
+        assert_eq!(alloc.used_bytes(), 4);
+
+        let _ = alloc.alloc_mut(0u64);
+        assert_eq!(alloc.used_bytes(), 16);
+        alloc.reset();
+        assert_eq!(alloc.peek(), end);
+        assert_eq!(alloc.used_bytes(), 0);
+    }
+
+    #[test]
+    fn grow_down_scoped() {
+        let alloc = LinearAllocator::with_direction(1024, Direction::Down);
+        {
+            let scratch = crate::ScopedScratch::new(&alloc);
+            let a = scratch.alloc(String::from("dropped"));
+            {
+                let scratch2 = scratch.new_scope();
+                let _ = scratch2.alloc_slice(4, |i| i.to_string());
+                assert!(scratch2.bytes_used() > 0);
+            }
+            assert_eq!(a, "dropped");
+        }
+        alloc.assert_empty();
+    }
+
+    #[should_panic(expected = "Not supported by allocators that grow down")]
+    #[test]
+    fn grow_down_offset() {
+        let alloc = LinearAllocator::with_direction(1024, Direction::Down);
+        let _ = alloc.alloc_indexed(0u32);
+    }
+
+    #[test]
+    fn prefault() {
+        let mut alloc = LinearAllocator::new(3 * PREFAULT_STRIDE + 1);
+        let _ = alloc.alloc_mut(0xCAFEBABEu32);
+        for byte in alloc.remaining_slice() {
+            byte.write(0xFF);
+        }
+
+        alloc.prefault();
+        assert_eq!(alloc.used_bytes(), 4);
+        // A byte is written on each page of the free block
+        let free = alloc.remaining_slice();
+        for (i, byte) in free.iter().enumerate() {
+            let expected = if i % PREFAULT_STRIDE == 0 { 0 } else { 0xFF };
+            assert_eq!(unsafe { byte.assume_init() }, expected);
+        }
+        // The allocation is left untouched
         assert_eq!(
-            unsafe { alloc.next_alloc.get().offset_from(alloc.block_start) },
-            size_of::<u32>() as isize * 2
+            unsafe { alloc.used_bytes_slice() },
+            0xCAFEBABEu32.to_ne_bytes()
         );
     }
 
-    #[should_panic(
-        expected = "Tried to allocate 1025 bytes aligned at 1 with only 1024 remaining."
-    )]
+    #[should_panic(expected = "Not supported by allocators that grow down")]
     #[test]
-    fn overflow_first() {
-        let alloc = LinearAllocator::new(1024);
-        let _ = alloc.alloc_internal([0u8; 1025]);
+    fn grow_down_back() {
+        let alloc = LinearAllocator::with_direction(1024, Direction::Down);
+        let _scratch = crate::ScopedScratch::new(&alloc);
+        // Dropping the scope would rewind over the object
+        let _ = alloc.alloc_internal_back(0u32);
     }
 
-    #[should_panic(expected = "Tried to allocate 1000 bytes aligned at 4 with only 768 remaining.")]
+    #[should_panic(expected = "Not supported by allocators that grow down")]
     #[test]
-    fn overflow_second() {
-        let alloc = LinearAllocator::new(1024);
-        let _ = alloc.alloc_internal([0u8; 256]);
-        let _ = alloc.alloc_internal([0u32; 250]);
+    fn grow_down_freeze() {
+        let alloc = LinearAllocator::with_direction(1024, Direction::Down);
+        let _ = alloc.alloc_internal(0u32);
+        let _ = alloc.freeze();
     }
 
     #[test]
-    fn different_alignment() {
-        let alloc = LinearAllocator::new(1024);
+    fn split() {
+        let mut alloc = LinearAllocator::new(1024);
+        let _ = alloc.alloc_internal(0u32);
+        {
+            let arenas = alloc.split(&[64, 64, 128]);
+            assert_eq!(arenas.len(), 3);
+            assert_eq!(arenas[2].capacity(), 128);
 
-        #[allow(dead_code)]
-        #[repr(C)]
-        struct A {
-            data: u8,
-        }
-        #[allow(dead_code)]
-        #[repr(C)]
-        struct B {
-            data: u64,
+            // Filling one sub-arena leaves the others untouched
+            let _ = arenas[0].alloc_internal([0xABu8; 64]);
+            assert!(arenas[0].try_alloc_internal(0u8).is_err());
+            let a = arenas[1].alloc_internal(0xCAFEBABEu32);
+            let b = arenas[2].alloc_internal([0xCDu8; 128]);
+            assert_eq!(*a, 0xCAFEBABEu32);
+            assert_eq!(b[127], 0xCD);
+            assert!(!arenas[0].contains(a as *const u32 as *const u8));
+            assert!(arenas[1].contains(a as *const u32 as *const u8));
         }
-        assert_ne!(size_of::<A>() % align_of::<B>(), 0);
+        // The memory is free again in the parent
+        assert_eq!(alloc.used_bytes(), 4);
+        let _ = alloc.alloc_internal([0u8; 1020]);
+    }
 
-        let _ = alloc.alloc_internal(A { data: 0 });
-        let b = alloc.alloc_internal(B { data: 0 });
-        assert_eq!((b as *const B as usize) % align_of::<B>(), 0);
+    #[should_panic(expected = "Sub-arenas don't fit the 1024 bytes of the free block")]
+    #[test]
+    fn split_overflow() {
+        let mut alloc = LinearAllocator::new(1024);
+        let _ = alloc.split(&[1000, 25]);
     }
 
+    #[should_panic(expected = "Cannot create a sub-arena with size 0")]
     #[test]
-    fn rewind() {
-        let alloc = LinearAllocator::new(1024);
+    fn split_empty() {
+        let mut alloc = LinearAllocator::new(1024);
+        let _ = alloc.split(&[512, 0, 256]);
+    }
 
-        let _ = alloc.alloc_internal(0u8);
-        let target = alloc.peek();
-        let _ = alloc.alloc_internal(0u64);
-        assert_ne!(alloc.next_alloc.get(), target);
-        unsafe { alloc.rewind(target) };
-        assert_eq!(alloc.next_alloc.get(), target);
+    #[test]
+    fn from_buffer_scoped() {
+        let mut buf = [0u8; 1024];
+        let alloc = LinearAllocator::from_buffer(&mut buf);
+        {
+            let scratch = crate::ScopedScratch::new(&alloc);
+            let a = scratch.alloc(vec![1u32, 2, 3]);
+            assert_eq!(a.len(), 3);
+        }
+        assert_eq!(alloc.used_bytes(), 0);
     }
 
-    #[should_panic(expected = "alloc doesn't belong to this allocator")]
+    #[should_panic(expected = "Cannot create an allocator with size 0")]
     #[test]
-    fn rewind_assert_below() {
-        let alloc = LinearAllocator::new(1024);
-        unsafe { alloc.rewind(0x1 as *mut u8) };
+    fn from_buffer_empty() {
+        let _ = LinearAllocator::from_buffer(&mut []);
     }
 
-    #[should_panic(expected = "alloc doesn't belong to this allocator")]
     #[test]
-    fn rewind_assert_above() {
+    fn used_bytes_slice() {
+        use std::hash::{DefaultHasher, Hash, Hasher};
+
         let alloc = LinearAllocator::new(1024);
-        unsafe { alloc.rewind(alloc.peek().offset(1024)) }
+        // Allocated in decreasing alignment so that there's no padding
+        let _ = alloc.alloc_internal(0x0123456789ABCDEFu64);
+        let _ = alloc.alloc_internal(0xCAFEBABEu32);
+        let _ = alloc.alloc_internal([0xABu8; 3]);
+        let _ = alloc.alloc_internal_back(0xDEADBEEFu32);
+
+        let mut expected = Vec::new();
+        expected.extend_from_slice(&0x0123456789ABCDEFu64.to_ne_bytes());
+        expected.extend_from_slice(&0xCAFEBABEu32.to_ne_bytes());
+        expected.extend_from_slice(&[0xABu8; 3]);
+
+        let bytes = unsafe { alloc.used_bytes_slice() };
+        assert_eq!(bytes.len(), 15);
+
+        let mut hasher = DefaultHasher::new();
+        bytes.hash(&mut hasher);
+        let mut expected_hasher = DefaultHasher::new();
+        expected.hash(&mut expected_hasher);
+        assert_eq!(hasher.finish(), expected_hasher.finish());
     }
 }
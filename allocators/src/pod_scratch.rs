@@ -0,0 +1,97 @@
+use crate::{
+    error::AllocationError,
+    linear_allocator::{LinearAllocator, LinearAllocatorInternal},
+};
+
+/// A root scope that only allocates `Copy` types, created with
+/// [ScopedScratch::new_pod_only()](crate::ScopedScratch::new_pod_only). Types
+/// that are `Copy` can't implement Drop, so the scope doesn't track destructors
+/// and every allocation is a plain bump of the allocator. Like a root
+/// [ScopedScratch](crate::ScopedScratch), it rewinds the allocator to where it
+/// started when dropped.
+#[must_use = "the scope is rewound as soon as it is dropped"]
+pub struct PodScratch<'a, A: LinearAllocatorInternal = LinearAllocator<'a>> {
+    allocator: &'a A,
+    alloc_start: *mut u8,
+}
+
+impl<A: LinearAllocatorInternal> Drop for PodScratch<'_, A> {
+    fn drop(&mut self) {
+        // # Safety
+        //  - self.alloc_start is from self.allocator.peek() at the start of the scratch
+        //  - Copy types don't need their destructors called
+        //  - Any references to objects in this scratch are limited by its lifetime
+        unsafe {
+            self.allocator.rewind(self.alloc_start);
+        }
+        self.allocator.release_scope();
+    }
+}
+
+impl<'a, A: LinearAllocatorInternal> PodScratch<'a, A> {
+    pub(crate) fn new(allocator: &'a A) -> Self {
+        allocator.acquire_scope();
+        Self {
+            allocator,
+            alloc_start: allocator.peek(),
+        }
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    /// Allocates `obj` with the held allocator, panicking if it doesn't fit
+    #[must_use]
+    pub fn alloc<T: Copy>(&self, obj: T) -> &mut T {
+        self.allocator.alloc_internal(obj)
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    /// Like [alloc()], but returns an error instead of panicking if `obj`
+    /// doesn't fit
+    pub fn try_alloc<T: Copy>(&self, obj: T) -> Result<&mut T, AllocationError> {
+        self.allocator.try_alloc_internal(obj)
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    /// Allocates a slice of `len` objects, initializing each with `init(index)`
+    #[must_use]
+    pub fn alloc_slice<T: Copy>(&self, len: usize, init: impl FnMut(usize) -> T) -> &mut [T] {
+        self.allocator.alloc_slice(len, init)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::ScopedScratch;
+
+    #[test]
+    fn alloc() {
+        let alloc = LinearAllocator::new(1024);
+        let start = alloc.peek();
+        {
+            let scratch = ScopedScratch::new_pod_only(&alloc);
+            let a = scratch.alloc(0xCAFEBABEu32);
+            let b = scratch.alloc_slice(4, |i| i as u64);
+            assert_eq!(*a, 0xCAFEBABEu32);
+            assert_eq!(b, &[0, 1, 2, 3]);
+            // Nothing but the objects themselves is allocated
+            assert_eq!(alloc.used_bytes(), 4 + 4 + 4 * 8);
+
+            assert!(scratch.try_alloc([0u8; 1024]).is_err());
+        }
+        assert_eq!(alloc.peek(), start);
+
+        // The allocator is free for other scopes after the drop
+        let scratch = ScopedScratch::new(&alloc);
+        let _ = scratch.alloc(0u8);
+    }
+
+    #[should_panic(expected = "Allocator is already used by another root ScopedScratch")]
+    #[test]
+    fn sibling_root() {
+        let alloc = LinearAllocator::new(1024);
+        let _scratch = ScopedScratch::new(&alloc);
+        let _pod_scratch = ScopedScratch::new_pod_only(&alloc);
+    }
+}
@@ -0,0 +1,271 @@
+use crate::{
+    error::AllocationError,
+    linear_allocator::{check_size, L1_CACHE_LINE_SIZE},
+};
+
+use core::{alloc::Layout, cell::Cell, ptr::NonNull};
+
+/// An allocator for streaming workloads where the oldest allocation is always
+/// freed first. Allocations bump forward from the head and wrap around to the
+/// start of the block when they don't fit before its end, while the consumer
+/// frees from the tail with [free()]. An allocation fails if it would overtake
+/// the tail.
+///
+/// Allocations are handed out as pointers since they are only released by the
+/// consumer. Objects are never dropped by the allocator.
+pub struct RingAllocator {
+    block_start: *mut u8,
+    layout: Layout,
+    size_bytes: usize,
+    // Offset of the start of the free region
+    head: Cell<usize>,
+    // Offset of the start of the oldest live allocation
+    tail: Cell<usize>,
+    // Tells an empty ring apart from a full one when head and tail meet
+    live_count: Cell<usize>,
+}
+
+impl RingAllocator {
+    /// Creates an allocator with a block of `size_bytes` aligned to the L1 cache line size
+    pub fn new(size_bytes: usize) -> Self {
+        assert_ne!(size_bytes, 0, "Cannot create an allocator with size 0");
+        // Limit so that we can assume allocation arithmetic can never overflow
+        assert!(size_bytes < isize::MAX as usize);
+
+        let layout = Layout::from_size_align(size_bytes, L1_CACHE_LINE_SIZE)
+            .expect("Failed to create memory layout");
+
+        // Safety:
+        // - layout has a non-zero size since size_bytes is not 0 and its construction succeeded
+        let block_start = unsafe { alloc::alloc::alloc(layout) };
+
+        if block_start.is_null() {
+            alloc::alloc::handle_alloc_error(layout);
+        }
+
+        Self {
+            block_start,
+            layout,
+            size_bytes,
+            head: Cell::new(0),
+            tail: Cell::new(0),
+            live_count: Cell::new(0),
+        }
+    }
+
+    /// Returns the total size of the allocator's memory in bytes
+    pub fn capacity(&self) -> usize {
+        self.size_bytes
+    }
+
+    /// Returns `true` if every allocation has been freed
+    pub fn is_empty(&self) -> bool {
+        self.live_count.get() == 0
+    }
+
+    /// Allocates and initializes `obj`, panicking if it doesn't fit
+    pub fn alloc<T: Sized>(&self, obj: T) -> NonNull<T> {
+        match self.try_alloc(obj) {
+            Ok(ret) => ret,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Allocates and initializes `obj`, or returns an error if it would
+    /// overtake the tail. On failure, `obj` is dropped.
+    pub fn try_alloc<T: Sized>(&self, obj: T) -> Result<NonNull<T>, AllocationError> {
+        let ptr = self.try_reserve(Layout::new::<T>())?.cast::<T>();
+        // Safety:
+        // - ptr was just reserved for a T
+        unsafe { ptr.as_ptr().write(obj) };
+        Ok(ptr)
+    }
+
+    /// Reserves uninitialized memory for `layout`. Zero-sized layouts get a
+    /// dangling, aligned pointer that doesn't need to be freed.
+    pub fn try_reserve(&self, layout: Layout) -> Result<NonNull<u8>, AllocationError> {
+        let size_bytes = layout.size();
+        let alignment = layout.align();
+        if size_bytes == 0 {
+            // Safety:
+            // - Alignments are never zero
+            return Ok(unsafe {
+                NonNull::new_unchecked(core::ptr::without_provenance_mut(alignment))
+            });
+        }
+        check_size(size_bytes, alignment)?;
+
+        // Start over from the beginning of the block when nothing is live so
+        // that the whole block is available
+        if self.is_empty() {
+            self.head.set(0);
+            self.tail.set(0);
+        }
+
+        let head = self.head.get();
+        let tail = self.tail.get();
+        // The head has to stay behind the tail once it has wrapped around, as
+        // meeting it would make a full ring look empty
+        let before_tail = tail.checked_sub(1);
+        let regions = if self.is_empty() || head > tail {
+            // Between the head and the end of the block, or wrap around
+            [
+                Some((head, self.size_bytes)),
+                before_tail.map(|end| (0, end)),
+            ]
+        } else {
+            [before_tail.map(|end| (head, end)), None]
+        };
+
+        let mut padding_bytes = 0;
+        let mut remaining_bytes = 0;
+        for (region_start, region_end) in regions.into_iter().flatten() {
+            let (start, padding) = self.align(region_start, alignment);
+            padding_bytes = padding;
+            remaining_bytes = region_end.saturating_sub(region_start);
+            if start + size_bytes <= region_end {
+                self.head.set(start + size_bytes);
+                self.live_count.set(self.live_count.get() + 1);
+                // Safety:
+                // - start is within the block since the allocation ends within it
+                return Ok(unsafe { NonNull::new_unchecked(self.block_start.add(start)) });
+            }
+        }
+
+        Err(AllocationError::OutOfMemory {
+            size_bytes,
+            alignment,
+            padding_bytes,
+            remaining_bytes,
+        })
+    }
+
+    /// Frees the oldest live allocation, which starts at `ptr` and spans `len`
+    /// bytes, moving the tail past it.
+    /// # Safety
+    ///  - `ptr` has to be the oldest allocation that hasn't been freed yet and
+    ///    `len` its size in bytes
+    ///  - The allocation must not be used after this call. Objects in it are
+    ///    not dropped.
+    pub unsafe fn free(&self, ptr: *mut u8, len: usize) {
+        let offset = (ptr as usize).wrapping_sub(self.block_start as usize);
+        assert!(
+            !self.is_empty() && offset < self.size_bytes && len <= self.size_bytes - offset,
+            "ptr isn't a live allocation of this allocator"
+        );
+
+        self.tail.set(offset + len);
+        self.live_count.set(self.live_count.get() - 1);
+    }
+
+    // Returns the offset of the first address at or after offset that is
+    // aligned at alignment, and the padding that skips
+    fn align(&self, offset: usize, alignment: usize) -> (usize, usize) {
+        let padding_bytes = self
+            .block_start
+            .wrapping_add(offset)
+            .align_offset(alignment);
+        assert_ne!(padding_bytes, usize::MAX);
+        (offset + padding_bytes, padding_bytes)
+    }
+}
+
+impl Drop for RingAllocator {
+    fn drop(&mut self) {
+        // Safety:
+        //  - self.block_start was allocated using the same allocator in new()
+        //  - self.layout is the layout it was allocated with
+        unsafe {
+            alloc::alloc::dealloc(self.block_start, self.layout);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn fifo() {
+        let alloc = RingAllocator::new(64);
+
+        let a = alloc.alloc(0xCAFEBABEu32);
+        let b = alloc.alloc([0xABu8; 16]);
+        assert_eq!(unsafe { *a.as_ptr() }, 0xCAFEBABEu32);
+        assert_eq!(unsafe { (*b.as_ptr())[15] }, 0xAB);
+        assert!(!alloc.is_empty());
+
+        unsafe {
+            alloc.free(a.as_ptr() as *mut u8, 4);
+            alloc.free(b.as_ptr() as *mut u8, 16);
+        }
+        assert!(alloc.is_empty());
+        // An empty ring starts over from the beginning of the block
+        let c = alloc.alloc([0u8; 64]);
+        assert_eq!(c.as_ptr() as *mut u8, alloc.block_start);
+    }
+
+    #[test]
+    fn wraparound() {
+        let alloc = RingAllocator::new(64);
+
+        let a = alloc.alloc([0u8; 24]);
+        let b = alloc.alloc([1u8; 24]);
+        unsafe { alloc.free(a.as_ptr() as *mut u8, 24) };
+
+        // Doesn't fit before the end, so it goes to the start freed by a
+        let c = alloc.alloc([2u8; 20]);
+        assert_eq!(c.as_ptr() as *mut u8, alloc.block_start);
+        assert_eq!(unsafe { (*b.as_ptr())[23] }, 1);
+
+        // The room between the head and b is too small
+        assert!(alloc.try_alloc([3u8; 4]).is_err());
+        let d = alloc.alloc([3u8; 3]);
+        assert_eq!(d.as_ptr() as *mut u8, alloc.block_start.wrapping_add(20));
+
+        unsafe {
+            alloc.free(b.as_ptr() as *mut u8, 24);
+            alloc.free(c.as_ptr() as *mut u8, 20);
+        }
+        // Only d is live, everything after it is free again
+        let e = alloc.alloc([4u8; 40]);
+        assert_eq!(e.as_ptr() as *mut u8, alloc.block_start.wrapping_add(23));
+        assert_eq!(unsafe { (*d.as_ptr())[2] }, 3);
+    }
+
+    #[test]
+    fn full() {
+        let alloc = RingAllocator::new(64);
+
+        let a = alloc.alloc([0u8; 32]);
+        let _ = alloc.alloc([0u8; 32]);
+        match alloc.try_alloc(0u8) {
+            Err(AllocationError::OutOfMemory {
+                size_bytes,
+                remaining_bytes,
+                ..
+            }) => {
+                assert_eq!(size_bytes, 1);
+                assert_eq!(remaining_bytes, 0);
+            }
+            _ => panic!("Expected OutOfMemory"),
+        }
+
+        // Freeing the tail makes room for allocations that stay behind it
+        unsafe { alloc.free(a.as_ptr() as *mut u8, 32) };
+        assert!(alloc.try_alloc([0u8; 32]).is_err());
+        let _ = alloc.alloc([0u8; 31]);
+    }
+
+    #[should_panic(expected = "ptr isn't a live allocation of this allocator")]
+    #[test]
+    fn free_empty() {
+        let alloc = RingAllocator::new(64);
+        let a = alloc.alloc(0u32);
+        unsafe {
+            alloc.free(a.as_ptr() as *mut u8, 4);
+            alloc.free(a.as_ptr() as *mut u8, 4);
+        }
+    }
+}
@@ -1,6 +1,14 @@
-use crate::linear_allocator::{LinearAllocator, LinearAllocatorInternal};
+use crate::{
+    error::AllocationError,
+    linear_allocator::{LinearAllocator, LinearAllocatorInternal},
+    pod_scratch::PodScratch,
+};
 
-use std::cell::{Cell, RefCell};
+use core::{
+    cell::Cell,
+    mem::{ManuallyDrop, MaybeUninit},
+    pin::Pin,
+};
 
 // Inspired by Frostbite's Scope Stack Allocation
 // Runtime asserts that only the innermost scope is used
@@ -8,64 +16,293 @@ use std::cell::{Cell, RefCell};
 
 struct ScopeData<'a> {
     mem: *mut u8,
-    dtor: Option<&'a dyn Fn(*mut u8)>,
+    // Number of consecutive objects at mem
+    len: usize,
+    dtor: Option<&'a dyn Fn(*mut u8, usize)>,
     previous: Option<&'a ScopeData<'a>>,
 }
 
-pub struct ScopedScratch<'a, 'b> {
-    allocator: &'a LinearAllocator,
+/// The backing allocator defaults to [LinearAllocator], but any of the linear
+/// allocators in this crate, e.g. [GrowableLinearAllocator](crate::GrowableLinearAllocator),
+/// can be used.
+#[must_use = "the scope is rewound as soon as it is dropped"]
+pub struct ScopedScratch<'a, 'b, A: LinearAllocatorInternal = LinearAllocator<'a>> {
+    allocator: &'a A,
     alloc_start: *mut u8,
     // Interior mutability because new_scope() and alloc_internal() need to work
     // on immutable references so that we can create multiple scopes and allocate
     // multiple objects
     data_chain: Cell<Option<&'a ScopeData<'a>>>,
-    parent_locked: Option<&'b RefCell<bool>>,
-    locked: RefCell<bool>,
+    // Chain of the scope that runs the dtors of a shared child scope, see
+    // new_shared_scope(). None if this scope runs its own dtors.
+    owner_chain: Option<&'b Cell<Option<&'a ScopeData<'a>>>>,
+    // Head of the chain when this scope was created, the entries after it are
+    // registered by this scope
+    chain_start: Option<&'a ScopeData<'a>>,
+    // Kept separately from the chain so that they can be queried in O(1)
+    dtor_count: Cell<usize>,
+    alloc_count: Cell<usize>,
+    // Next ID handed out by alloc_with_id()
+    next_id: Cell<u32>,
+    // Set while this scope has an active child scope. Checked on every
+    // allocation as safe code could otherwise allocate over the child.
+    parent_locked: Option<&'b Cell<bool>>,
+    locked: Cell<bool>,
+    // Set on drop to catch allocations through references that were unsafely
+    // kept past the scope, as long as its memory is still around
+    #[cfg(feature = "checked_scopes")]
+    dropped: Cell<bool>,
 }
 
-impl Drop for ScopedScratch<'_, '_> {
+impl<A: LinearAllocatorInternal> Drop for ScopedScratch<'_, '_, A> {
     fn drop(&mut self) {
-        self.iter_chain(&mut |scope| {
-            if let Some(dtor) = scope.dtor {
-                dtor(scope.mem)
+        #[cfg(feature = "checked_scopes")]
+        self.dropped.set(true);
+
+        // Shared scopes leave their objects and memory to the owner
+        let panic = if self.owner_chain.is_none() {
+            let panic = self.run_dtors(None);
+
+            // # Safety
+            //  - self.alloc_start is from self.allocator.peek() at the start of the scratch
+            //  - dtors for the objects that require it in this scratch were just called
+            //    - lock assertions ensure only the innermost scratch scope is ever used
+            //  - Any references to objects in this scratch are limited by its lifetime
+            unsafe {
+                self.allocator.rewind(self.alloc_start);
             }
-        });
+            panic
+        } else {
+            None
+        };
+
+        if let Some(parent_locked) = self.parent_locked {
+            parent_locked.set(false);
+        } else {
+            self.allocator.release_scope();
+        }
+
+        resume_panic(panic);
+    }
+}
+
+/// A point in a [ScopedScratch] that it can be repeatedly rewound back to with
+/// [Checkpoint::rewind()], e.g. to reuse the same memory for temporaries on each
+/// iteration of a loop. Allocations are made through the checkpoint, which
+/// derefs to the scratch, so that none of them are alive on rewind.
+pub struct Checkpoint<'s, 'a, 'b, A: LinearAllocatorInternal = LinearAllocator<'a>> {
+    scratch: &'s mut ScopedScratch<'a, 'b, A>,
+    alloc_start: *mut u8,
+    data_chain: Option<&'a ScopeData<'a>>,
+    dtor_count: usize,
+}
+
+impl<'a, 'b, A: LinearAllocatorInternal> Checkpoint<'_, 'a, 'b, A> {
+    /// Drops the objects allocated after the checkpoint in reverse allocation
+    /// order and rewinds the allocator back to the checkpoint.
+    pub fn rewind(&mut self) {
+        self.scratch.assert_unlocked();
+
+        let panic = self.scratch.run_dtors(self.data_chain);
 
         // # Safety
-        //  - self.alloc_start is from self.allocator.peek() at the start of the scratch
-        //  - dtors for the objects that require it in this scratch were just called
-        //    - lock assertions ensure only the innermost scratch scope is ever used
-        //  - Any references to objects in this scratch are limited by its lifetime
+        //  - self.alloc_start is from self.allocator.peek() when the checkpoint was created
+        //  - dtors for the objects that require it after the checkpoint were just called
+        //  - References to objects allocated after the checkpoint are limited
+        //    by the borrow of the checkpoint
         unsafe {
-            self.allocator.rewind(self.alloc_start);
+            self.scratch.allocator.rewind(self.alloc_start);
         }
+        self.scratch.chain().set(self.data_chain);
+        self.scratch.dtor_count.set(self.dtor_count);
 
-        if let Some(parent_locked) = self.parent_locked {
-            *parent_locked.borrow_mut() = false;
-        }
+        resume_panic(panic);
+    }
+}
+
+impl<'a, 'b, A: LinearAllocatorInternal> core::ops::Deref for Checkpoint<'_, 'a, 'b, A> {
+    type Target = ScopedScratch<'a, 'b, A>;
+
+    fn deref(&self) -> &Self::Target {
+        self.scratch
     }
 }
 
-impl<'a, 'b> ScopedScratch<'a, 'b> {
-    pub fn new(allocator: &'a mut LinearAllocator) -> Self {
+impl<'a, 'b, A: LinearAllocatorInternal> ScopedScratch<'a, 'b, A> {
+    /// Creates a root scope on `allocator`. Only one root scope can be alive
+    /// per allocator at a time, further scopes are created with [new_scope()].
+    pub fn new(allocator: &'a A) -> Self {
+        allocator.acquire_scope();
         Self {
             allocator,
             alloc_start: allocator.peek(),
             data_chain: Cell::new(None),
+            owner_chain: None,
+            chain_start: None,
+            dtor_count: Cell::new(0),
+            alloc_count: Cell::new(0),
+            next_id: Cell::new(0),
             parent_locked: None,
-            locked: RefCell::new(false),
+            locked: Cell::new(false),
+            #[cfg(feature = "checked_scopes")]
+            dropped: Cell::new(false),
+        }
+    }
+
+    /// Creates a root scope on `allocator` that can only allocate `Copy` types,
+    /// skipping the bookkeeping for destructors and scope nesting. See [PodScratch].
+    pub fn new_pod_only(allocator: &'a A) -> PodScratch<'a, A> {
+        PodScratch::new(allocator)
+    }
+
+    /// Drops the objects allocated in this scope in reverse allocation order and
+    /// rewinds the allocator back to the start of the scope, like dropping the
+    /// scope would. The scope stays usable and dropping it afterwards only
+    /// handles what was allocated after this call.
+    pub fn drop_all(&mut self) {
+        self.assert_unlocked();
+
+        let panic = self.run_dtors(self.chain_start);
+
+        // # Safety
+        //  - self.alloc_start is from self.allocator.peek() at the start of the scratch
+        //  - dtors for the objects that require it in this scratch were just called
+        //  - References to objects in this scratch are limited by the borrow of self
+        unsafe {
+            self.allocator.rewind(self.alloc_start);
+        }
+        self.chain().set(self.chain_start);
+        self.dtor_count.set(0);
+
+        resume_panic(panic);
+    }
+
+    /// Like [drop_all()], but also resets [alloc_count()] and the IDs of
+    /// [alloc_with_id()] so that the scope can be reused for a fresh batch of
+    /// allocations as if it was just created.
+    pub fn reset(&mut self) {
+        self.drop_all();
+        self.alloc_count.set(0);
+        self.next_id.set(0);
+    }
+
+    /// Creates a checkpoint at the current state of the scope, see [Checkpoint].
+    pub fn checkpoint(&mut self) -> Checkpoint<'_, 'a, 'b, A> {
+        self.assert_unlocked();
+        Checkpoint {
+            alloc_start: self.allocator.peek(),
+            data_chain: self.chain().get(),
+            dtor_count: self.dtor_count.get(),
+            scratch: self,
+        }
+    }
+
+    pub fn new_scope(&'b self) -> ScopedScratch<'a, 'b, A> {
+        self.locked.set(true);
+        Self {
+            allocator: self.allocator,
+            alloc_start: self.allocator.peek(),
+            data_chain: Cell::new(None),
+            owner_chain: None,
+            chain_start: None,
+            dtor_count: Cell::new(0),
+            alloc_count: Cell::new(0),
+            next_id: Cell::new(0),
+            parent_locked: Some(&self.locked),
+            locked: Cell::new(false),
+            #[cfg(feature = "checked_scopes")]
+            dropped: Cell::new(false),
         }
     }
 
-    pub fn new_scope(&'b self) -> ScopedScratch<'a, 'b> {
-        *self.locked.borrow_mut() = true;
+    /// Runs `f` with a new child scope that is dropped when `f` returns, so
+    /// that nothing allocated from the child can outlive it. This scope is
+    /// locked for the duration of `f` like with [new_scope()].
+    pub fn scope<R>(&self, f: impl FnOnce(&ScopedScratch<'a, '_, A>) -> R) -> R {
+        let child = self.new_scope();
+        f(&child)
+    }
+
+    /// Creates a child scope whose objects are dropped by this scope instead,
+    /// so that all of them are dropped in strict reverse allocation order
+    /// regardless of scope boundaries. The trade-off is that dropping the
+    /// child frees neither its objects nor its memory, both are held until
+    /// this scope is dropped. Shared scopes of shared scopes defer to the same
+    /// owner.
+    pub fn new_shared_scope(&'b self) -> ScopedScratch<'a, 'b, A> {
+        self.locked.set(true);
         Self {
             allocator: self.allocator,
             alloc_start: self.allocator.peek(),
             data_chain: Cell::new(None),
+            owner_chain: Some(self.chain()),
+            chain_start: self.chain().get(),
+            dtor_count: Cell::new(0),
+            alloc_count: Cell::new(0),
+            next_id: Cell::new(0),
             parent_locked: Some(&self.locked),
-            locked: RefCell::new(false),
+            locked: Cell::new(false),
+            #[cfg(feature = "checked_scopes")]
+            dropped: Cell::new(false),
+        }
+    }
+
+    /// Hands the objects and memory of this scope over to `outer`, so that they
+    /// are dropped and rewound with `outer` as if they were allocated from it.
+    /// `outer` has to be the scope this one was created from with [new_scope()]
+    /// or [new_shared_scope()], which also means they share the allocator.
+    /// References into this scope still end with it, but pointers to its objects
+    /// stay valid until `outer` is dropped or rewound, e.g. to keep a structure
+    /// that was built in a temporary scope without copying it.
+    /// The adopted destructors are counted in `outer` like its own allocations,
+    /// also when `outer` is a shared scope whose objects are run by its owner.
+    /// Panics if the entry that adopts the objects doesn't fit, in which case
+    /// this scope is dropped normally first.
+    pub fn extend_lifetime_into(self, outer: &ScopedScratch<'a, '_, A>) {
+        let parent_locked = match self.parent_locked {
+            Some(locked) if core::ptr::eq(locked, &outer.locked) => locked,
+            _ => panic!("Tried to extend the lifetime of a ScopedScratch into a scope other than its parent"),
+        };
+
+        // Shared scopes already register their objects on the chain of the owner
+        // and are credited to outer as if they were allocated from it, even if
+        // outer is shared itself
+        if self.owner_chain.is_some() {
+            outer
+                .dtor_count
+                .set(outer.dtor_count.get() + self.dtor_count.get());
+        } else if let Some(chain) = self.data_chain.get() {
+            // The chain of this scope ends in None instead of the chain of
+            // outer, so it's registered as a single entry that runs it.
+            // The entry is allocated before the scope is given up so that it
+            // still cleans up normally if this fails.
+            let data = self.allocator.try_alloc_internal(ScopeData {
+                mem: chain as *const ScopeData as *mut u8,
+                len: 0,
+                dtor: Some(&|ptr: *mut u8, _len: usize| {
+                    // Safety:
+                    // - ptr is the head of the chain of the adopted scope,
+                    //   which is kept alive until outer is rewound
+                    let chain = unsafe { &*(ptr as *const ScopeData) };
+                    resume_panic(run_chain(Some(chain), None));
+                }),
+                previous: outer.chain().get(),
+            });
+            let data = match data {
+                Ok(data) => data,
+                Err(err) => {
+                    let allocator = self.allocator;
+                    drop(self);
+                    allocator.alloc_failed(err)
+                }
+            };
+            outer.chain().set(Some(data));
+            outer.dtor_count.set(outer.dtor_count.get() + 1);
         }
+
+        // The scope isn't dropped so neither its dtors nor its rewind run
+        let _ = ManuallyDrop::new(self);
+        parent_locked.set(false);
     }
 
     // Interior mutability required by interface
@@ -74,39 +311,685 @@ impl<'a, 'b> ScopedScratch<'a, 'b> {
     #[allow(clippy::mut_from_ref)]
     /// Allocates `obj` with the held allocator. If `obj` needs Drop, its destruction
     /// is added to internal bookkeeping and is handled when this `ScopeScratch` is dropped.
-    pub fn alloc<T: Sized>(&self, obj: T) -> &mut T {
-        assert!(
-            !*self.locked.borrow(),
-            "Tried to allocate from a ScopedScratch that has an active child scope"
-        );
+    /// `T` has to outlive the allocator borrow since its destructor might only
+    /// run when the scope is dropped.
+    /// The returned reference coerces to a trait object, e.g. `&mut dyn Fn()`,
+    /// and the destructor of the concrete type is still the one registered.
+    #[must_use]
+    pub fn alloc<T: Sized + 'a>(&self, obj: T) -> &mut T {
+        self.assert_unlocked();
 
         // The compiler seems smart enough that this check is optimized out
-        if !std::mem::needs_drop::<T>() {
+        self.count_alloc();
+        if !core::mem::needs_drop::<T>() {
             return self.allocator.alloc_internal(obj);
         }
 
-        let mut data = self.allocator.alloc_internal(ScopeData {
-            mem: std::ptr::null_mut::<u8>(),
-            dtor: Some(&|ptr: *mut u8| {
+        let data = self.allocator.alloc_internal(self.drop_data::<T>(1));
+        let ret = self.allocator.alloc_internal(obj);
+        data.mem = (ret as *mut T) as *mut u8;
+        self.register(data);
+        ret
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    /// Like [alloc()], but aligns `obj` to at least `align`, e.g. for SIMD loads.
+    /// `align` has to be a power of two.
+    #[must_use]
+    pub fn alloc_aligned<T: Sized + 'a>(&self, obj: T, align: usize) -> &mut T {
+        self.assert_unlocked();
+
+        self.count_alloc();
+        if !core::mem::needs_drop::<T>() {
+            return self.allocator.alloc_aligned(obj, align);
+        }
+
+        let data = self.allocator.alloc_internal(self.drop_data::<T>(1));
+        let ret = self.allocator.alloc_aligned(obj, align);
+        data.mem = (ret as *mut T) as *mut u8;
+        self.register(data);
+        ret
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    /// Allocates `obj` like [alloc()] and pins it. The object doesn't move until
+    /// it is dropped with the scope, which also invalidates the pin.
+    /// # Safety
+    ///  - This scope and its parents must not be leaked, e.g. with
+    ///    `std::mem::forget`, since a parent would then rewind over the object
+    ///    without dropping it, breaking the drop guarantee of `Pin`
+    #[must_use]
+    pub unsafe fn alloc_pinned<T: Sized + 'a>(&self, obj: T) -> Pin<&mut T> {
+        // Safety:
+        // - The allocation isn't moved or reused until the scope is dropped,
+        //   which drops obj first if it needs to
+        // - The caller guarantees that the scope is dropped
+        unsafe { Pin::new_unchecked(self.alloc(obj)) }
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    /// Allocates a clone of `value`, see [alloc()].
+    #[must_use]
+    pub fn alloc_clone<T: Clone + 'a>(&self, value: &T) -> &mut T {
+        self.alloc(value.clone())
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    /// Allocates `T::default()` with the held allocator, see [alloc()].
+    #[must_use]
+    pub fn alloc_default<T: Default + 'a>(&self) -> &mut T {
+        self.alloc(T::default())
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    /// Allocates `obj` like [alloc()] and returns it with an ID that is unique
+    /// within this scope, e.g. for logging. IDs count up from 0 in the order of
+    /// the calls, and child scopes start their own sequence.
+    pub fn alloc_with_id<T: Sized + 'a>(&self, obj: T) -> (&mut T, u32) {
+        let ret = self.alloc(obj);
+        let id = self.next_id.get();
+        self.next_id.set(
+            id.checked_add(1)
+                .expect("Ran out of IDs in the ScopedScratch"),
+        );
+        (ret, id)
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    /// Allocates the closure `f` as a trait object, e.g. for lists of deferred
+    /// callbacks, see [alloc()]. Captured objects are dropped with the scope.
+    pub fn alloc_fn<F: FnMut() + 'a>(&self, f: F) -> &mut (dyn FnMut() + 'a) {
+        self.alloc(f)
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    /// Reserves uninitialized memory for a `T` so that it can be constructed in
+    /// place. No destructor is registered since the slot might never be
+    /// initialized, so a `T` written into it is leaked unless the caller drops it.
+    #[must_use]
+    pub fn alloc_uninit<T: Sized + 'a>(&self) -> &mut MaybeUninit<T> {
+        self.assert_unlocked();
+        let ret = self.allocator.alloc_uninit();
+        self.count_alloc();
+        ret
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    /// Like [alloc()], but returns an error instead of panicking if `obj` doesn't
+    /// fit. `obj` is dropped on failure and no destructor is registered for it.
+    /// The scope is left as it was, so it can still be used after a failure.
+    pub fn try_alloc<T: Sized + 'a>(&self, obj: T) -> Result<&mut T, AllocationError> {
+        self.assert_unlocked();
+
+        if !core::mem::needs_drop::<T>() {
+            let ret = self.allocator.try_alloc_internal(obj)?;
+            self.count_alloc();
+            return Ok(ret);
+        }
+
+        let ret = self.try_alloc_tracked(1, || {
+            self.allocator
+                .try_alloc_internal(obj)
+                .map(|ret| ret as *mut T)
+        })?;
+        self.count_alloc();
+        // Safety:
+        // - ret was just allocated and initialized by try_alloc_internal()
+        Ok(unsafe { &mut *ret })
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    /// Like [alloc()], but reserves the room first and only then constructs
+    /// the object with `f`, directly into the allocation.
+    #[must_use]
+    pub fn alloc_with<T: Sized + 'a>(&self, f: impl FnOnce() -> T) -> &mut T {
+        if !core::mem::needs_drop::<T>() {
+            self.assert_unlocked();
+            let ret = self.allocator.alloc_with(f);
+            self.count_alloc();
+            return ret;
+        }
+
+        match self.try_alloc_with(f) {
+            Ok(ret) => ret,
+            Err(err) => self.allocator.alloc_failed(err),
+        }
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    /// Like [alloc_with()], but returns an error instead of panicking if the
+    /// object doesn't fit. `f` is not called on failure.
+    pub fn try_alloc_with<T: Sized + 'a>(
+        &self,
+        f: impl FnOnce() -> T,
+    ) -> Result<&mut T, AllocationError> {
+        self.assert_unlocked();
+
+        if !core::mem::needs_drop::<T>() {
+            let ret = self.allocator.try_alloc_with(f)?;
+            self.count_alloc();
+            return Ok(ret);
+        }
+
+        let ret = self.try_alloc_tracked(1, || {
+            self.allocator.try_alloc_with(f).map(|ret| ret as *mut T)
+        })?;
+        self.count_alloc();
+        // Safety:
+        // - ret was just allocated and initialized by try_alloc_with()
+        Ok(unsafe { &mut *ret })
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    /// Copies `s` into the held allocator.
+    #[must_use]
+    pub fn alloc_str(&self, s: &str) -> &mut str {
+        self.assert_unlocked();
+        let ret = self.allocator.alloc_str(s);
+        self.count_alloc();
+        ret
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    /// Reserves `len` zeroed bytes from the held allocator.
+    #[must_use]
+    pub fn alloc_bytes_zeroed(&self, len: usize) -> &mut [u8] {
+        self.assert_unlocked();
+        let ret = self.allocator.alloc_bytes_zeroed(len);
+        self.count_alloc();
+        ret
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    /// Allocates an array of `N` objects, initializing each with `init(index)`.
+    /// The objects are dropped when this `ScopedScratch` is dropped.
+    #[must_use]
+    pub fn alloc_array<T: Sized + 'a, const N: usize>(
+        &self,
+        init: impl FnMut(usize) -> T,
+    ) -> &mut [T; N] {
+        if !core::mem::needs_drop::<T>() {
+            self.assert_unlocked();
+            let ret = self.allocator.alloc_array(init);
+            self.count_alloc();
+            return ret;
+        }
+
+        let slice = self.alloc_slice(N, init);
+        // Safety:
+        // - The slice has exactly N elements and [T; N] has the same layout as [T]
+        unsafe { &mut *(slice.as_mut_ptr() as *mut [T; N]) }
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    /// Allocates a slice of `len` objects, initializing each with `init(index)`.
+    /// If `T` needs Drop, a single destructor entry is registered for the whole
+    /// slice and the objects are dropped when this `ScopedScratch` is dropped.
+    #[must_use]
+    pub fn alloc_slice<T: Sized + 'a>(&self, len: usize, init: impl FnMut(usize) -> T) -> &mut [T] {
+        if !core::mem::needs_drop::<T>() {
+            self.assert_unlocked();
+            let ret = self.allocator.alloc_slice(len, init);
+            self.count_alloc();
+            return ret;
+        }
+
+        match self.try_alloc_slice_with(len, init) {
+            Ok(ret) => ret,
+            Err(err) => self.allocator.alloc_failed(err),
+        }
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    /// Allocates `header` followed by a slice of `len` objects, initializing
+    /// each with `init(index)`, in a single allocation, like a struct with a
+    /// trailing variable-length array. If they need Drop, the objects in the
+    /// slice are dropped before the header when this `ScopedScratch` is dropped.
+    #[must_use]
+    pub fn alloc_slice_header<H: Sized + 'a, T: Sized + 'a>(
+        &self,
+        header: H,
+        len: usize,
+        init: impl FnMut(usize) -> T,
+    ) -> (&mut H, &mut [T]) {
+        self.assert_unlocked();
+
+        let header_data = core::mem::needs_drop::<H>()
+            .then(|| self.allocator.alloc_internal(self.drop_data::<H>(1)));
+        let slice_data = (core::mem::needs_drop::<T>() && len > 0)
+            .then(|| self.allocator.alloc_internal(self.drop_data::<T>(len)));
+        let (header, slice) = self.allocator.alloc_slice_header(header, len, init);
+
+        if let Some(data) = header_data {
+            data.mem = (header as *mut H) as *mut u8;
+            // init might have registered objects after the entry was created
+            data.previous = self.chain().get();
+            self.register(data);
+        }
+        if let Some(data) = slice_data {
+            data.mem = slice.as_mut_ptr() as *mut u8;
+            // The header might have been registered after the entry was created
+            data.previous = self.chain().get();
+            self.register(data);
+        }
+        self.count_alloc();
+
+        (header, slice)
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    /// Allocates a slice of `len` copies of `value`.
+    #[must_use]
+    pub fn alloc_slice_fill_copy<T: Copy + 'a>(&self, len: usize, value: T) -> &mut [T] {
+        self.alloc_slice(len, |_| value)
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    /// Allocates a slice of `len` clones of `value`. The clones are dropped when
+    /// this `ScopedScratch` is dropped.
+    #[must_use]
+    pub fn alloc_slice_fill_clone<T: Clone + 'a>(&self, len: usize, value: &T) -> &mut [T] {
+        self.alloc_slice(len, |_| value.clone())
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    /// Allocates a slice of `len` objects initialized with `T::default()`. The
+    /// objects are dropped when this `ScopedScratch` is dropped.
+    #[must_use]
+    pub fn alloc_slice_default<T: Default + 'a>(&self, len: usize) -> &mut [T] {
+        self.alloc_slice(len, |_| T::default())
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    /// Allocates a slice of clones of the elements in `src`. The clones are
+    /// dropped when this `ScopedScratch` is dropped.
+    #[must_use]
+    pub fn alloc_slice_clone<T: Clone + 'a>(&self, src: &[T]) -> &mut [T] {
+        self.alloc_slice(src.len(), |i| src[i].clone())
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    /// Like [alloc_slice_clone()], but copies all of `src` at once, which is
+    /// considerably faster for large slices.
+    #[must_use]
+    pub fn alloc_slice_copy<T: Copy + 'a>(&self, src: &[T]) -> &mut [T] {
+        self.assert_unlocked();
+        let ret = self.allocator.alloc_slice_copy(src);
+        self.count_alloc();
+        ret
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    /// Allocates the items yielded by `iter` as a contiguous slice. Room is
+    /// reserved for `max_len` items and the unused part is given back if
+    /// nothing else was allocated in the meantime. Panics if `iter` yields more
+    /// than `max_len` items. The yielded items are dropped when this
+    /// `ScopedScratch` is dropped.
+    #[must_use]
+    pub fn alloc_from_iter<T: Sized + 'a, I: IntoIterator<Item = T>>(
+        &self,
+        iter: I,
+        max_len: usize,
+    ) -> &mut [T] {
+        self.assert_unlocked();
+
+        let data = if core::mem::needs_drop::<T>() && max_len > 0 {
+            Some(self.allocator.alloc_internal(self.drop_data::<T>(0)))
+        } else {
+            None
+        };
+        let slots = match self.allocator.try_alloc_slice_uninit::<T>(max_len) {
+            Ok(slots) => slots,
+            Err(err) => self.allocator.alloc_failed(err),
+        };
+        let slots_end = slots.as_mut_ptr_range().end as *mut u8;
+
+        let mut iter = iter.into_iter();
+        let mut len = 0;
+        for slot in slots.iter_mut() {
+            match iter.next() {
+                Some(item) => {
+                    slot.write(item);
+                    len += 1;
+                }
+                None => break,
+            }
+        }
+        let ret = slots.as_mut_ptr() as *mut T;
+
+        // Register before checking for excess items so that the written ones
+        // are dropped even if we panic
+        if let Some(data) = data {
+            data.mem = ret as *mut u8;
+            data.len = len;
+            // The iterator might have registered other objects in the meantime
+            data.previous = self.chain().get();
+            self.register(data);
+        }
+        self.count_alloc();
+
+        assert!(
+            iter.next().is_none(),
+            "Iterator yielded more than max_len = {} items",
+            max_len
+        );
+
+        if len < max_len && core::mem::size_of::<T>() > 0 && self.allocator.peek() == slots_end {
+            // # Safety
+            //  - The target is within the slots that were just allocated
+            //  - Nothing was allocated after the slots
+            //  - The rewound slots were never initialized
+            unsafe {
+                self.allocator.rewind(ret.add(len) as *mut u8);
+            }
+        }
+
+        // Safety:
+        // - The first len elements were initialized above
+        unsafe { core::slice::from_raw_parts_mut(ret, len) }
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    // Allocates a slice of len objects, initializing each with f(index)
+    fn try_alloc_slice_with<T: Sized + 'a>(
+        &self,
+        len: usize,
+        f: impl FnMut(usize) -> T,
+    ) -> Result<&mut [T], AllocationError> {
+        self.assert_unlocked();
+
+        if !core::mem::needs_drop::<T>() || len == 0 {
+            let ret = self.allocator.try_alloc_slice(len, f)?;
+            self.count_alloc();
+            return Ok(ret);
+        }
+
+        let ret = self.try_alloc_tracked(len, || {
+            self.allocator
+                .try_alloc_slice(len, f)
+                .map(|ret| ret.as_mut_ptr())
+        })?;
+        self.count_alloc();
+        // Safety:
+        // - ret points to len objects that were just allocated and initialized
+        Ok(unsafe { core::slice::from_raw_parts_mut(ret, len) })
+    }
+
+    // Registers the drop of the len objects of type T that alloc() places in
+    // the allocator. If alloc() fails, the allocator is rewound and nothing is
+    // registered.
+    fn try_alloc_tracked<T: Sized + 'a>(
+        &self,
+        len: usize,
+        alloc: impl FnOnce() -> Result<*mut T, AllocationError>,
+    ) -> Result<*mut T, AllocationError> {
+        let data_start = self.allocator.peek();
+        let data = self
+            .allocator
+            .try_alloc_internal(self.drop_data::<T>(len))?;
+        match alloc() {
+            Ok(ret) => {
+                data.mem = ret as *mut u8;
+                // alloc() might have registered other objects in the meantime
+                data.previous = self.chain().get();
+                self.register(data);
+                Ok(ret)
+            }
+            Err(err) => {
+                // # Safety
+                //  - data_start is from self.allocator.peek() before data was allocated
+                //  - alloc() failed so nothing was allocated after data
+                //  - data wasn't added to the chain so nothing refers to it
+                unsafe {
+                    self.allocator.rewind(data_start);
+                }
+                Err(err)
+            }
+        }
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    /// Resizes `last` to `new_len` objects. If `last` is the most recent
+    /// allocation of this scope, it is grown or shrunk in place. Otherwise a
+    /// new slice is allocated and the objects are copied over, leaving the old
+    /// one in place until the scope is dropped. The first
+    /// `min(last.len(), new_len)` objects of the returned slice are
+    /// initialized and the rest are not.
+    /// # Safety
+    ///  - `last` must not be used after this call, including through the
+    ///    reference it was reborrowed from, as the returned slice can alias it
+    pub unsafe fn realloc_last<T: Copy + 'a>(
+        &self,
+        last: &mut [T],
+        new_len: usize,
+    ) -> &mut [MaybeUninit<T>] {
+        self.assert_unlocked();
+
+        let old_len = last.len();
+        let start = last.as_mut_ptr();
+        if self.is_last(last) {
+            if new_len <= old_len {
+                // # Safety
+                //  - The rewound objects are Copy and the caller doesn't use them
+                //    through last anymore
+                unsafe { self.allocator.rewind(start.add(new_len) as *mut u8) };
+                // Safety:
+                // - The first new_len objects of last are still allocated
+                return unsafe {
+                    core::slice::from_raw_parts_mut(start as *mut MaybeUninit<T>, new_len)
+                };
+            }
+
+            // The end of last is aligned for T so the extension follows it
+            // directly unless the allocator moves on to another block
+            let end = start.wrapping_add(old_len) as *mut MaybeUninit<T>;
+            if let Ok(extension) = self
+                .allocator
+                .try_alloc_slice_uninit::<T>(new_len - old_len)
+            {
+                if extension.as_mut_ptr() == end {
+                    // Safety:
+                    // - last and the extension are contiguous
+                    return unsafe {
+                        core::slice::from_raw_parts_mut(start as *mut MaybeUninit<T>, new_len)
+                    };
+                }
+                // # Safety
+                //  - The extension was the last allocation and isn't used
+                unsafe { self.allocator.rewind(extension.as_mut_ptr() as *mut u8) };
+            }
+        } else if new_len <= old_len {
+            // Safety:
+            // - The first new_len objects of last are allocated
+            return unsafe {
+                core::slice::from_raw_parts_mut(start as *mut MaybeUninit<T>, new_len)
+            };
+        }
+
+        let ret = match self.allocator.try_alloc_slice_uninit::<T>(new_len) {
+            Ok(ret) => ret,
+            Err(err) => self.allocator.alloc_failed(err),
+        };
+        // Safety:
+        // - ret was just allocated with room for new_len > old_len objects and
+        //   can't overlap with last
+        // - Copy types can be duplicated bitwise
+        unsafe {
+            core::ptr::copy_nonoverlapping(start, ret.as_mut_ptr() as *mut T, old_len);
+        }
+        self.count_alloc();
+        ret
+    }
+
+    /// Returns the number of bytes allocated by this scope and its children so
+    /// far, including alignment padding and the drop entries of objects
+    pub fn bytes_used(&self) -> usize {
+        self.allocator.bytes_since(self.alloc_start)
+    }
+
+    /// Returns `true` if `r` ends where the next allocation would start, i.e. it
+    /// is the most recent allocation as required by [free_last()]. Zero-sized
+    /// objects don't take up room in the allocator and are never the last.
+    /// Objects in allocators that grow down never end there, so they can't be
+    /// freed or resized in place.
+    pub fn is_last<T: ?Sized>(&self, r: &T) -> bool {
+        let size = core::mem::size_of_val(r);
+        size > 0 && (r as *const T as *const u8).wrapping_add(size) == self.allocator.peek()
+    }
+
+    /// Frees `last`, which has to be the most recent allocation of this scope,
+    /// dropping it if needed and rewinding the allocator back to its start.
+    /// Panics if something was allocated after `last`.
+    /// # Safety
+    ///  - `last` has to be a whole allocation returned by this scope, not a
+    ///    reference into one
+    ///  - `last` must not be used after this call, including through the
+    ///    reference it was reborrowed from
+    pub unsafe fn free_last<T: ?Sized>(&self, last: &mut T) {
+        self.assert_unlocked();
+
+        let start = last as *mut T as *mut u8;
+        let size = core::mem::size_of_val(last);
+        // Zero-sized objects don't take up room in the allocator
+        let mut rewind_to = if size == 0 {
+            self.allocator.peek()
+        } else {
+            assert!(
+                self.is_last(last),
+                "Tried to free an object that isn't the last allocation of the ScopedScratch"
+            );
+            start
+        };
+
+        if core::mem::needs_drop::<T>() {
+            let data = match self.chain().get() {
+                Some(data) if data.mem == start => data,
+                _ => panic!(
+                    "Tried to free an object that isn't the last allocation of the ScopedScratch"
+                ),
+            };
+
+            // The chain entry is allocated right before the object, so it can be
+            // freed as well unless something was allocated in between
+            let data_ptr = data as *const ScopeData<'a> as *mut u8;
+            let data_end = data_ptr.wrapping_add(core::mem::size_of::<ScopeData<'a>>());
+            let gap_end = if size == 0 {
+                data_end
+            } else {
+                data_end.wrapping_add(data_end.align_offset(core::mem::align_of_val(last)))
+            };
+            if gap_end == rewind_to {
+                rewind_to = data_ptr;
+            }
+
+            // Unlink before dropping so that a panicking drop can't be run again
+            self.chain().set(data.previous);
+            self.dtor_count.set(self.dtor_count.get() - 1);
+            if let Some(dtor) = data.dtor {
+                dtor(data.mem, data.len);
+            }
+        }
+
+        // Safety:
+        // - rewind_to is either the start of last or its chain entry, which
+        //   were the last allocations of this scope
+        // - last was just dropped if it needed to be and the caller guarantees
+        //   that it isn't used anymore
+        // - The chain entry was unlinked above so nothing refers to it
+        unsafe {
+            self.allocator.rewind(rewind_to);
+        }
+    }
+
+    /// Returns the number of destructor entries registered in this scope.
+    /// Each non-empty allocation of a type that needs Drop registers one entry,
+    /// regardless of how many objects it holds. The objects of a child scope
+    /// adopted with [extend_lifetime_into()] are run through a single entry.
+    pub fn registered_dtor_count(&self) -> usize {
+        self.dtor_count.get()
+    }
+
+    /// Panics if destructors are registered in this scope, e.g. to check that a
+    /// test only allocated objects that don't need Drop
+    pub fn assert_no_pending_dtors(&self) {
+        assert_eq!(
+            self.registered_dtor_count(),
+            0,
+            "Expected no destructors to be registered in the ScopedScratch"
+        );
+    }
+
+    /// Returns the addresses of the objects whose destructors are registered in
+    /// this scope, in the order they will be dropped, e.g. for correlating them
+    /// with addresses in a debugger. Only available in debug builds.
+    #[cfg(debug_assertions)]
+    pub fn registered_objects(&self) -> impl Iterator<Item = *mut u8> + '_ {
+        let chain_start = self.chain_start;
+        core::iter::successors(self.chain().get(), |data| data.previous)
+            .take_while(move |data| !chain_start.is_some_and(|start| core::ptr::eq(*data, start)))
+            .map(|data| data.mem)
+    }
+
+    /// Returns the number of allocations made from this scope. Child scopes
+    /// keep their own counts.
+    pub fn alloc_count(&self) -> usize {
+        self.alloc_count.get()
+    }
+
+    // Adds data to the head of the chain
+    fn register(&self, data: &'a ScopeData<'a>) {
+        self.chain().replace(Some(data));
+        self.dtor_count.set(self.dtor_count.get() + 1);
+    }
+
+    // Returns the chain the objects of this scope are registered in
+    fn chain(&self) -> &Cell<Option<&'a ScopeData<'a>>> {
+        self.owner_chain.unwrap_or(&self.data_chain)
+    }
+
+    fn count_alloc(&self) {
+        self.alloc_count.set(self.alloc_count.get() + 1);
+    }
+
+    fn assert_unlocked(&self) {
+        #[cfg(feature = "checked_scopes")]
+        assert!(
+            !self.dropped.get(),
+            "Tried to allocate from a ScopedScratch that was already dropped"
+        );
+        assert!(
+            !self.locked.get(),
+            "Tried to allocate from a ScopedScratch that has an active child scope"
+        );
+    }
+
+    // Creates the chain entry for len objects of type T, mem has to be filled by the caller
+    fn drop_data<T: Sized + 'a>(&self, len: usize) -> ScopeData<'a> {
+        ScopeData {
+            mem: core::ptr::null_mut::<u8>(),
+            len,
+            dtor: Some(&|ptr: *mut u8, len: usize| {
                 assert!(!ptr.is_null());
                 // Safety:
                 // - We asserted that ptr is not null
                 // - Assume ptr has come the internal allocator so also assume it is
                 //   valid and properly aligned
-                // - Assume obj pointed by ptr is valid for dropping
-                //   - it was initialized by value so it should be in a valid state
-                //     for dropping
-                //   - it is effectively owned by us and we will only drop it once
-                //   - lifetimes of any references to it will be tied to our lifetime
-                unsafe { (ptr as *mut T).drop_in_place() }
+                // - Assume the len objs pointed by ptr are valid for dropping
+                //   - they were initialized by value so they should be in a valid
+                //     state for dropping
+                //   - they are effectively owned by us and we will only drop them once
+                //   - lifetimes of any references to them will be tied to our lifetime
+                unsafe { core::ptr::slice_from_raw_parts_mut(ptr as *mut T, len).drop_in_place() }
             }),
-            previous: self.data_chain.get(),
-        });
+            previous: self.chain().get(),
+        }
+    }
 
-        let ret = self.allocator.alloc_internal(obj);
-        data.mem = (ret as *mut T) as *mut u8;
-        self.data_chain.replace(Some(data));
-        ret
+    // Runs the dtors registered after until, in reverse registration order.
+    // A panicking destructor shouldn't leak the rest or skip the rewind, so the
+    // first panic is returned to be resumed once the caller has cleaned up.
+    fn run_dtors(&self, until: Option<&ScopeData<'a>>) -> Option<PanicPayload> {
+        run_chain(self.chain().get(), until)
     }
 
     #[cfg(test)]
@@ -116,8 +999,9 @@ impl<'a, 'b> ScopedScratch<'a, 'b> {
         len
     }
 
+    #[cfg(test)]
     fn iter_chain(&self, f: &mut dyn FnMut(&ScopeData)) {
-        let mut data_chain = self.data_chain.get();
+        let mut data_chain = self.chain().get();
         while let Some(scope) = data_chain {
             f(scope);
             data_chain = scope.previous;
@@ -125,15 +1009,65 @@ impl<'a, 'b> ScopedScratch<'a, 'b> {
     }
 }
 
+#[cfg(feature = "std")]
+type PanicPayload = Box<dyn std::any::Any + Send + 'static>;
+// Panics are never caught without std
+#[cfg(not(feature = "std"))]
+type PanicPayload = core::convert::Infallible;
+
+// Runs the dtors of data_chain in order until reaching until, returning the
+// first panic so that the rest of the dtors still run
+fn run_chain<'a>(
+    mut data_chain: Option<&ScopeData<'a>>,
+    until: Option<&ScopeData<'a>>,
+) -> Option<PanicPayload> {
+    #[allow(unused_mut)]
+    let mut panic = None;
+    while let Some(scope) = data_chain {
+        if until.is_some_and(|until| core::ptr::eq(scope, until)) {
+            break;
+        }
+        if let Some(dtor) = scope.dtor {
+            #[cfg(feature = "std")]
+            {
+                let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                    dtor(scope.mem, scope.len)
+                }));
+                if let Err(payload) = result {
+                    panic.get_or_insert(payload);
+                }
+            }
+            // Panics can't be caught without std
+            #[cfg(not(feature = "std"))]
+            dtor(scope.mem, scope.len);
+        }
+        data_chain = scope.previous;
+    }
+    panic
+}
+
+fn resume_panic(panic: Option<PanicPayload>) {
+    #[cfg(feature = "std")]
+    if let Some(payload) = panic {
+        std::panic::resume_unwind(payload);
+    }
+    #[cfg(not(feature = "std"))]
+    if let Some(payload) = panic {
+        match payload {}
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
     use super::*;
+    use crate::GrowableLinearAllocator;
+    use core::cell::RefCell;
 
     #[test]
     fn alloc_primitive() {
-        let mut alloc = LinearAllocator::new(1024);
-        let scratch = ScopedScratch::new(&mut alloc);
+        let alloc = LinearAllocator::new(1024);
+        let scratch = ScopedScratch::new(&alloc);
 
         let a = scratch.alloc(0xABu8);
         assert_eq!(*a, 0xABu8);
@@ -141,8 +1075,8 @@ mod tests {
 
     #[test]
     fn alloc_pod() {
-        let mut alloc = LinearAllocator::new(1024);
-        let scratch = ScopedScratch::new(&mut alloc);
+        let alloc = LinearAllocator::new(1024);
+        let scratch = ScopedScratch::new(&alloc);
 
         #[derive(Clone, Copy)]
         #[allow(dead_code)]
@@ -158,8 +1092,8 @@ mod tests {
 
     #[test]
     fn alloc_obj() {
-        let mut alloc = LinearAllocator::new(1024);
-        let scratch = ScopedScratch::new(&mut alloc);
+        let alloc = LinearAllocator::new(1024);
+        let scratch = ScopedScratch::new(&alloc);
 
         #[allow(dead_code)]
         struct A {
@@ -174,11 +1108,374 @@ mod tests {
     }
 
     #[test]
-    fn scope_rewind() {
-        let mut alloc = LinearAllocator::new(1024);
-        let start_ptr = alloc.peek();
+    fn alloc_default() {
+        #[derive(Default)]
+        struct A {
+            a: u32,
+            b: Vec<u32>,
+        }
+
+        let alloc = LinearAllocator::new(1024);
+        let scratch = ScopedScratch::new(&alloc);
+
+        let a = scratch.alloc_default::<u64>();
+        assert_eq!(*a, 0);
+        assert_eq!(scratch.data_chain_len(), 0);
+
+        let b = scratch.alloc_default::<A>();
+        assert_eq!(b.a, 0);
+        assert!(b.b.is_empty());
+        b.b.push(1);
+        assert_eq!(scratch.data_chain_len(), 1);
+    }
+
+    #[test]
+    fn alloc_uninit() {
+        let alloc = LinearAllocator::new(1024);
+        let scratch = ScopedScratch::new(&alloc);
+
+        let a = scratch.alloc_uninit::<[u32; 4]>();
+        let a = a.write([0xCAFEBABEu32; 4]);
+        assert_eq!(a[3], 0xCAFEBABEu32);
+        assert_eq!(scratch.data_chain_len(), 0);
+    }
+
+    #[test]
+    fn alloc_dyn() {
+        let dtor_data: RefCell<Vec<u32>> = RefCell::new(vec![]);
+        let called: RefCell<Vec<u32>> = RefCell::new(vec![]);
+
+        struct A<'a> {
+            data: u32,
+            dtor_push: &'a dyn Fn(u32),
+        }
+        impl<'a> Drop for A<'a> {
+            fn drop(&mut self) {
+                (self.dtor_push)(self.data);
+            }
+        }
+
+        let dtor_push = |v| dtor_data.borrow_mut().push(v);
+        let alloc = LinearAllocator::new(1024);
+        {
+            let scratch = ScopedScratch::new(&alloc);
+
+            let mut commands: Vec<&mut dyn Fn()> = Vec::new();
+            for i in 0..3u32 {
+                let a = A {
+                    data: i,
+                    dtor_push: &dtor_push,
+                };
+                let called = &called;
+                commands.push(scratch.alloc(move || {
+                    // Capture the whole object instead of just the field
+                    let a = &a;
+                    called.borrow_mut().push(a.data)
+                }));
+            }
+            commands.push(scratch.alloc(|| called.borrow_mut().push(0xCAFEBABE)));
+
+            for c in commands.iter() {
+                (**c)();
+            }
+            assert_eq!(*called.borrow(), vec![0, 1, 2, 0xCAFEBABE]);
+            assert_eq!(scratch.data_chain_len(), 3);
+            assert!(dtor_data.borrow().is_empty());
+        }
+        assert_eq!(*dtor_data.borrow(), vec![2, 1, 0]);
+    }
+
+    #[test]
+    fn alloc_with_id() {
+        let alloc = LinearAllocator::new(1024);
+        let mut scratch = ScopedScratch::new(&alloc);
+
+        let ids: Vec<u32> = (0..3u32)
+            .map(|i| {
+                let (a, id) = scratch.alloc_with_id(vec![i]);
+                assert_eq!(a[0], i);
+                id
+            })
+            .collect();
+        assert_eq!(ids, [0, 1, 2]);
+        {
+            let scratch2 = scratch.new_scope();
+            assert_eq!(scratch2.alloc_with_id(0u32).1, 0);
+        }
+        // Other allocations don't take IDs
+        let _ = scratch.alloc(0u32);
+        assert_eq!(scratch.alloc_with_id(0u32).1, 3);
+
+        scratch.reset();
+        assert_eq!(scratch.alloc_with_id(0u32).1, 0);
+    }
+
+    #[test]
+    fn alloc_fn() {
+        let count = Cell::new(0);
+        let dropped = Cell::new(0);
+        struct A<'a>(&'a Cell<u32>);
+        impl Drop for A<'_> {
+            fn drop(&mut self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        let alloc = LinearAllocator::new(1024);
+        {
+            let scratch = ScopedScratch::new(&alloc);
+
+            let mut tasks: Vec<&mut dyn FnMut()> = Vec::new();
+            for i in 1..4u32 {
+                let a = A(&dropped);
+                let mut calls = 0;
+                let count = &count;
+                tasks.push(scratch.alloc_fn(move || {
+                    let _ = &a;
+                    calls += 1;
+                    count.set(count.get() + i * calls);
+                }));
+            }
+
+            for _ in 0..2 {
+                for t in tasks.iter_mut() {
+                    t();
+                }
+            }
+            assert_eq!(count.get(), (1 + 2 + 3) + (1 + 2 + 3) * 2);
+            assert_eq!(dropped.get(), 0);
+        }
+        assert_eq!(dropped.get(), 3);
+    }
+
+    #[test]
+    fn counts() {
+        let value = std::rc::Rc::new(0u32);
+
+        let alloc = LinearAllocator::new(1024);
+        let scratch = ScopedScratch::new(&alloc);
+
+        let _ = scratch.alloc(0u32);
+        let _ = scratch.alloc(value.clone());
+        let _ = scratch.alloc_slice_fill_clone(4, &value);
+        let _ = scratch.alloc_str("abc");
+        assert!(scratch.try_alloc([0u8; 2048]).is_err());
+        assert_eq!(scratch.alloc_count(), 4);
+        assert_eq!(scratch.registered_dtor_count(), 2);
+        assert_eq!(scratch.registered_dtor_count(), scratch.data_chain_len());
+
+        {
+            let child = scratch.new_scope();
+            let _ = child.alloc(value.clone());
+            assert_eq!(child.alloc_count(), 1);
+            assert_eq!(child.registered_dtor_count(), 1);
+        }
+        assert_eq!(scratch.alloc_count(), 4);
+        assert_eq!(scratch.registered_dtor_count(), 2);
+    }
+
+    #[test]
+    fn alloc_aligned() {
+        let value = std::rc::Rc::new(0u32);
+
+        let alloc = LinearAllocator::new(1024);
+        {
+            let scratch = ScopedScratch::new(&alloc);
+
+            let _ = scratch.alloc(0u8);
+            let a = scratch.alloc_aligned([0f32; 8], 32);
+            assert_eq!((a.as_ptr() as usize) % 32, 0);
+            let b = scratch.alloc_aligned(value.clone(), 64);
+            assert_eq!((b as *const std::rc::Rc<u32> as usize) % 64, 0);
+            assert_eq!(scratch.data_chain_len(), 1);
+            assert_eq!(std::rc::Rc::strong_count(&value), 2);
+        }
+        assert_eq!(std::rc::Rc::strong_count(&value), 1);
+    }
+
+    #[test]
+    fn alloc_pinned() {
+        struct Node {
+            data: u32,
+            this: *const Node,
+            _pin: std::marker::PhantomPinned,
+        }
+        impl Node {
+            fn init(self: Pin<&mut Self>) {
+                // Safety:
+                // - Only a pointer to the node is stored, it isn't moved
+                let this = unsafe { self.get_unchecked_mut() };
+                this.this = this as *const Node;
+            }
+        }
+
+        let alloc = LinearAllocator::new(1024);
+        let scratch = ScopedScratch::new(&alloc);
+
+        let mut node = unsafe {
+            scratch.alloc_pinned(Node {
+                data: 0xCAFEBABE,
+                this: std::ptr::null(),
+                _pin: std::marker::PhantomPinned,
+            })
+        };
+        node.as_mut().init();
+        assert_eq!(node.this, &*node as *const Node);
+        assert_eq!(unsafe { (*node.this).data }, 0xCAFEBABE);
+    }
+
+    #[test]
+    fn alloc_slice_copy() {
+        let alloc = LinearAllocator::new(1024);
+        let scratch = ScopedScratch::new(&alloc);
+
+        let _ = scratch.alloc(0u8);
+        let src: Vec<u64> = (0..16).map(|i| i * 0x0101010101010101).collect();
+        let a = scratch.alloc_slice_copy(&src);
+        assert_eq!(a, src.as_slice());
+        assert_ne!(a.as_ptr(), src.as_ptr());
+        assert_eq!((a.as_ptr() as usize) % std::mem::align_of::<u64>(), 0);
+        assert_eq!(scratch.registered_dtor_count(), 0);
+
+        let peek = alloc.peek();
+        let b = scratch.alloc_slice_copy::<u32>(&[]);
+        assert!(b.is_empty());
+        assert_eq!(alloc.peek(), peek);
+    }
+
+    #[test]
+    fn alloc_slice_header() {
+        let alloc = LinearAllocator::new(1024);
+        let scratch = ScopedScratch::new(&alloc);
+
+        let _ = scratch.alloc(0u8);
+        let start = alloc.peek();
+        let (header, slice) = scratch.alloc_slice_header(0xABu8, 3, |i| i as u64);
+        assert_eq!(*header, 0xABu8);
+        assert_eq!(slice, &[0, 1, 2]);
+        // The slice follows the header at the alignment of u64 in a single bump
+        let header_ptr = header as *mut u8;
+        assert_eq!((slice.as_ptr() as usize) % std::mem::align_of::<u64>(), 0);
+        assert_eq!(slice.as_ptr() as *const u8, header_ptr.wrapping_add(8));
+        assert_eq!(alloc.peek(), header_ptr.wrapping_add(8 + 3 * 8));
+        assert!(header_ptr >= start && (header_ptr as usize) - (start as usize) < 8);
+        assert_eq!(scratch.registered_dtor_count(), 0);
+
+        let (header, slice) = scratch.alloc_slice_header([0u64; 2], 0, |_| 0u8);
+        assert_eq!(*header, [0u64; 2]);
+        assert!(slice.is_empty());
+    }
+
+    #[test]
+    fn alloc_slice_header_drop() {
+        struct A<'a> {
+            data: u32,
+            dtor_push: &'a dyn Fn(u32),
+        }
+        impl<'a> Drop for A<'a> {
+            fn drop(&mut self) {
+                (self.dtor_push)(self.data);
+            }
+        }
+
+        let dtor_data: RefCell<Vec<u32>> = RefCell::new(vec![]);
+        let dtor_push = |v| dtor_data.borrow_mut().push(v);
+
+        let alloc = LinearAllocator::new(1024);
         {
-            let scratch = ScopedScratch::new(&mut alloc);
+            let scratch = ScopedScratch::new(&alloc);
+            let (header, slice) = scratch.alloc_slice_header(
+                A {
+                    data: 0,
+                    dtor_push: &dtor_push,
+                },
+                2,
+                |i| A {
+                    data: i as u32 + 1,
+                    dtor_push: &dtor_push,
+                },
+            );
+            assert_eq!(header.data, 0);
+            assert_eq!(slice[1].data, 2);
+            assert_eq!(scratch.registered_dtor_count(), 2);
+        }
+        assert_eq!(*dtor_data.borrow(), vec![1, 2, 0]);
+    }
+
+    #[test]
+    fn alloc_slice_header_init_allocs() {
+        struct A<'a> {
+            data: u32,
+            dtor_push: &'a dyn Fn(u32),
+        }
+        impl<'a> Drop for A<'a> {
+            fn drop(&mut self) {
+                (self.dtor_push)(self.data);
+            }
+        }
+
+        let dtor_data: RefCell<Vec<u32>> = RefCell::new(vec![]);
+        let dtor_push = |v| dtor_data.borrow_mut().push(v);
+
+        let alloc = LinearAllocator::new(1024);
+        {
+            let scratch = ScopedScratch::new(&alloc);
+            let (header, slice) = scratch.alloc_slice_header(
+                A {
+                    data: 0,
+                    dtor_push: &dtor_push,
+                },
+                2,
+                |i| {
+                    let inner = scratch.alloc(A {
+                        data: 10 + i as u32,
+                        dtor_push: &dtor_push,
+                    });
+                    A {
+                        data: inner.data - 9,
+                        dtor_push: &dtor_push,
+                    }
+                },
+            );
+            assert_eq!(header.data, 0);
+            assert_eq!(slice[1].data, 2);
+            assert_eq!(scratch.registered_dtor_count(), 4);
+        }
+        let mut dropped = dtor_data.borrow().clone();
+        dropped.sort();
+        assert_eq!(dropped, vec![0, 1, 2, 10, 11]);
+    }
+
+    #[test]
+    fn alloc_str() {
+        let alloc = LinearAllocator::new(1024);
+        let scratch = ScopedScratch::new(&alloc);
+
+        let a = scratch.alloc_str("Hello");
+        let b = scratch.alloc_str(" world");
+        assert_eq!(a, "Hello");
+        assert_eq!(b, " world");
+        assert_eq!(scratch.data_chain_len(), 0);
+    }
+
+    #[test]
+    fn alloc_bytes_zeroed() {
+        let alloc = LinearAllocator::new(1024);
+        let scratch = ScopedScratch::new(&alloc);
+
+        let a = scratch.alloc_bytes_zeroed(16);
+        assert_eq!(a, &[0u8; 16]);
+        a[0] = 1;
+        assert_eq!(alloc.used_bytes(), 16);
+        assert_eq!(scratch.data_chain_len(), 0);
+    }
+
+    #[test]
+    fn scope_rewind() {
+        let alloc = LinearAllocator::new(1024);
+        let start_ptr = alloc.peek();
+        {
+            let scratch = ScopedScratch::new(&alloc);
             let _ = scratch.alloc(0u32);
         }
         assert_eq!(start_ptr, alloc.peek());
@@ -186,9 +1483,9 @@ mod tests {
 
     #[test]
     fn new_scope() {
-        let mut alloc = LinearAllocator::new(1024);
+        let alloc = LinearAllocator::new(1024);
         {
-            let scratch = ScopedScratch::new(&mut alloc);
+            let scratch = ScopedScratch::new(&alloc);
             let a = scratch.alloc(0xCAFEBABEu32);
             assert_eq!(*a, 0xCAFEBABEu32);
             {
@@ -202,14 +1499,100 @@ mod tests {
         }
     }
 
+    #[test]
+    fn scope() {
+        let alloc = LinearAllocator::new(1024);
+        {
+            let scratch = ScopedScratch::new(&alloc);
+            let a = scratch.alloc(0xCAFEBABEu32);
+            assert_eq!(*a, 0xCAFEBABEu32);
+            let peek = alloc.peek();
+            let sum = scratch.scope(|scratch2| {
+                let b = scratch2.alloc(0xDEADCAFEu32);
+                assert_eq!(*b, 0xDEADCAFEu32);
+                let c = scratch2.scope(|scratch3| *scratch3.alloc(1u32));
+                *b + c
+            });
+            assert_eq!(sum, 0xDEADCAFFu32);
+            assert_eq!(alloc.peek(), peek);
+            assert_eq!(*a, 0xCAFEBABEu32);
+            let b = scratch.alloc(0xC0FFEEEEu32);
+            assert_eq!(*b, 0xC0FFEEEEu32);
+        }
+    }
+
+    #[test]
+    fn shared_allocator() {
+        let alloc = LinearAllocator::new(1024);
+        {
+            let scratch = ScopedScratch::new(&alloc);
+            let _ = scratch.alloc(0u32);
+        }
+        // The allocator is free for a new root after the previous one is dropped
+        let scratch = ScopedScratch::new(&alloc);
+        let a = scratch.alloc(0xCAFEBABEu32);
+        assert_eq!(*a, 0xCAFEBABEu32);
+    }
+
+    #[should_panic(expected = "Allocator is already used by another root ScopedScratch")]
+    #[test]
+    fn sibling_root() {
+        let alloc = LinearAllocator::new(1024);
+        let _scratch = ScopedScratch::new(&alloc);
+        let _sibling = ScopedScratch::new(&alloc);
+    }
+
+    #[test]
+    fn growable_allocator() {
+        struct A<'a> {
+            dropped: &'a Cell<usize>,
+        }
+        impl Drop for A<'_> {
+            fn drop(&mut self) {
+                self.dropped.set(self.dropped.get() + 1);
+            }
+        }
+
+        let dropped = Cell::new(0);
+        let alloc = GrowableLinearAllocator::new(64);
+        let start_ptr = alloc.peek();
+        {
+            let scratch = ScopedScratch::new(&alloc);
+            let a = scratch.alloc([0xABu8; 60]);
+            {
+                let scratch2 = scratch.new_scope();
+                // Spills over to a new block
+                let b = scratch2.alloc_slice(4, |_| A { dropped: &dropped });
+                assert_eq!(b.len(), 4);
+                assert_eq!(alloc.block_count(), 2);
+            }
+            assert_eq!(dropped.get(), 4);
+            assert_eq!(a[59], 0xAB);
+        }
+        assert_eq!(start_ptr, alloc.peek());
+
+        // The blocks are kept for the next scope
+        let scratch = ScopedScratch::new(&alloc);
+        let _ = scratch.alloc([0u8; 128]);
+        assert_eq!(alloc.block_count(), 2);
+    }
+
+    #[should_panic(expected = "Allocator is already used by another root ScopedScratch")]
+    #[test]
+    fn growable_sibling_root() {
+        let alloc = GrowableLinearAllocator::new(64);
+        let _scratch = ScopedScratch::new(&alloc);
+        let _sibling = ScopedScratch::new(&alloc);
+    }
+
     #[should_panic(
         expected = "Tried to allocate from a ScopedScratch that has an active child scope"
     )]
     #[test]
     fn active_parent_alloc() {
-        let mut alloc = LinearAllocator::new(1024);
+        let alloc = LinearAllocator::new(1024);
         {
-            let scratch = ScopedScratch::new(&mut alloc);
+            let scratch = ScopedScratch::new(&alloc);
             let _ = scratch.alloc(0xCAFEBABEu32);
             {
                 let _scratch2 = scratch.new_scope();
@@ -218,6 +1601,18 @@ mod tests {
         }
     }
 
+    #[cfg(feature = "checked_scopes")]
+    #[should_panic(expected = "Tried to allocate from a ScopedScratch that was already dropped")]
+    #[test]
+    fn dropped_alloc() {
+        let alloc = LinearAllocator::new(1024);
+        let mut scratch = core::mem::ManuallyDrop::new(ScopedScratch::new(&alloc));
+        let _ = scratch.alloc(0xCAFEBABEu32);
+        // Keeps the memory of the scope around after it's dropped
+        unsafe { core::mem::ManuallyDrop::drop(&mut scratch) };
+        let _ = scratch.alloc(0xDEADCAFEu32);
+    }
+
     #[test]
     fn no_drop() {
         #[derive(Clone, Copy)]
@@ -226,9 +1621,9 @@ mod tests {
             data: u32,
         }
 
-        let mut alloc = LinearAllocator::new(1024);
+        let alloc = LinearAllocator::new(1024);
         {
-            let scratch = ScopedScratch::new(&mut alloc);
+            let scratch = ScopedScratch::new(&alloc);
 
             let _ = scratch.alloc(A {
                 data: 0xC0FFEEEEu32,
@@ -244,7 +1639,7 @@ mod tests {
     fn drop_order() {
         struct A<'a> {
             data: u32,
-            dtor_push: &'a mut dyn FnMut(u32) -> (),
+            dtor_push: &'a dyn Fn(u32),
         }
         impl<'a> Drop for A<'a> {
             fn drop(&mut self) {
@@ -252,33 +1647,33 @@ mod tests {
             }
         }
 
-        let mut dtor_data: Vec<u32> = vec![];
-        let mut dtor_push = |v| dtor_data.push(v);
+        let dtor_data: RefCell<Vec<u32>> = RefCell::new(vec![]);
+        let dtor_push = |v| dtor_data.borrow_mut().push(v);
 
-        let mut alloc = LinearAllocator::new(1024);
+        let alloc = LinearAllocator::new(1024);
         {
-            let scratch = ScopedScratch::new(&mut alloc);
+            let scratch = ScopedScratch::new(&alloc);
 
             let _ = scratch.alloc(A {
                 data: 0xCAFEBABEu32,
-                dtor_push: &mut dtor_push,
+                dtor_push: &dtor_push,
             });
             let _ = scratch.alloc(A {
                 data: 0xDEADCAFEu32,
-                dtor_push: &mut dtor_push,
+                dtor_push: &dtor_push,
             });
             assert_eq!(scratch.data_chain_len(), 2);
         }
-        assert_eq!(dtor_data.len(), 2);
-        assert_eq!(dtor_data[0], 0xDEADCAFEu32);
-        assert_eq!(dtor_data[1], 0xCAFEBABEu32);
+        assert_eq!(dtor_data.borrow().len(), 2);
+        assert_eq!(dtor_data.borrow()[0], 0xDEADCAFEu32);
+        assert_eq!(dtor_data.borrow()[1], 0xCAFEBABEu32);
     }
 
     #[test]
-    fn drop_some() {
+    fn extend_lifetime_into() {
         struct A<'a> {
             data: u32,
-            dtor_push: &'a mut dyn FnMut(u32) -> (),
+            dtor_push: &'a dyn Fn(u32),
         }
         impl<'a> Drop for A<'a> {
             fn drop(&mut self) {
@@ -286,37 +1681,1007 @@ mod tests {
             }
         }
 
-        #[derive(Clone, Copy)]
-        #[allow(dead_code)]
-        struct B {
-            data: u32,
+        let dtor_data: RefCell<Vec<u32>> = RefCell::new(vec![]);
+        let dtor_push = |v| dtor_data.borrow_mut().push(v);
+
+        let alloc = LinearAllocator::new(1024);
+        let start = alloc.peek();
+        {
+            let scratch = ScopedScratch::new(&alloc);
+            let _ = scratch.alloc(A {
+                data: 0,
+                dtor_push: &dtor_push,
+            });
+            let kept: *const A;
+            {
+                let scratch2 = scratch.new_scope();
+                kept = scratch2.alloc(A {
+                    data: 1,
+                    dtor_push: &dtor_push,
+                });
+                let _ = scratch2.alloc(A {
+                    data: 2,
+                    dtor_push: &dtor_push,
+                });
+                scratch2.extend_lifetime_into(&scratch);
+            }
+            {
+                let shared = scratch.new_shared_scope();
+                let _ = shared.alloc(A {
+                    data: 3,
+                    dtor_push: &dtor_push,
+                });
+                shared.extend_lifetime_into(&scratch);
+            }
+            // The adopted objects are still alive and the parent is usable
+            assert!(dtor_data.borrow().is_empty());
+            assert_eq!(unsafe { (*kept).data }, 1);
+            // The chain of the child is adopted as a single entry
+            assert_eq!(scratch.registered_dtor_count(), 3);
+            assert_eq!(scratch.registered_dtor_count(), scratch.data_chain_len());
+            let _ = scratch.alloc(A {
+                data: 4,
+                dtor_push: &dtor_push,
+            });
+        }
+        assert_eq!(*dtor_data.borrow(), vec![4, 3, 2, 1, 0]);
+        assert_eq!(alloc.peek(), start);
+    }
+
+    #[test]
+    fn extend_lifetime_into_dtor_count() {
+        let alloc = LinearAllocator::new(1024);
+        let scratch = ScopedScratch::new(&alloc);
+        let _ = scratch.alloc(String::from("parent"));
+        {
+            let scratch2 = scratch.new_scope();
+            let _ = scratch2.alloc(String::from("a"));
+            let _ = scratch2.alloc(String::from("b"));
+            let _ = scratch2.alloc_slice(3, |i| i.to_string());
+            assert_eq!(scratch2.registered_dtor_count(), 3);
+            scratch2.extend_lifetime_into(&scratch);
         }
+        assert_eq!(scratch.registered_dtor_count(), 2);
+        assert_eq!(scratch.registered_dtor_count(), scratch.data_chain_len());
 
-        let mut dtor_data: Vec<u32> = vec![];
-        let mut dtor_push = |v| dtor_data.push(v);
+        // A child without destructors doesn't add an entry
+        let scratch2 = scratch.new_scope();
+        let _ = scratch2.alloc(0u32);
+        scratch2.extend_lifetime_into(&scratch);
+        assert_eq!(scratch.registered_dtor_count(), 2);
+    }
 
-        let mut alloc = LinearAllocator::new(1024);
+    #[test]
+    fn extend_lifetime_into_shared_outer() {
+        let alloc = LinearAllocator::new(1024);
+        let scratch = ScopedScratch::new(&alloc);
+        let _ = scratch.alloc(String::from("root"));
         {
-            let scratch = ScopedScratch::new(&mut alloc);
+            let shared = scratch.new_shared_scope();
+            let _ = shared.alloc(String::from("a"));
+            {
+                let shared2 = shared.new_shared_scope();
+                let _ = shared2.alloc(String::from("b"));
+                let _ = shared2.alloc(String::from("c"));
+                shared2.extend_lifetime_into(&shared);
+            }
+            // The adopted entries are counted in the scope they were handed to
+            assert_eq!(shared.registered_dtor_count(), 3);
+        }
+        // The objects are run by the root, which doesn't count them
+        assert_eq!(scratch.registered_dtor_count(), 1);
+        assert_eq!(scratch.data_chain_len(), 4);
+    }
 
-            let _ = scratch.alloc(A {
-                data: 0xCAFEBABEu32,
-                dtor_push: &mut dtor_push,
-            });
-            let _ = scratch.alloc(B {
-                data: 0xC0FFEEEEu32,
+    #[test]
+    fn extend_lifetime_into_oom() {
+        struct A<'a> {
+            data: u32,
+            dtor_push: &'a dyn Fn(u32),
+        }
+        impl<'a> Drop for A<'a> {
+            fn drop(&mut self) {
+                (self.dtor_push)(self.data);
+            }
+        }
+
+        let dtor_data: RefCell<Vec<u32>> = RefCell::new(vec![]);
+        let dtor_push = |v| dtor_data.borrow_mut().push(v);
+
+        let alloc = LinearAllocator::new(1024);
+        let scratch = ScopedScratch::new(&alloc);
+        let start = alloc.peek();
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let scratch2 = scratch.new_scope();
+            let _ = scratch2.alloc(A {
+                data: 1,
+                dtor_push: &dtor_push,
             });
+            // Leave no room for the entry that adopts the chain
+            let _ = scratch2.alloc_bytes_zeroed(alloc.remaining_bytes());
+            scratch2.extend_lifetime_into(&scratch);
+        }));
+        assert!(result.is_err());
+        // The child was dropped normally, so its objects and memory are released
+        assert_eq!(*dtor_data.borrow(), vec![1]);
+        assert_eq!(alloc.peek(), start);
+        assert_eq!(scratch.registered_dtor_count(), 0);
+        // The parent is unlocked
+        let _ = scratch.alloc(0u32);
+    }
+
+    #[should_panic(
+        expected = "Tried to extend the lifetime of a ScopedScratch into a scope other than its parent"
+    )]
+    #[test]
+    fn extend_lifetime_into_grandparent() {
+        let alloc = LinearAllocator::new(1024);
+        let scratch = ScopedScratch::new(&alloc);
+        let scratch2 = scratch.new_scope();
+        let scratch3 = scratch2.new_scope();
+        scratch3.extend_lifetime_into(&scratch);
+    }
+
+    #[test]
+    fn shared_scope_drop_order() {
+        struct A<'a> {
+            data: u32,
+            dtor_push: &'a dyn Fn(u32),
+        }
+        impl<'a> Drop for A<'a> {
+            fn drop(&mut self) {
+                (self.dtor_push)(self.data);
+            }
+        }
+
+        let dtor_data: RefCell<Vec<u32>> = RefCell::new(vec![]);
+        let dtor_push = |v| dtor_data.borrow_mut().push(v);
+
+        let alloc = LinearAllocator::new(1024);
+        let start = alloc.peek();
+        {
+            let scratch = ScopedScratch::new(&alloc);
             let _ = scratch.alloc(A {
-                data: 0xDEADCAFEu32,
-                dtor_push: &mut dtor_push,
+                data: 0,
+                dtor_push: &dtor_push,
             });
-            let _ = scratch.alloc(B {
-                data: 0xDEADC0DEu32,
+            {
+                let shared = scratch.new_shared_scope();
+                let _ = shared.alloc(A {
+                    data: 1,
+                    dtor_push: &dtor_push,
+                });
+                {
+                    let shared2 = shared.new_shared_scope();
+                    let _ = shared2.alloc(A {
+                        data: 2,
+                        dtor_push: &dtor_push,
+                    });
+                }
+                let _ = shared.alloc(A {
+                    data: 3,
+                    dtor_push: &dtor_push,
+                });
+                assert_eq!(shared.registered_dtor_count(), 2);
+            }
+            // The objects and memory of the shared scopes are kept
+            assert!(dtor_data.borrow().is_empty());
+            assert_eq!(scratch.data_chain_len(), 4);
+            let peek = alloc.peek();
+            let _ = scratch.alloc(A {
+                data: 4,
+                dtor_push: &dtor_push,
             });
-            assert_eq!(scratch.data_chain_len(), 2);
+            assert!(alloc.peek() > peek);
         }
-        assert_eq!(dtor_data.len(), 2);
-        assert_eq!(dtor_data[0], 0xDEADCAFEu32);
-        assert_eq!(dtor_data[1], 0xCAFEBABEu32);
+        assert_eq!(*dtor_data.borrow(), vec![4, 3, 2, 1, 0]);
+        assert_eq!(alloc.peek(), start);
+    }
+
+    #[test]
+    fn shared_scope_drop_all() {
+        struct A<'a> {
+            data: u32,
+            dtor_push: &'a dyn Fn(u32),
+        }
+        impl<'a> Drop for A<'a> {
+            fn drop(&mut self) {
+                (self.dtor_push)(self.data);
+            }
+        }
+
+        let dtor_data: RefCell<Vec<u32>> = RefCell::new(vec![]);
+        let dtor_push = |v| dtor_data.borrow_mut().push(v);
+
+        let alloc = LinearAllocator::new(1024);
+        {
+            let scratch = ScopedScratch::new(&alloc);
+            let _ = scratch.alloc(A {
+                data: 0,
+                dtor_push: &dtor_push,
+            });
+            let peek = alloc.peek();
+            {
+                let mut shared = scratch.new_shared_scope();
+                let _ = shared.alloc(A {
+                    data: 1,
+                    dtor_push: &dtor_push,
+                });
+                // Only drops the objects of the shared scope
+                shared.drop_all();
+                assert_eq!(*dtor_data.borrow(), vec![1]);
+                assert_eq!(alloc.peek(), peek);
+            }
+            assert_eq!(scratch.data_chain_len(), 1);
+        }
+        assert_eq!(*dtor_data.borrow(), vec![1, 0]);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn panicking_drop() {
+        struct A<'a> {
+            data: u32,
+            dtor_push: &'a dyn Fn(u32),
+        }
+        impl<'a> Drop for A<'a> {
+            fn drop(&mut self) {
+                (self.dtor_push)(self.data);
+                if self.data == 1 {
+                    panic!("A::drop");
+                }
+            }
+        }
+
+        let dtor_data: RefCell<Vec<u32>> = RefCell::new(vec![]);
+        let dtor_push = |v| dtor_data.borrow_mut().push(v);
+
+        let alloc = LinearAllocator::new(1024);
+        let start = alloc.peek();
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let scratch = ScopedScratch::new(&alloc);
+            for i in 0..3 {
+                let _ = scratch.alloc(A {
+                    data: i,
+                    dtor_push: &dtor_push,
+                });
+            }
+        }));
+        assert_eq!(
+            *result.unwrap_err().downcast_ref::<&'static str>().unwrap(),
+            "A::drop"
+        );
+        assert_eq!(*dtor_data.borrow(), vec![2, 1, 0]);
+        assert_eq!(alloc.peek(), start);
+        // The allocator is released for a new root scope
+        let _scratch = ScopedScratch::new(&alloc);
+    }
+
+    #[test]
+    fn checkpoint() {
+        struct A<'a> {
+            data: u32,
+            dtor_push: &'a dyn Fn(u32),
+        }
+        impl<'a> Drop for A<'a> {
+            fn drop(&mut self) {
+                (self.dtor_push)(self.data);
+            }
+        }
+
+        let dtor_data: RefCell<Vec<u32>> = RefCell::new(vec![]);
+        let dtor_push = |v| dtor_data.borrow_mut().push(v);
+
+        let alloc = LinearAllocator::new(1024);
+        {
+            let mut scratch = ScopedScratch::new(&alloc);
+            let _ = scratch.alloc(A {
+                data: 0,
+                dtor_push: &dtor_push,
+            });
+
+            let mut checkpoint = scratch.checkpoint();
+            let start = alloc.peek();
+            for i in 0..3 {
+                let _ = checkpoint.alloc(A {
+                    data: 10 * i + 1,
+                    dtor_push: &dtor_push,
+                });
+                let _ = checkpoint.alloc(0u64);
+                let _ = checkpoint.alloc(A {
+                    data: 10 * i + 2,
+                    dtor_push: &dtor_push,
+                });
+                assert_eq!(checkpoint.registered_dtor_count(), 3);
+                checkpoint.rewind();
+                assert_eq!(alloc.peek(), start);
+                assert_eq!(checkpoint.data_chain_len(), 1);
+                assert_eq!(checkpoint.registered_dtor_count(), 1);
+            }
+            assert_eq!(*dtor_data.borrow(), vec![2, 1, 12, 11, 22, 21]);
+
+            // Allocations after the checkpoint that aren't rewound stay in the scope
+            let _ = checkpoint.alloc(A {
+                data: 3,
+                dtor_push: &dtor_push,
+            });
+        }
+        assert_eq!(*dtor_data.borrow(), vec![2, 1, 12, 11, 22, 21, 3, 0]);
+    }
+
+    #[test]
+    fn drop_all() {
+        struct A<'a> {
+            data: u32,
+            dtor_push: &'a dyn Fn(u32),
+        }
+        impl<'a> Drop for A<'a> {
+            fn drop(&mut self) {
+                (self.dtor_push)(self.data);
+            }
+        }
+
+        let dtor_data: RefCell<Vec<u32>> = RefCell::new(vec![]);
+        let dtor_push = |v| dtor_data.borrow_mut().push(v);
+
+        let alloc = LinearAllocator::new(1024);
+        let start = alloc.peek();
+        {
+            let mut scratch = ScopedScratch::new(&alloc);
+            let _ = scratch.alloc(A {
+                data: 0,
+                dtor_push: &dtor_push,
+            });
+            let _ = scratch.alloc(A {
+                data: 1,
+                dtor_push: &dtor_push,
+            });
+
+            scratch.drop_all();
+            assert_eq!(*dtor_data.borrow(), vec![1, 0]);
+            assert_eq!(alloc.peek(), start);
+            assert_eq!(scratch.registered_dtor_count(), 0);
+
+            // Calling again is a no-op
+            scratch.drop_all();
+            assert_eq!(*dtor_data.borrow(), vec![1, 0]);
+
+            let _ = scratch.alloc(A {
+                data: 2,
+                dtor_push: &dtor_push,
+            });
+        }
+        assert_eq!(*dtor_data.borrow(), vec![1, 0, 2]);
+        assert_eq!(alloc.peek(), start);
+    }
+
+    #[test]
+    fn reset() {
+        struct A<'a> {
+            data: u32,
+            dtor_push: &'a dyn Fn(u32),
+        }
+        impl<'a> Drop for A<'a> {
+            fn drop(&mut self) {
+                (self.dtor_push)(self.data);
+            }
+        }
+
+        let dtor_data: RefCell<Vec<u32>> = RefCell::new(vec![]);
+        let dtor_push = |v| dtor_data.borrow_mut().push(v);
+
+        let alloc = LinearAllocator::new(1024);
+        let start = alloc.peek();
+        {
+            let mut scratch = ScopedScratch::new(&alloc);
+            for batch in 0..3 {
+                let _ = scratch.alloc(A {
+                    data: batch,
+                    dtor_push: &dtor_push,
+                });
+                let _ = scratch.alloc(0u64);
+                assert_eq!(scratch.alloc_count(), 2);
+
+                scratch.reset();
+                assert_eq!(scratch.alloc_count(), 0);
+                assert_eq!(scratch.registered_dtor_count(), 0);
+                assert_eq!(alloc.peek(), start);
+            }
+            assert_eq!(*dtor_data.borrow(), vec![0, 1, 2]);
+        }
+        assert_eq!(*dtor_data.borrow(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn drop_some() {
+        struct A<'a> {
+            data: u32,
+            dtor_push: &'a dyn Fn(u32),
+        }
+        impl<'a> Drop for A<'a> {
+            fn drop(&mut self) {
+                (self.dtor_push)(self.data);
+            }
+        }
+
+        #[derive(Clone, Copy)]
+        #[allow(dead_code)]
+        struct B {
+            data: u32,
+        }
+
+        let dtor_data: RefCell<Vec<u32>> = RefCell::new(vec![]);
+        let dtor_push = |v| dtor_data.borrow_mut().push(v);
+
+        let alloc = LinearAllocator::new(1024);
+        {
+            let scratch = ScopedScratch::new(&alloc);
+
+            let _ = scratch.alloc(A {
+                data: 0xCAFEBABEu32,
+                dtor_push: &dtor_push,
+            });
+            let _ = scratch.alloc(B {
+                data: 0xC0FFEEEEu32,
+            });
+            let _ = scratch.alloc(A {
+                data: 0xDEADCAFEu32,
+                dtor_push: &dtor_push,
+            });
+            let _ = scratch.alloc(B {
+                data: 0xDEADC0DEu32,
+            });
+            assert_eq!(scratch.data_chain_len(), 2);
+        }
+        assert_eq!(dtor_data.borrow().len(), 2);
+        assert_eq!(dtor_data.borrow()[0], 0xDEADCAFEu32);
+        assert_eq!(dtor_data.borrow()[1], 0xCAFEBABEu32);
+    }
+
+    #[test]
+    fn alloc_enum() {
+        struct A<'a> {
+            dropped: &'a Cell<usize>,
+        }
+        impl Drop for A<'_> {
+            fn drop(&mut self) {
+                self.dropped.set(self.dropped.get() + 1);
+            }
+        }
+        fn compute(ok: bool, dropped: &Cell<usize>) -> Result<A<'_>, A<'_>> {
+            if ok {
+                Ok(A { dropped })
+            } else {
+                Err(A { dropped })
+            }
+        }
+
+        let ok_dropped = Cell::new(0);
+        let err_dropped = Cell::new(0);
+        let alloc = LinearAllocator::new(1024);
+        {
+            let scratch = ScopedScratch::new(&alloc);
+
+            let a = scratch.alloc(compute(true, &ok_dropped));
+            let b = scratch.alloc(compute(false, &err_dropped));
+            let c = scratch.alloc(Some(A {
+                dropped: &ok_dropped,
+            }));
+            let d: &mut Option<A> = scratch.alloc(None);
+            assert!(a.is_ok() && b.is_err() && c.is_some() && d.is_none());
+            assert_eq!(scratch.data_chain_len(), 4);
+
+            let e: &mut Result<String, String> = scratch.alloc(Ok(String::from("ok")));
+            let f: &mut Result<String, String> = scratch.alloc(Err(String::from("err")));
+            assert_eq!(e.as_deref(), Ok("ok"));
+            assert_eq!(f.as_deref().unwrap_err(), "err");
+        }
+        // The values in whichever variant is held are dropped with the scope
+        assert_eq!(ok_dropped.get(), 2);
+        assert_eq!(err_dropped.get(), 1);
+    }
+
+    #[test]
+    fn try_alloc_oom() {
+        struct A<'a> {
+            data: [u32; 8],
+            dtor_push: &'a dyn Fn(u32),
+        }
+        impl<'a> Drop for A<'a> {
+            fn drop(&mut self) {
+                (self.dtor_push)(self.data[0]);
+            }
+        }
+
+        let dtor_data: RefCell<Vec<u32>> = RefCell::new(vec![]);
+        let dtor_push = |v| dtor_data.borrow_mut().push(v);
+
+        let alloc = LinearAllocator::new(128);
+        {
+            let scratch = ScopedScratch::new(&alloc);
+
+            assert!(scratch
+                .try_alloc(A {
+                    data: [0xCAFEBABEu32; 8],
+                    dtor_push: &dtor_push,
+                })
+                .is_ok());
+            assert_eq!(scratch.data_chain_len(), 1);
+
+            // There's room for the ScopeData but not the object itself
+            let peek = scratch.allocator.peek();
+            assert!(scratch
+                .try_alloc(A {
+                    data: [0xDEADCAFEu32; 8],
+                    dtor_push: &dtor_push,
+                })
+                .is_err());
+            assert_eq!(scratch.allocator.peek(), peek);
+            assert_eq!(scratch.data_chain_len(), 1);
+        }
+        // The failed object is dropped immediately, the allocated one with the scope
+        assert_eq!(dtor_data.borrow().len(), 2);
+        assert_eq!(dtor_data.borrow()[0], 0xDEADCAFEu32);
+        assert_eq!(dtor_data.borrow()[1], 0xCAFEBABEu32);
+    }
+
+    #[test]
+    fn try_alloc_propagate() {
+        fn build<'a>(
+            scratch: &'a ScopedScratch<'_, '_>,
+            len: usize,
+        ) -> Result<&'a mut [u64], AllocationError> {
+            let header = scratch.try_alloc(String::from("header"))?;
+            let values = scratch.try_alloc_with(|| [0u64; 8])?;
+            let _ = scratch.try_alloc(len)?;
+            assert_eq!(header, "header");
+            Ok(&mut values[..len])
+        }
+
+        let alloc = LinearAllocator::new(200);
+        let scratch = ScopedScratch::new(&alloc);
+        assert_eq!(build(&scratch, 4).unwrap().len(), 4);
+        // The first allocations fit, the error comes from a later one
+        assert!(matches!(
+            build(&scratch, 4),
+            Err(AllocationError::OutOfMemory { .. })
+        ));
+    }
+
+    #[test]
+    fn try_alloc_oom_no_room_for_data() {
+        struct A {
+            _data: u32,
+        }
+        impl Drop for A {
+            fn drop(&mut self) {}
+        }
+
+        let alloc = LinearAllocator::new(64);
+        let scratch = ScopedScratch::new(&alloc);
+
+        assert!(scratch.try_alloc([0u8; 60]).is_ok());
+        assert!(scratch.try_alloc([0u8; 8]).is_err());
+
+        // Neither the ScopeData nor the object fit
+        let peek = scratch.allocator.peek();
+        assert!(scratch.try_alloc(A { _data: 0 }).ok().is_none());
+        assert_eq!(scratch.allocator.peek(), peek);
+        assert_eq!(scratch.data_chain_len(), 0);
+
+        assert!(scratch.try_alloc(0u32).is_ok());
+    }
+
+    #[test]
+    fn alloc_with() {
+        struct A<'a> {
+            data: [u32; 8],
+            dtor_push: &'a dyn Fn(u32),
+        }
+        impl<'a> Drop for A<'a> {
+            fn drop(&mut self) {
+                (self.dtor_push)(self.data[0]);
+            }
+        }
+
+        let dtor_data: RefCell<Vec<u32>> = RefCell::new(vec![]);
+        let dtor_push = |v| dtor_data.borrow_mut().push(v);
+
+        let alloc = LinearAllocator::new(128);
+        {
+            let scratch = ScopedScratch::new(&alloc);
+
+            let a = scratch.alloc_with(|| A {
+                data: [0xCAFEBABEu32; 8],
+                dtor_push: &dtor_push,
+            });
+            assert_eq!(a.data[7], 0xCAFEBABEu32);
+            assert_eq!(scratch.data_chain_len(), 1);
+
+            // There's room for the ScopeData but not the object itself
+            let peek = scratch.allocator.peek();
+            let called = Cell::new(false);
+            assert!(scratch
+                .try_alloc_with(|| {
+                    called.set(true);
+                    A {
+                        data: [0xDEADCAFEu32; 8],
+                        dtor_push: &dtor_push,
+                    }
+                })
+                .is_err());
+            assert!(!called.get());
+            assert_eq!(scratch.allocator.peek(), peek);
+            assert_eq!(scratch.data_chain_len(), 1);
+        }
+        assert_eq!(dtor_data.borrow().len(), 1);
+        assert_eq!(dtor_data.borrow()[0], 0xCAFEBABEu32);
+    }
+
+    #[test]
+    fn bytes_used() {
+        let alloc = LinearAllocator::new(1024);
+        let _ = alloc.alloc_internal(0u8);
+        let scratch = ScopedScratch::new(&alloc);
+        assert_eq!(scratch.bytes_used(), 0);
+
+        let _ = scratch.alloc([0u32; 4]);
+        let outer = scratch.bytes_used();
+        // Includes the padding after the allocation made before the scope
+        assert_eq!(outer, 3 + 16);
+        {
+            let scratch2 = scratch.new_scope();
+            let _ = scratch2.alloc([0u8; 32]);
+            assert_eq!(scratch2.bytes_used(), 32);
+            assert_eq!(scratch.bytes_used(), outer + 32);
+        }
+        assert_eq!(scratch.bytes_used(), outer);
+    }
+
+    #[test]
+    fn is_last() {
+        let alloc = LinearAllocator::new(1024);
+        let scratch = ScopedScratch::new(&alloc);
+
+        let a = scratch.alloc(0xCAFEBABEu32);
+        assert!(scratch.is_last(a));
+        let b = scratch.alloc_slice(3, |i| i as u8);
+        assert!(!scratch.is_last(a));
+        assert!(scratch.is_last(b));
+        // A part of the last allocation doesn't end at the free block
+        assert!(!scratch.is_last(&b[0]));
+        let c = scratch.alloc(());
+        assert!(!scratch.is_last(c));
+        assert!(scratch.is_last(b));
+    }
+
+    #[test]
+    fn assert_no_pending_dtors() {
+        let alloc = LinearAllocator::new(1024);
+        let scratch = ScopedScratch::new(&alloc);
+        scratch.assert_no_pending_dtors();
+        let _ = scratch.alloc(0u32);
+        scratch.assert_no_pending_dtors();
+    }
+
+    #[should_panic(expected = "Expected no destructors to be registered in the ScopedScratch")]
+    #[test]
+    fn assert_no_pending_dtors_fail() {
+        let alloc = LinearAllocator::new(1024);
+        let scratch = ScopedScratch::new(&alloc);
+        let _ = scratch.alloc(vec![0u32]);
+        scratch.assert_no_pending_dtors();
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    fn registered_objects() {
+        let alloc = LinearAllocator::new(1024);
+        let scratch = ScopedScratch::new(&alloc);
+
+        let a = scratch.alloc(vec![0u32]);
+        let _ = scratch.alloc(0u32);
+        let b = scratch.alloc_slice(2, |_| String::new());
+        let expected = [b.as_mut_ptr() as *mut u8, a as *mut Vec<u32> as *mut u8];
+        assert!(scratch.registered_objects().eq(expected));
+
+        // Shared scopes only list their own objects
+        let shared = scratch.new_shared_scope();
+        let c = shared.alloc(vec![0u8]) as *mut Vec<u8> as *mut u8;
+        assert!(shared.registered_objects().eq([c]));
+    }
+
+    #[test]
+    fn realloc_last() {
+        let alloc = LinearAllocator::new(1024);
+        let scratch = ScopedScratch::new(&alloc);
+
+        let a = scratch.alloc_slice(4, |i| i as u32);
+        let start = a.as_ptr();
+        let a = unsafe { scratch.realloc_last(a, 8) };
+        // Grown in place
+        assert_eq!(a.len(), 8);
+        assert_eq!(a.as_ptr() as *const u32, start);
+        assert_eq!(alloc.peek(), start.wrapping_add(8) as *mut u8);
+        for (i, v) in a.iter_mut().enumerate().skip(4) {
+            v.write(i as u32);
+        }
+        let a = unsafe { &mut *(a as *mut [MaybeUninit<u32>] as *mut [u32]) };
+
+        // Shrunk in place
+        let a = unsafe { scratch.realloc_last(a, 6) };
+        assert_eq!(a.len(), 6);
+        assert_eq!(alloc.peek(), start.wrapping_add(6) as *mut u8);
+        let a = unsafe { &mut *(a as *mut [MaybeUninit<u32>] as *mut [u32]) };
+
+        // Relocated since it's not the last allocation anymore
+        let b = scratch.alloc(0xABu8);
+        let c = unsafe { scratch.realloc_last(a, 10) };
+        assert_eq!(c.len(), 10);
+        assert_ne!(c.as_ptr() as *const u32, start);
+        assert_eq!(*b, 0xABu8);
+        let c = unsafe { &*(&c[..6] as *const [MaybeUninit<u32>] as *const [u32]) };
+        assert_eq!(c, &[0, 1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn realloc_last_grow_down() {
+        let alloc = LinearAllocator::with_direction(1024, crate::Direction::Down);
+        let scratch = ScopedScratch::new(&alloc);
+
+        // The scope can't grow in place downwards, so the objects are relocated
+        let a = scratch.alloc_slice(4, |i| i as u32);
+        assert!(!scratch.is_last(a));
+        assert!(alloc.is_last(a));
+        let start = a.as_ptr();
+        let a = unsafe { scratch.realloc_last(a, 8) };
+        assert_eq!(a.len(), 8);
+        assert_ne!(a.as_ptr() as *const u32, start);
+        for (i, v) in a.iter().take(4).enumerate() {
+            assert_eq!(unsafe { v.assume_init() }, i as u32);
+        }
+    }
+
+    #[test]
+    fn realloc_last_growable() {
+        let alloc = GrowableLinearAllocator::new(64);
+        let scratch = ScopedScratch::new(&alloc);
+
+        let a = scratch.alloc_slice(8, |i| i as u32);
+        // Doesn't fit in the first block, so it's moved to the next one
+        let a = unsafe { scratch.realloc_last(a, 32) };
+        assert_eq!(alloc.block_count(), 2);
+        assert!(scratch.is_last(a));
+        let a = unsafe { &*(&a[..8] as *const [MaybeUninit<u32>] as *const [u32]) };
+        assert_eq!(a, &[0, 1, 2, 3, 4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn free_last() {
+        struct A<'a> {
+            data: u32,
+            dtor_push: &'a dyn Fn(u32),
+        }
+        impl<'a> Drop for A<'a> {
+            fn drop(&mut self) {
+                (self.dtor_push)(self.data);
+            }
+        }
+
+        let dtor_data: RefCell<Vec<u32>> = RefCell::new(vec![]);
+        let dtor_push = |v| dtor_data.borrow_mut().push(v);
+
+        let alloc = LinearAllocator::new(1024);
+        {
+            let scratch = ScopedScratch::new(&alloc);
+
+            let a = scratch.alloc(A {
+                data: 0xCAFEBABEu32,
+                dtor_push: &dtor_push,
+            });
+            let peek = alloc.peek();
+            let b = scratch.alloc(0u8);
+            unsafe { scratch.free_last(b) };
+            assert_eq!(alloc.peek(), peek);
+
+            // The chain entry is freed with the object
+            let c = scratch.alloc(A {
+                data: 0xDEADCAFEu32,
+                dtor_push: &dtor_push,
+            });
+            assert_eq!(scratch.registered_dtor_count(), 2);
+            unsafe { scratch.free_last(c) };
+            assert_eq!(alloc.peek(), peek);
+            assert_eq!(scratch.registered_dtor_count(), 1);
+            assert_eq!(*dtor_data.borrow(), vec![0xDEADCAFEu32]);
+
+            let d = scratch.alloc_slice(4, |i| A {
+                data: i as u32,
+                dtor_push: &dtor_push,
+            });
+            unsafe { scratch.free_last(d) };
+            assert_eq!(alloc.peek(), peek);
+            assert_eq!(scratch.data_chain_len(), 1);
+            assert_eq!(*dtor_data.borrow(), vec![0xDEADCAFEu32, 0, 1, 2, 3]);
+
+            assert_eq!(a.data, 0xCAFEBABEu32);
+        }
+        assert_eq!(dtor_data.borrow().len(), 6);
+        assert_eq!(dtor_data.borrow()[5], 0xCAFEBABEu32);
+    }
+
+    #[should_panic(
+        expected = "Tried to free an object that isn't the last allocation of the ScopedScratch"
+    )]
+    #[test]
+    fn free_last_not_last() {
+        let alloc = LinearAllocator::new(1024);
+        let scratch = ScopedScratch::new(&alloc);
+
+        let a = scratch.alloc(0u32);
+        let _ = scratch.alloc(0u32);
+        unsafe { scratch.free_last(a) };
+    }
+
+    #[test]
+    fn alloc_slice() {
+        struct A<'a> {
+            data: u32,
+            dtor_push: &'a dyn Fn(u32),
+        }
+        impl<'a> Drop for A<'a> {
+            fn drop(&mut self) {
+                (self.dtor_push)(self.data);
+            }
+        }
+
+        let dtor_data: RefCell<Vec<u32>> = RefCell::new(vec![]);
+        let dtor_push = |v| dtor_data.borrow_mut().push(v);
+
+        let alloc = LinearAllocator::new(1024);
+        {
+            let scratch = ScopedScratch::new(&alloc);
+
+            let a = scratch.alloc_slice(8, |i| A {
+                data: i as u32,
+                dtor_push: &dtor_push,
+            });
+            assert_eq!(a.len(), 8);
+            assert_eq!(a[7].data, 7);
+            assert_eq!(scratch.data_chain_len(), 1);
+
+            let b = scratch.alloc_slice(4, |i| i as u32);
+            assert_eq!(b, &[0, 1, 2, 3]);
+            assert_eq!(scratch.data_chain_len(), 1);
+            assert!(dtor_data.borrow().is_empty());
+        }
+        assert_eq!(*dtor_data.borrow(), (0..8).collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn alloc_slice_fill_copy() {
+        let alloc = LinearAllocator::new(1 << 16);
+        let scratch = ScopedScratch::new(&alloc);
+
+        let a = scratch.alloc_slice_fill_copy(3, 0xCAFEBABEu32);
+        assert_eq!(a, &[0xCAFEBABEu32; 3]);
+        let b = scratch.alloc_slice_fill_copy(10000, 0xABu8);
+        assert_eq!(b.len(), 10000);
+        assert!(b.iter().all(|&v| v == 0xABu8));
+        let c = scratch.alloc_slice_fill_copy(0, 0u64);
+        assert!(c.is_empty());
+        assert_eq!(scratch.data_chain_len(), 0);
+    }
+
+    #[test]
+    fn alloc_array() {
+        let value = std::rc::Rc::new(0xC0FFEEEEu32);
+
+        let alloc = LinearAllocator::new(1024);
+        {
+            let scratch = ScopedScratch::new(&alloc);
+
+            let a: &mut [u32; 16] = scratch.alloc_array(|i| i as u32);
+            assert_eq!(a[15], 15);
+            assert_eq!(scratch.data_chain_len(), 0);
+
+            let b: &mut [std::rc::Rc<u32>; 4] = scratch.alloc_array(|_| value.clone());
+            assert_eq!(*b[3], 0xC0FFEEEEu32);
+            assert_eq!(std::rc::Rc::strong_count(&value), 5);
+            assert_eq!(scratch.data_chain_len(), 1);
+        }
+        assert_eq!(std::rc::Rc::strong_count(&value), 1);
+    }
+
+    #[test]
+    fn alloc_slice_fill_clone() {
+        let value = std::rc::Rc::new(0xC0FFEEEEu32);
+
+        let alloc = LinearAllocator::new(1 << 16);
+        {
+            let scratch = ScopedScratch::new(&alloc);
+
+            let a = scratch.alloc_slice_fill_clone(3, &value);
+            assert_eq!(a.len(), 3);
+            assert!(a.iter().all(|v| **v == 0xC0FFEEEEu32));
+            let b = scratch.alloc_slice_fill_clone(1000, &value);
+            assert_eq!(b.len(), 1000);
+            assert_eq!(std::rc::Rc::strong_count(&value), 1004);
+            let c = scratch.alloc_slice_fill_clone(0, &value);
+            assert!(c.is_empty());
+            assert_eq!(scratch.data_chain_len(), 2);
+        }
+        assert_eq!(std::rc::Rc::strong_count(&value), 1);
+    }
+
+    #[test]
+    fn alloc_slice_default() {
+        let alloc = LinearAllocator::new(1024);
+        {
+            let scratch = ScopedScratch::new(&alloc);
+
+            let a = scratch.alloc_slice_default::<String>(4);
+            assert!(a.iter().all(|s| s.is_empty()));
+            a[3].push_str("heap allocated");
+            assert_eq!(scratch.data_chain_len(), 1);
+
+            let b = scratch.alloc_slice_default::<u32>(4);
+            assert_eq!(b, [0; 4]);
+            assert_eq!(scratch.data_chain_len(), 1);
+        }
+        alloc.assert_empty();
+    }
+
+    #[test]
+    fn alloc_clone() {
+        let strings = vec![String::from("a"), String::from("bc"), String::from("def")];
+
+        let alloc = LinearAllocator::new(1024);
+        let scratch = ScopedScratch::new(&alloc);
+
+        let a = scratch.alloc_clone(&strings[1]);
+        assert_eq!(a, "bc");
+        let b = scratch.alloc_slice_clone(&strings);
+        assert_eq!(b, &strings[..]);
+        assert_ne!(b[0].as_ptr(), strings[0].as_ptr());
+        b[2].push('g');
+        assert_eq!(strings[2], "def");
+        assert!(scratch.alloc_slice_clone::<String>(&[]).is_empty());
+        assert_eq!(scratch.data_chain_len(), 2);
+    }
+
+    #[test]
+    fn alloc_from_iter() {
+        let value = std::rc::Rc::new(0xC0FFEEEEu32);
+
+        let alloc = LinearAllocator::new(1024);
+        {
+            let scratch = ScopedScratch::new(&alloc);
+
+            let a = scratch.alloc_from_iter((0..3).map(|_| value.clone()), 10);
+            assert_eq!(a.len(), 3);
+            assert_eq!(std::rc::Rc::strong_count(&value), 4);
+            assert_eq!(scratch.data_chain_len(), 1);
+            // Unused slots are given back
+            assert_eq!(
+                scratch.allocator.peek(),
+                a.as_mut_ptr_range().end as *mut u8
+            );
+
+            let b = scratch.alloc_from_iter(0u32..4, 4);
+            assert_eq!(b, &[0, 1, 2, 3]);
+            let c = scratch.alloc_from_iter(std::iter::empty::<u32>(), 0);
+            assert!(c.is_empty());
+            assert_eq!(scratch.data_chain_len(), 1);
+        }
+        assert_eq!(std::rc::Rc::strong_count(&value), 1);
+    }
+
+    #[should_panic(expected = "Iterator yielded more than max_len = 2 items")]
+    #[test]
+    fn alloc_from_iter_too_long() {
+        let alloc = LinearAllocator::new(1024);
+        let scratch = ScopedScratch::new(&alloc);
+        let _ = scratch.alloc_from_iter(0u32..3, 2);
     }
 }
@@ -0,0 +1,296 @@
+use crate::{error::AllocationError, linear_allocator::LinearAllocatorInternal};
+
+use core::{
+    alloc::Layout,
+    cell::{Cell, UnsafeCell},
+    mem::MaybeUninit,
+    ptr::NonNull,
+};
+
+/// A linear allocator that holds its block of `N` bytes inline instead of on
+/// the heap, for small scratch arenas where the allocation of the block itself
+/// would dominate. Allocations borrow the allocator so it can't be moved while
+/// they are alive.
+pub struct StackAllocator<const N: usize> {
+    buffer: UnsafeCell<[MaybeUninit<u8>; N]>,
+    // Offset of the start of the free block from the start of the buffer
+    next_alloc: Cell<usize>,
+    // Set while a root ScopedScratch is alive, as it assumes to be the only user
+    scoped: Cell<bool>,
+}
+
+impl<const N: usize> StackAllocator<N> {
+    /// Creates an allocator with an inline block of `N` bytes
+    pub fn new() -> Self {
+        Self {
+            buffer: UnsafeCell::new([MaybeUninit::uninit(); N]),
+            next_alloc: Cell::new(0),
+            scoped: Cell::new(false),
+        }
+    }
+
+    /// Returns the size of the block in bytes
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Returns the number of bytes allocated, including alignment padding
+    pub fn used_bytes(&self) -> usize {
+        self.next_alloc.get()
+    }
+
+    /// Rewinds the allocator back to the start of the block.
+    ///
+    /// **This does NOT run destructors.**
+    pub fn reset(&mut self) {
+        self.next_alloc.set(0);
+    }
+
+    fn block_start(&self) -> *mut u8 {
+        self.buffer.get() as *mut u8
+    }
+
+    // Bumps the free block by size_bytes aligned at alignment, returning the
+    // start of the allocation
+    fn try_bump(&self, size_bytes: usize, alignment: usize) -> Result<*mut u8, AllocationError> {
+        let previous_size = self.next_alloc.get();
+        // Safety:
+        // - previous_size is at most N, so the pointer is within the buffer or
+        //   one byte past it
+        let next_alloc = unsafe { self.block_start().add(previous_size) };
+        let align_offset = next_alloc.align_offset(alignment);
+        assert_ne!(align_offset, usize::MAX);
+
+        let remaining_bytes = N - previous_size;
+        if align_offset
+            .checked_add(size_bytes)
+            .is_none_or(|size| size > remaining_bytes)
+        {
+            return Err(AllocationError::OutOfMemory {
+                size_bytes,
+                alignment,
+                padding_bytes: align_offset,
+                remaining_bytes,
+            });
+        }
+
+        self.next_alloc
+            .set(previous_size + align_offset + size_bytes);
+        // Safety:
+        // - We just verified that the aligned allocation fits the buffer
+        Ok(unsafe { next_alloc.add(align_offset) })
+    }
+}
+
+impl<const N: usize> Default for StackAllocator<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> LinearAllocatorInternal for StackAllocator<N> {
+    #[allow(clippy::mut_from_ref)]
+    fn try_alloc_aligned<T: Sized>(&self, obj: T, align: usize) -> Result<&mut T, AllocationError> {
+        assert!(align.is_power_of_two(), "align has to be a power of two");
+        let alignment = core::mem::align_of::<T>().max(align);
+
+        // Zero-sized types don't need memory, see LinearAllocator
+        if core::mem::size_of::<T>() == 0 {
+            let t_ptr = if alignment == core::mem::align_of::<T>() {
+                NonNull::<T>::dangling().as_ptr()
+            } else {
+                core::ptr::without_provenance_mut::<T>(alignment)
+            };
+            // Safety:
+            // - A dangling pointer is well-aligned and non-null, which is
+            //   enough for reads and writes of zero-sized types
+            unsafe {
+                t_ptr.write(obj);
+                return Ok(&mut *t_ptr);
+            }
+        }
+
+        let new_alloc = self.try_bump(core::mem::size_of::<T>(), alignment)?;
+
+        // Safety:
+        // - new_alloc points to at least size_of::<T>() bytes of the buffer that
+        //   aren't referenced by earlier allocations, and is aligned for T
+        unsafe {
+            let t_ptr = new_alloc as *mut T;
+            t_ptr.write(obj);
+            Ok(&mut *t_ptr)
+        }
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    fn try_alloc_slice_uninit<T: Sized>(
+        &self,
+        len: usize,
+    ) -> Result<&mut [MaybeUninit<T>], AllocationError> {
+        if len == 0 {
+            return Ok(&mut []);
+        }
+        if core::mem::size_of::<T>() == 0 {
+            // Safety:
+            // - A dangling pointer is well-aligned and non-null, and the slice
+            //   spans zero bytes for any len
+            return Ok(unsafe {
+                core::slice::from_raw_parts_mut(NonNull::<MaybeUninit<T>>::dangling().as_ptr(), len)
+            });
+        }
+
+        let size_bytes = core::mem::size_of::<T>().saturating_mul(len);
+        let new_alloc = self.try_bump(size_bytes, core::mem::align_of::<T>())?;
+
+        // Safety:
+        // - new_alloc points to at least len * size_of::<T>() bytes of the
+        //   buffer and is aligned for T
+        // - MaybeUninit doesn't require the memory to be initialized
+        unsafe {
+            Ok(core::slice::from_raw_parts_mut(
+                new_alloc as *mut MaybeUninit<T>,
+                len,
+            ))
+        }
+    }
+
+    fn try_alloc_layout(&self, layout: Layout) -> Result<*mut u8, AllocationError> {
+        if layout.size() == 0 {
+            return Ok(core::ptr::without_provenance_mut(layout.align()));
+        }
+        self.try_bump(layout.size(), layout.align())
+    }
+
+    unsafe fn rewind(&self, alloc: *mut u8) {
+        let offset = (alloc as usize).wrapping_sub(self.block_start() as usize);
+        assert!(
+            offset <= self.next_alloc.get(),
+            "alloc doesn't belong to this allocator"
+        );
+        self.next_alloc.set(offset);
+    }
+
+    fn peek(&self) -> *mut u8 {
+        // Safety:
+        // - next_alloc is at most N, so the pointer is within the buffer or one
+        //   byte past it
+        unsafe { self.block_start().add(self.next_alloc.get()) }
+    }
+
+    fn acquire_scope(&self) {
+        assert!(
+            !self.scoped.replace(true),
+            "Allocator is already used by another root ScopedScratch"
+        );
+    }
+
+    fn release_scope(&self) {
+        self.scoped.set(false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::ScopedScratch;
+
+    #[test]
+    fn exhaust() {
+        let alloc = StackAllocator::<64>::new();
+        assert_eq!(alloc.capacity(), 64);
+
+        for i in 0..4u8 {
+            let a = alloc.alloc_internal([i; 16]);
+            assert_eq!(a[15], i);
+        }
+        assert_eq!(alloc.used_bytes(), 64);
+
+        match alloc.try_alloc_internal(0u8) {
+            Err(AllocationError::OutOfMemory {
+                size_bytes,
+                remaining_bytes,
+                ..
+            }) => {
+                assert_eq!(size_bytes, 1);
+                assert_eq!(remaining_bytes, 0);
+            }
+            _ => panic!("Expected OutOfMemory"),
+        }
+        // Zero-sized types still fit
+        let _ = alloc.alloc_internal(());
+    }
+
+    #[test]
+    fn overflow_padding() {
+        let alloc = StackAllocator::<64>::new();
+        // Leave the free block starting halfway between 16 byte boundaries
+        let used = (48..64)
+            .find(|&i| (alloc.peek() as usize + i) % 16 == 8)
+            .unwrap();
+        let _ = alloc.alloc_slice(used, |_| 0u8);
+
+        // Fits the remaining bytes, but not after aligning
+        match alloc.try_alloc_aligned([0u8; 16], 16) {
+            Err(AllocationError::OutOfMemory {
+                padding_bytes,
+                remaining_bytes,
+                ..
+            }) => {
+                assert_eq!(padding_bytes, 8);
+                assert_eq!(remaining_bytes, 64 - used);
+            }
+            _ => panic!("Expected OutOfMemory"),
+        }
+        // The allocator is left untouched
+        assert_eq!(alloc.used_bytes(), used);
+        let _ = alloc.alloc_slice(64 - used, |_| 0u8);
+    }
+
+    #[should_panic(expected = "Tried to allocate 128 bytes")]
+    #[test]
+    fn overflow_panic() {
+        let alloc = StackAllocator::<64>::new();
+        let _ = alloc.alloc_internal([0u8; 128]);
+    }
+
+    #[test]
+    fn rewind() {
+        let mut alloc = StackAllocator::<64>::new();
+        let _ = alloc.alloc_internal(0u8);
+        let target = alloc.peek();
+        let _ = alloc.alloc_internal(0u64);
+        unsafe { alloc.rewind(target) };
+        assert_eq!(alloc.peek(), target);
+        assert_eq!(alloc.used_bytes(), 1);
+
+        alloc.reset();
+        assert_eq!(alloc.used_bytes(), 0);
+    }
+
+    #[test]
+    fn scoped() {
+        struct A<'a> {
+            dropped: &'a Cell<usize>,
+        }
+        impl Drop for A<'_> {
+            fn drop(&mut self) {
+                self.dropped.set(self.dropped.get() + 1);
+            }
+        }
+
+        let dropped = Cell::new(0);
+        let alloc = StackAllocator::<256>::new();
+        {
+            let scratch = ScopedScratch::new(&alloc);
+            let a = scratch.alloc(0xCAFEBABEu32);
+            {
+                let scratch2 = scratch.new_scope();
+                let _ = scratch2.alloc_slice(2, |_| A { dropped: &dropped });
+            }
+            assert_eq!(dropped.get(), 2);
+            assert_eq!(*a, 0xCAFEBABEu32);
+        }
+        assert_eq!(alloc.used_bytes(), 0);
+    }
+}
@@ -0,0 +1,346 @@
+use crate::{
+    error::AllocationError,
+    linear_allocator::{check_size, LinearAllocatorInternal},
+};
+
+use core::{alloc::Layout, cell::Cell, mem::MaybeUninit, ptr::NonNull};
+
+/// A linear allocator that reserves its block as address space with `mmap` and
+/// only commits pages as allocations advance into them. This keeps the memory
+/// footprint proportional to the high water mark instead of the capacity, so
+/// huge arenas can be reserved up front. Pages stay committed on rewind to
+/// avoid committing them again every frame. Unix only.
+pub struct VirtualLinearAllocator {
+    block_start: *mut u8,
+    size_bytes: usize,
+    // Length of the mapping, size_bytes rounded up to the page size
+    reserved_bytes: usize,
+    page_size: usize,
+    // Offset of the start of the free block from the start of the block
+    next_alloc: Cell<usize>,
+    // Bytes at the start of the block that are readable and writable
+    committed_bytes: Cell<usize>,
+    // Set while a root ScopedScratch is alive, as it assumes to be the only user
+    scoped: Cell<bool>,
+}
+
+impl VirtualLinearAllocator {
+    /// Reserves a block of `size_bytes` without committing any of it, panicking
+    /// if the address space can't be reserved
+    pub fn new(size_bytes: usize) -> Self {
+        match Self::try_new(size_bytes) {
+            Ok(ret) => ret,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Like [new()], but returns an error instead of panicking
+    pub fn try_new(size_bytes: usize) -> Result<Self, AllocationError> {
+        // Safety:
+        // - sysconf has no preconditions
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        // Limit so that we can assume allocation arithmetic can never overflow
+        if size_bytes == 0 || size_bytes >= (isize::MAX as usize) - page_size {
+            return Err(AllocationError::InvalidLayout {
+                size_bytes,
+                alignment: page_size,
+            });
+        }
+        let reserved_bytes = size_bytes.next_multiple_of(page_size);
+
+        // Safety:
+        // - An anonymous mapping without a hint doesn't touch existing memory
+        let block_start = unsafe {
+            libc::mmap(
+                core::ptr::null_mut(),
+                reserved_bytes,
+                libc::PROT_NONE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_NORESERVE,
+                -1,
+                0,
+            )
+        };
+        if block_start == libc::MAP_FAILED {
+            return Err(AllocationError::AllocationFailed {
+                size_bytes,
+                alignment: page_size,
+            });
+        }
+
+        Ok(Self {
+            block_start: block_start as *mut u8,
+            size_bytes,
+            reserved_bytes,
+            page_size,
+            next_alloc: Cell::new(0),
+            committed_bytes: Cell::new(0),
+            scoped: Cell::new(false),
+        })
+    }
+
+    /// Returns the size of the reserved block in bytes
+    pub fn capacity(&self) -> usize {
+        self.size_bytes
+    }
+
+    /// Returns the number of bytes allocated, including alignment padding
+    pub fn used_bytes(&self) -> usize {
+        self.next_alloc.get()
+    }
+
+    /// Returns the number of bytes that have been committed, which is the
+    /// high water mark rounded up to the page size
+    pub fn committed_bytes(&self) -> usize {
+        self.committed_bytes.get()
+    }
+
+    /// Rewinds the allocator back to the start of the block. Committed pages
+    /// are kept.
+    ///
+    /// **This does NOT run destructors.**
+    pub fn reset(&mut self) {
+        self.next_alloc.set(0);
+    }
+
+    // Bumps the free block by size_bytes aligned at alignment, committing the
+    // pages it reaches into, and returns the start of the allocation
+    fn try_bump(&self, size_bytes: usize, alignment: usize) -> Result<*mut u8, AllocationError> {
+        check_size(size_bytes, alignment)?;
+
+        let previous_size = self.next_alloc.get();
+        let align_offset = self
+            .block_start
+            .wrapping_add(previous_size)
+            .align_offset(alignment);
+        assert_ne!(align_offset, usize::MAX);
+
+        let remaining_bytes = self.size_bytes - previous_size;
+        if align_offset
+            .checked_add(size_bytes)
+            .is_none_or(|size| size > remaining_bytes)
+        {
+            return Err(AllocationError::OutOfMemory {
+                size_bytes,
+                alignment,
+                padding_bytes: align_offset,
+                remaining_bytes,
+            });
+        }
+
+        let end = previous_size + align_offset + size_bytes;
+        self.commit(end)?;
+        self.next_alloc.set(end);
+        // Safety:
+        // - We just verified that the aligned allocation fits the block
+        Ok(unsafe { self.block_start.add(previous_size + align_offset) })
+    }
+
+    // Makes the pages up to end readable and writable
+    fn commit(&self, end: usize) -> Result<(), AllocationError> {
+        let committed_bytes = self.committed_bytes.get();
+        if end <= committed_bytes {
+            return Ok(());
+        }
+
+        let new_committed_bytes = end
+            .next_multiple_of(self.page_size)
+            .min(self.reserved_bytes);
+        let len = new_committed_bytes - committed_bytes;
+        // Safety:
+        // - The range is page aligned and within the mapping
+        let ret = unsafe {
+            libc::mprotect(
+                self.block_start.add(committed_bytes) as *mut libc::c_void,
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+            )
+        };
+        if ret != 0 {
+            return Err(AllocationError::AllocationFailed {
+                size_bytes: len,
+                alignment: self.page_size,
+            });
+        }
+        self.committed_bytes.set(new_committed_bytes);
+
+        Ok(())
+    }
+}
+
+impl LinearAllocatorInternal for VirtualLinearAllocator {
+    #[allow(clippy::mut_from_ref)]
+    fn try_alloc_aligned<T: Sized>(&self, obj: T, align: usize) -> Result<&mut T, AllocationError> {
+        assert!(align.is_power_of_two(), "align has to be a power of two");
+        let alignment = core::mem::align_of::<T>().max(align);
+
+        // Zero-sized types don't need memory, see LinearAllocator
+        if core::mem::size_of::<T>() == 0 {
+            let t_ptr = if alignment == core::mem::align_of::<T>() {
+                NonNull::<T>::dangling().as_ptr()
+            } else {
+                core::ptr::without_provenance_mut::<T>(alignment)
+            };
+            // Safety:
+            // - A dangling pointer is well-aligned and non-null, which is
+            //   enough for reads and writes of zero-sized types
+            unsafe {
+                t_ptr.write(obj);
+                return Ok(&mut *t_ptr);
+            }
+        }
+
+        let new_alloc = self.try_bump(core::mem::size_of::<T>(), alignment)?;
+
+        // Safety:
+        // - new_alloc points to at least size_of::<T>() committed bytes that
+        //   aren't referenced by earlier allocations, and is aligned for T
+        unsafe {
+            let t_ptr = new_alloc as *mut T;
+            t_ptr.write(obj);
+            Ok(&mut *t_ptr)
+        }
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    fn try_alloc_slice_uninit<T: Sized>(
+        &self,
+        len: usize,
+    ) -> Result<&mut [MaybeUninit<T>], AllocationError> {
+        if len == 0 {
+            return Ok(&mut []);
+        }
+        if core::mem::size_of::<T>() == 0 {
+            // Safety:
+            // - A dangling pointer is well-aligned and non-null, and the slice
+            //   spans zero bytes for any len
+            return Ok(unsafe {
+                core::slice::from_raw_parts_mut(NonNull::<MaybeUninit<T>>::dangling().as_ptr(), len)
+            });
+        }
+
+        let size_bytes = core::mem::size_of::<T>().saturating_mul(len);
+        let new_alloc = self.try_bump(size_bytes, core::mem::align_of::<T>())?;
+
+        // Safety:
+        // - new_alloc points to at least len * size_of::<T>() committed bytes
+        //   and is aligned for T
+        // - MaybeUninit doesn't require the memory to be initialized
+        unsafe {
+            Ok(core::slice::from_raw_parts_mut(
+                new_alloc as *mut MaybeUninit<T>,
+                len,
+            ))
+        }
+    }
+
+    fn try_alloc_layout(&self, layout: Layout) -> Result<*mut u8, AllocationError> {
+        if layout.size() == 0 {
+            return Ok(core::ptr::without_provenance_mut(layout.align()));
+        }
+        self.try_bump(layout.size(), layout.align())
+    }
+
+    unsafe fn rewind(&self, alloc: *mut u8) {
+        let offset = (alloc as usize).wrapping_sub(self.block_start as usize);
+        assert!(
+            offset <= self.next_alloc.get(),
+            "alloc doesn't belong to this allocator"
+        );
+        self.next_alloc.set(offset);
+    }
+
+    fn peek(&self) -> *mut u8 {
+        self.block_start.wrapping_add(self.next_alloc.get())
+    }
+
+    fn acquire_scope(&self) {
+        assert!(
+            !self.scoped.replace(true),
+            "Allocator is already used by another root ScopedScratch"
+        );
+    }
+
+    fn release_scope(&self) {
+        self.scoped.set(false);
+    }
+}
+
+impl Drop for VirtualLinearAllocator {
+    fn drop(&mut self) {
+        // Safety:
+        //  - self.block_start was mapped with self.reserved_bytes in try_new()
+        unsafe {
+            libc::munmap(self.block_start as *mut libc::c_void, self.reserved_bytes);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::ScopedScratch;
+
+    #[test]
+    fn lazy_commit() {
+        let alloc = VirtualLinearAllocator::new(1 << 32);
+        let page_size = alloc.page_size;
+        assert_eq!(alloc.capacity(), 1 << 32);
+        assert_eq!(alloc.committed_bytes(), 0);
+
+        let a = alloc.alloc_internal(0xCAFEBABEu32);
+        assert_eq!(*a, 0xCAFEBABEu32);
+        assert_eq!(alloc.committed_bytes(), page_size);
+
+        // Crosses into the next two pages
+        let b = alloc.alloc_slice(page_size * 2, |i| i as u8);
+        assert_eq!(b[page_size * 2 - 1], (page_size * 2 - 1) as u8);
+        assert_eq!(alloc.used_bytes(), 4 + page_size * 2);
+        assert_eq!(alloc.committed_bytes(), page_size * 3);
+    }
+
+    #[test]
+    fn rewind_keeps_pages() {
+        let mut alloc = VirtualLinearAllocator::new(1 << 20);
+        let page_size = alloc.page_size;
+
+        let _ = alloc.alloc_slice(page_size + 1, |_| 0u8);
+        alloc.reset();
+        assert_eq!(alloc.used_bytes(), 0);
+        assert_eq!(alloc.committed_bytes(), page_size * 2);
+
+        let _ = alloc.alloc_internal(0u8);
+        assert_eq!(alloc.committed_bytes(), page_size * 2);
+    }
+
+    #[test]
+    fn exhaust() {
+        // Not a multiple of the page size
+        let alloc = VirtualLinearAllocator::new(100);
+
+        let _ = alloc.alloc_slice(100, |i| i as u8);
+        match alloc.try_alloc_internal(0u8) {
+            Err(AllocationError::OutOfMemory {
+                remaining_bytes, ..
+            }) => assert_eq!(remaining_bytes, 0),
+            _ => panic!("Expected OutOfMemory"),
+        }
+        assert_eq!(alloc.committed_bytes(), alloc.page_size);
+    }
+
+    #[test]
+    fn scoped() {
+        let alloc = VirtualLinearAllocator::new(1 << 20);
+        {
+            let scratch = ScopedScratch::new(&alloc);
+            let a = scratch.alloc(0xCAFEBABEu32);
+            {
+                let scratch2 = scratch.new_scope();
+                let _ = scratch2.alloc_slice(alloc.page_size, |i| i as u32);
+            }
+            assert_eq!(*a, 0xCAFEBABEu32);
+        }
+        assert_eq!(alloc.used_bytes(), 0);
+        assert!(alloc.committed_bytes() > alloc.page_size);
+    }
+}
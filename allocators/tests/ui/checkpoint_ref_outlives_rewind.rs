@@ -0,0 +1,10 @@
+use allocators::{LinearAllocator, ScopedScratch};
+
+fn main() {
+    let allocator = LinearAllocator::new(1024);
+    let mut scratch = ScopedScratch::new(&allocator);
+    let mut checkpoint = scratch.checkpoint();
+    let prim = checkpoint.alloc(0u32);
+    checkpoint.rewind();
+    *prim = 1;
+}
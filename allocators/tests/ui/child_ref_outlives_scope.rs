@@ -0,0 +1,12 @@
+use allocators::{LinearAllocator, ScopedScratch};
+
+fn main() {
+    let allocator = LinearAllocator::new(1024);
+    let scratch = ScopedScratch::new(&allocator);
+    let prim: &mut u32;
+    {
+        let inner_scratch = scratch.new_scope();
+        prim = inner_scratch.alloc(0u32);
+    }
+    *prim = 1;
+}
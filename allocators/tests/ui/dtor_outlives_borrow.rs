@@ -0,0 +1,18 @@
+use allocators::{LinearAllocator, ScopedScratch};
+
+struct PrintOnDrop<'a>(&'a String);
+
+impl Drop for PrintOnDrop<'_> {
+    fn drop(&mut self) {
+        println!("{}", self.0);
+    }
+}
+
+fn main() {
+    let allocator = LinearAllocator::new(1024);
+    let scratch = ScopedScratch::new(&allocator);
+    {
+        let s = String::from("dropped before the scope");
+        let _ = scratch.alloc(PrintOnDrop(&s));
+    }
+}
@@ -0,0 +1,11 @@
+use allocators::LinearAllocator;
+use std::rc::Rc;
+
+fn main() {
+    let alloc = LinearAllocator::new(1024);
+    let count = Rc::new(0);
+    // The handler would move between threads with a FrozenArena
+    alloc.set_oom_handler(move |_, _, _| {
+        let _ = Rc::strong_count(&count);
+    });
+}
@@ -0,0 +1,7 @@
+use allocators::{LinearAllocator, ScopedScratch};
+
+fn main() {
+    let allocator = LinearAllocator::new(1024);
+    let scratch = ScopedScratch::new_pod_only(&allocator);
+    let _ = scratch.alloc(String::from("needs drop"));
+}
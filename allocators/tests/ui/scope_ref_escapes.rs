@@ -0,0 +1,8 @@
+use allocators::{LinearAllocator, ScopedScratch};
+
+fn main() {
+    let allocator = LinearAllocator::new(1024);
+    let scratch = ScopedScratch::new(&allocator);
+    let a = scratch.scope(|scratch2| scratch2.alloc(0u32));
+    *a = 1;
+}
@@ -0,0 +1,10 @@
+#![deny(unused_must_use)]
+
+use allocators::{LinearAllocator, ScopedScratch};
+
+fn main() {
+    let allocator = LinearAllocator::new(1024);
+    let scratch = ScopedScratch::new(&allocator);
+    scratch.new_scope();
+    scratch.alloc(0u32);
+}
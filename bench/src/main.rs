@@ -1,6 +1,6 @@
-use allocators::{LinearAllocator, ScopedScratch};
+use allocators::{FastLinearAllocator, LinearAllocator, ScopedScratch};
 
-use std::time::Instant;
+use std::{hint::black_box, time::Instant};
 
 trait BenchNew {
     fn new(v: u32) -> Self;
@@ -61,9 +61,7 @@ macro_rules! declare_structs {
         }
 
         impl Drop for $obj_name {
-            fn drop(&mut self) {
-                ()
-            }
+            fn drop(&mut self) {}
         }
     };
 }
@@ -90,6 +88,7 @@ impl Default for Timing {
     }
 }
 
+#[derive(Default)]
 struct TestTimes {
     naive_pod: Timing,
     naive_obj: Timing,
@@ -97,16 +96,6 @@ struct TestTimes {
     scoped_obj: Timing,
 }
 
-impl Default for TestTimes {
-    fn default() -> Self {
-        Self {
-            naive_pod: Timing::default(),
-            naive_obj: Timing::default(),
-            scoped_pod: Timing::default(),
-            scoped_obj: Timing::default(),
-        }
-    }
-}
 const ITEM_COUNT: usize = 2_000_000;
 const ITERATIONS: usize = 10;
 const TOTAL_ALLOCATIONS: usize = ITEM_COUNT * ITERATIONS;
@@ -116,8 +105,7 @@ fn bench_alloc<'a, T: BenchData>(
     alloc: &dyn Fn(&'a ScopedScratch, u32) -> T,
 ) -> (Vec<T>, f32) {
     let start = Instant::now();
-    let mut datas: Vec<T> = vec![];
-    datas.reserve(ITEM_COUNT);
+    let mut datas: Vec<T> = Vec::with_capacity(ITEM_COUNT);
     for i in 0..ITEM_COUNT as u32 {
         datas.push(alloc(scratch, i));
     }
@@ -139,11 +127,11 @@ fn bench_iter<T: BenchData>(datas: &Vec<T>) -> (u32, f32) {
     (acc, spent_ns)
 }
 
-fn alloc<'a, T: BenchNew + BenchData>(scratch: &'a ScopedScratch, v: u32) -> &'a mut T {
+fn alloc<'a, T: BenchNew + BenchData + 'static>(scratch: &'a ScopedScratch, v: u32) -> &'a mut T {
     scratch.alloc(T::new(v))
 }
 
-fn bench<T: Copy + BenchNew + BenchData, V: BenchNew + BenchData>() -> String {
+fn bench<T: Copy + BenchNew + BenchData + 'static, V: BenchNew + BenchData + 'static>() -> String {
     assert_eq!(std::mem::size_of::<T>(), std::mem::size_of::<V>());
 
     println!(
@@ -155,7 +143,9 @@ fn bench<T: Copy + BenchNew + BenchData, V: BenchNew + BenchData>() -> String {
     let mut times = TestTimes::default();
 
     // Allocate space for both the objects and potential ScopeData
-    let mut allocator = LinearAllocator::new(ITEM_COUNT * (std::mem::size_of::<T>() + 32));
+    let allocator = LinearAllocator::new(ITEM_COUNT * (std::mem::size_of::<T>() + 40));
+    // Fault the pages in so that the first scoped variant doesn't pay for them
+    allocator.prefault();
 
     macro_rules! bench {
         ($name:expr, $time:expr, $alloc_fn:expr) => {
@@ -163,7 +153,7 @@ fn bench<T: Copy + BenchNew + BenchData, V: BenchNew + BenchData>() -> String {
             for i in 0..ITERATIONS {
                 println!("{} iter {}", $name, i);
                 let dtor_start = {
-                    let scope = ScopedScratch::new(&mut allocator);
+                    let scope = ScopedScratch::new(&allocator);
                     let (datas, alloc_ns) = bench_alloc(&scope, $alloc_fn);
                     $time.alloc_ns += alloc_ns;
                     let (acc, iter_ns) = bench_iter(&datas);
@@ -211,13 +201,13 @@ fn bench<T: Copy + BenchNew + BenchData, V: BenchNew + BenchData>() -> String {
     //       a single large allocation or do we just get lucky with the tight loop getting
     //       contiguous addresses?
     let mut ret = String::new();
-    ret += &format!("Results (average per item)\n");
+    ret += "Results (average per item)\n";
     ret += &format!("Struct size: {}\n", std::mem::size_of::<T>());
-    ret += &format!("  Naive POD boxing\n");
+    ret += "  Naive POD boxing\n";
     ret += &format!("    Alloc {:.2}ns\n", times.naive_pod.alloc_ns);
     ret += &format!("    Iter {:.2}ns\n", times.naive_pod.iter_ns);
     ret += &format!("    Dtor {:.2}ns\n", times.naive_pod.dtor_ns);
-    ret += &format!("  Naive obj boxing\n");
+    ret += "  Naive obj boxing\n";
     ret += &format!(
         "    Alloc {:.2}ns ({}% of naive POD)\n",
         times.naive_obj.alloc_ns,
@@ -233,7 +223,7 @@ fn bench<T: Copy + BenchNew + BenchData, V: BenchNew + BenchData>() -> String {
         times.naive_obj.dtor_ns,
         dtor_diff!(naive_obj, naive_pod)
     );
-    ret += &format!("  Scoped POD\n");
+    ret += "  Scoped POD\n";
     ret += &format!(
         "    Alloc {:.2}ns ({}% of naive POD)\n",
         times.scoped_pod.alloc_ns,
@@ -249,7 +239,7 @@ fn bench<T: Copy + BenchNew + BenchData, V: BenchNew + BenchData>() -> String {
         times.scoped_pod.dtor_ns,
         dtor_diff!(scoped_pod, naive_pod)
     );
-    ret += &format!("  Scoped obj\n");
+    ret += "  Scoped obj\n";
     ret += &format!(
         "    Alloc {:.2}ns ({}% of naive POD, {}% of scoped POD, {}% of naive obj)\n",
         times.scoped_obj.alloc_ns,
@@ -274,12 +264,171 @@ fn bench<T: Copy + BenchNew + BenchData, V: BenchNew + BenchData>() -> String {
     ret
 }
 
+// Compares allocating through the shared reference of a scope to allocating
+// through an exclusive reference to the allocator, using each object right away
+fn bench_bump<T: Copy + BenchNew + BenchData + 'static>() -> String {
+    println!("{} bump", std::any::type_name::<T>());
+
+    let mut allocator = LinearAllocator::new(ITEM_COUNT * std::mem::size_of::<T>());
+    let mut shared_ns = 0.0;
+    let mut exclusive_ns = 0.0;
+    let mut tot_acc = 0u32;
+    // Fault the pages in so that they don't skew the first variant
+    allocator.prefault();
+    for i in 0..ITERATIONS {
+        println!("Bump iter {}", i);
+        {
+            let scope = ScopedScratch::new(&allocator);
+            let start = Instant::now();
+            for v in 0..ITEM_COUNT as u32 {
+                tot_acc = tot_acc.wrapping_add(scope.alloc(T::new(v)).data(0));
+            }
+            shared_ns += (Instant::now() - start).as_nanos() as f32;
+        }
+        {
+            let start = Instant::now();
+            for v in 0..ITEM_COUNT as u32 {
+                tot_acc = tot_acc.wrapping_add(allocator.alloc_mut(T::new(v)).data(0));
+            }
+            exclusive_ns += (Instant::now() - start).as_nanos() as f32;
+            allocator.reset();
+        }
+    }
+    println!("{}", tot_acc);
+    shared_ns /= TOTAL_ALLOCATIONS as f32;
+    exclusive_ns /= TOTAL_ALLOCATIONS as f32;
+
+    let mut ret = String::new();
+    ret += "Bump results (average per item)\n";
+    ret += &format!("Struct size: {}\n", std::mem::size_of::<T>());
+    ret += &format!("  Scoped alloc {:.2}ns\n", shared_ns);
+    ret += &format!(
+        "  Exclusive alloc_mut {:.2}ns ({}% of scoped)\n",
+        exclusive_ns,
+        (exclusive_ns / shared_ns * 100.0) as u32
+    );
+    ret
+}
+
+// Compares the bump of a LinearAllocator to that of a FastLinearAllocator that
+// doesn't compute alignment padding, using each object right away
+fn bench_fast<T: Copy + BenchNew + BenchData + 'static>() -> String {
+    println!("{} fast", std::any::type_name::<T>());
+
+    // The bench structs are aligned as u32
+    const ALIGN: usize = 4;
+    assert_eq!(std::mem::align_of::<T>(), ALIGN);
+
+    let mut allocator = LinearAllocator::new(ITEM_COUNT * std::mem::size_of::<T>());
+    let mut fast_allocator =
+        FastLinearAllocator::<ALIGN>::new(ITEM_COUNT * std::mem::size_of::<T>());
+    let mut linear_ns = 0.0;
+    let mut fast_ns = 0.0;
+    let mut tot_acc = 0u32;
+    // Touch the memory once so that page faults don't skew the results
+    for v in 0..ITEM_COUNT as u32 {
+        tot_acc = tot_acc.wrapping_add(allocator.alloc_mut(T::new(v)).data(0));
+        tot_acc = tot_acc.wrapping_add(fast_allocator.alloc(T::new(v)).data(0));
+    }
+    allocator.reset();
+    fast_allocator.reset();
+    for i in 0..ITERATIONS {
+        println!("Fast iter {}", i);
+        // Without black_box, the fast loop is optimized out entirely
+        {
+            let start = Instant::now();
+            for v in 0..ITEM_COUNT as u32 {
+                tot_acc = tot_acc.wrapping_add(black_box(allocator.alloc_mut(T::new(v))).data(0));
+            }
+            linear_ns += (Instant::now() - start).as_nanos() as f32;
+            allocator.reset();
+        }
+        {
+            let start = Instant::now();
+            for v in 0..ITEM_COUNT as u32 {
+                tot_acc = tot_acc.wrapping_add(black_box(fast_allocator.alloc(T::new(v))).data(0));
+            }
+            fast_ns += (Instant::now() - start).as_nanos() as f32;
+            fast_allocator.reset();
+        }
+    }
+    println!("{}", tot_acc);
+    linear_ns /= TOTAL_ALLOCATIONS as f32;
+    fast_ns /= TOTAL_ALLOCATIONS as f32;
+
+    let mut ret = String::new();
+    ret += "Fast results (average per item)\n";
+    ret += &format!("Struct size: {}\n", std::mem::size_of::<T>());
+    ret += &format!("  LinearAllocator alloc_mut {:.2}ns\n", linear_ns);
+    ret += &format!(
+        "  FastLinearAllocator alloc {:.2}ns ({}% of LinearAllocator)\n",
+        fast_ns,
+        (fast_ns / linear_ns * 100.0) as u32
+    );
+    ret
+}
+
+const COPY_BYTES: usize = 1024 * 1024;
+const COPY_COUNT: usize = 100;
+
+fn bench_slice_copy() -> String {
+    println!("Slice copy");
+
+    let src: Vec<u8> = (0..COPY_BYTES).map(|i| i as u8).collect();
+    let allocator = LinearAllocator::new(COPY_BYTES);
+    let mut copy_ns = 0.0;
+    let mut loop_ns = 0.0;
+    let mut tot_acc = 0u8;
+    // Touch the memory once so that page faults don't skew the first variant
+    {
+        let scope = ScopedScratch::new(&allocator);
+        tot_acc = tot_acc.wrapping_add(scope.alloc_slice_copy(&src)[COPY_BYTES - 1]);
+    }
+    for i in 0..ITERATIONS {
+        println!("Copy iter {}", i);
+        for _ in 0..COPY_COUNT {
+            let scope = ScopedScratch::new(&allocator);
+            let start = Instant::now();
+            let dst = scope.alloc_slice_copy(&src);
+            copy_ns += (Instant::now() - start).as_nanos() as f32;
+            tot_acc = tot_acc.wrapping_add(dst[COPY_BYTES - 1]);
+        }
+        for _ in 0..COPY_COUNT {
+            let scope = ScopedScratch::new(&allocator);
+            let start = Instant::now();
+            let dst = scope.alloc_slice(src.len(), |i| src[i]);
+            loop_ns += (Instant::now() - start).as_nanos() as f32;
+            tot_acc = tot_acc.wrapping_add(dst[COPY_BYTES - 1]);
+        }
+    }
+    println!("{}", tot_acc);
+    copy_ns /= (ITERATIONS * COPY_COUNT) as f32;
+    loop_ns /= (ITERATIONS * COPY_COUNT) as f32;
+
+    let mut ret = String::new();
+    ret += "Slice copy results (average per slice)\n";
+    ret += &format!("Slice size: {}\n", COPY_BYTES);
+    ret += &format!("  alloc_slice_copy {:.2}us\n", copy_ns / 1000.0);
+    ret += &format!(
+        "  alloc_slice loop {:.2}us ({}% of alloc_slice_copy)\n",
+        loop_ns / 1000.0,
+        (loop_ns / copy_ns * 100.0) as u32
+    );
+    ret
+}
+
 fn main() {
-    let mut results = vec![];
-    results.push(bench::<Pod64, Obj64>());
-    results.push(bench::<Pod128, Obj128>());
-    results.push(bench::<Pod256, Obj256>());
-    results.push(bench::<Pod512, Obj512>());
-    results.push(bench::<Pod1k, Obj1k>());
+    let results = [
+        bench::<Pod64, Obj64>(),
+        bench::<Pod128, Obj128>(),
+        bench::<Pod256, Obj256>(),
+        bench::<Pod512, Obj512>(),
+        bench::<Pod1k, Obj1k>(),
+        bench_bump::<Pod64>(),
+        bench_bump::<Pod1k>(),
+        bench_fast::<Pod64>(),
+        bench_fast::<Pod1k>(),
+        bench_slice_copy(),
+    ];
     println!("{}", results.join("\n"));
 }